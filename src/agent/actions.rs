@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+
+use ndarray::{Array1, ArrayView1};
+
+use crate::{
+    game::{
+        RESOURCES,
+        board::ResourceField,
+        buildings::BUILDINGS,
+        mechs::MECHS,
+        production::WORKERS,
+        recruits::RECRUITS,
+        upgrades::{PRIMARY_UPGRADES, SECONDARY_UPGRADES},
+    },
+    template::Position,
+    turn::mask::{
+        Movement, Primary, Secondary, Trade, TurnMask, UNITS, UnitMovement, UnitPosition,
+    },
+};
+
+use super::encoder::Encoder;
+
+/// The parts of a turn, each with its own block of the output vector
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Head {
+    /// Tax, promote, bolster, enforce, trade, produce or move
+    Primary,
+    /// The first unit that moves, trades or produces
+    Unit,
+    /// Where the first moving unit ends up
+    Destination,
+    /// The resource the first moving unit carries most of, or the resource a trade buys
+    Resource,
+    /// The secondary action, regardless of how it is paid
+    Secondary,
+}
+
+pub const HEADS: [Head; 5] = [
+    Head::Primary,
+    Head::Unit,
+    Head::Destination,
+    Head::Resource,
+    Head::Secondary,
+];
+
+const PRIMARY_KINDS: usize = 7;
+
+const SECONDARY_CHOICES: usize = PRIMARY_UPGRADES.len() * SECONDARY_UPGRADES.len()
+    + MECHS.len() * WORKERS.len()
+    + BUILDINGS.len() * WORKERS.len()
+    + RECRUITS.len() * RECRUITS.len();
+
+/// The index of a turn in every head. All heads but the primary one use 0 for "none".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ActionFactors {
+    pub primary: usize,
+    pub unit: usize,
+    pub destination: usize,
+    pub resource: usize,
+    pub secondary: usize,
+}
+
+impl ActionFactors {
+    pub fn get(&self, head: Head) -> usize {
+        match head {
+            Head::Primary => self.primary,
+            Head::Unit => self.unit,
+            Head::Destination => self.destination,
+            Head::Resource => self.resource,
+            Head::Secondary => self.secondary,
+        }
+    }
+}
+
+/// A factorised encoding of turns into a fixed size output vector.
+///
+/// Moves of several units and the payment of secondary actions are not part of the factors, so
+/// different legal turns can share their factors. Outputs are therefore always decoded against
+/// the list of legal turns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActionSpace {
+    destinations: HashMap<Position, usize>,
+}
+
+fn index_of<T: PartialEq>(items: &[T], item: &T) -> usize {
+    items
+        .iter()
+        .position(|i| i == item)
+        .expect("all values are listed")
+}
+
+/// The index of the secondary action without its payment, starting at 1
+fn secondary_index(secondary: &Secondary) -> usize {
+    let upgrades = PRIMARY_UPGRADES.len() * SECONDARY_UPGRADES.len();
+    let deploys = MECHS.len() * WORKERS.len();
+    let builds = BUILDINGS.len() * WORKERS.len();
+    1 + match secondary {
+        Secondary::Upgrade(p, s, _) => {
+            index_of(&PRIMARY_UPGRADES, p) * SECONDARY_UPGRADES.len()
+                + index_of(&SECONDARY_UPGRADES, s)
+        }
+        Secondary::Deploy(m, w, _) => {
+            upgrades + index_of(&MECHS, m) * WORKERS.len() + index_of(&WORKERS, w)
+        }
+        Secondary::Build(b, w, _) => {
+            upgrades + deploys + index_of(&BUILDINGS, b) * WORKERS.len() + index_of(&WORKERS, w)
+        }
+        Secondary::Enlist(s, o, _) => {
+            upgrades
+                + deploys
+                + builds
+                + index_of(&RECRUITS, s) * RECRUITS.len()
+                + index_of(&RECRUITS, o)
+        }
+    }
+}
+
+/// The resource there is most of, if there is any
+fn main_resource(resources: &ResourceField) -> usize {
+    RESOURCES
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| resources.get(r) > 0)
+        .max_by_key(|(i, r)| (resources.get(r), std::cmp::Reverse(*i)))
+        .map_or(0, |(i, _)| i + 1)
+}
+
+/// The unit, its last position and its cargo
+fn movement_parts(movement: &UnitMovement) -> (UnitPosition, Position, ResourceField) {
+    fn last<P: Copy>(movement: &Movement<P>) -> P {
+        match movement {
+            Movement::Single(p) | Movement::Double(_, p) => *p,
+        }
+    }
+    match movement {
+        UnitMovement::Character(m) => {
+            let (position, resources) = last(m);
+            (UnitPosition::Character, position, resources)
+        }
+        UnitMovement::Worker(worker, m) => {
+            let (position, resources) = last(m);
+            (UnitPosition::Worker(*worker), position, resources)
+        }
+        UnitMovement::Mech(mech, m) => {
+            let (position, _, resources) = last(m);
+            (UnitPosition::Mech(*mech), position, resources)
+        }
+    }
+}
+
+impl ActionSpace {
+    /// Destinations are the fields of the encoder, in the same order
+    pub fn new(encoder: &Encoder) -> Self {
+        let destinations = encoder
+            .positions()
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (*p, i + 1))
+            .collect();
+        ActionSpace { destinations }
+    }
+
+    pub fn head_size(&self, head: Head) -> usize {
+        match head {
+            Head::Primary => PRIMARY_KINDS,
+            Head::Unit => 1 + UNITS.len(),
+            Head::Destination => 1 + self.destinations.len(),
+            Head::Resource => 1 + RESOURCES.len(),
+            Head::Secondary => 1 + SECONDARY_CHOICES,
+        }
+    }
+
+    /// Where the block of the head starts in the output vector
+    pub fn offset(&self, head: Head) -> usize {
+        HEADS
+            .iter()
+            .take_while(|h| **h != head)
+            .map(|h| self.head_size(*h))
+            .sum()
+    }
+
+    /// The length of the output vector
+    pub fn size(&self) -> usize {
+        HEADS.iter().map(|h| self.head_size(*h)).sum()
+    }
+
+    pub fn factors(&self, mask: &TurnMask) -> ActionFactors {
+        let (primary, secondary) = match mask {
+            TurnMask::PrimaryOnly(primary) => (primary, 0),
+            TurnMask::PrimaryAndSecondary(primary, secondary) => {
+                (primary, secondary_index(secondary))
+            }
+        };
+        let unit = |unit: &UnitPosition| 1 + index_of(&UNITS, unit);
+        let (primary, unit, destination, resource) = match primary {
+            Primary::Tax => (0, 0, 0, 0),
+            Primary::Promote => (1, 0, 0, 0),
+            Primary::Bolster => (2, 0, 0, 0),
+            Primary::Enforce => (3, 0, 0, 0),
+            Primary::Trade(Trade::Trade1(trade) | Trade::Trade2(trade, _)) => {
+                let (u, _, to) = trade;
+                (4, unit(u), 0, 1 + index_of(&RESOURCES, to))
+            }
+            Primary::Produce(produce) => {
+                let worker = produce.workers()[0];
+                (5, unit(&UnitPosition::Worker(worker)), 0, 0)
+            }
+            Primary::Move(movement) => {
+                let (u, position, resources) = movement_parts(movement.movements()[0]);
+                let destination = self.destinations.get(&position).copied().unwrap_or(0);
+                (6, unit(&u), destination, main_resource(&resources))
+            }
+        };
+        ActionFactors {
+            primary,
+            unit,
+            destination,
+            resource,
+            secondary,
+        }
+    }
+
+    /// The positions of the factors of the turn in the output vector
+    pub fn indices(&self, mask: &TurnMask) -> [usize; HEADS.len()] {
+        let factors = self.factors(mask);
+        HEADS.map(|head| self.offset(head) + factors.get(head))
+    }
+
+    /// 1 for every output that is a factor of a legal turn, 0 otherwise
+    pub fn legal_mask(&self, legal: &[TurnMask]) -> Array1<f64> {
+        let mut mask = Array1::zeros(self.size());
+        for turn in legal {
+            for index in self.indices(turn) {
+                mask[index] = 1.0;
+            }
+        }
+        mask
+    }
+
+    /// The softmax of every head over the outputs that the mask allows, 0 for all others
+    pub fn head_probabilities(&self, output: ArrayView1<f64>, mask: &Array1<f64>) -> Array1<f64> {
+        let mut probabilities = Array1::zeros(self.size());
+        for head in HEADS {
+            let range = self.offset(head)..self.offset(head) + self.head_size(head);
+            let max = range
+                .clone()
+                .filter(|i| mask[*i] > 0.0)
+                .map(|i| output[i])
+                .fold(f64::NEG_INFINITY, f64::max);
+            let mut sum = 0.0;
+            for i in range.clone().filter(|i| mask[*i] > 0.0) {
+                probabilities[i] = (output[i] - max).exp();
+                sum += probabilities[i];
+            }
+            for i in range {
+                probabilities[i] /= sum;
+            }
+        }
+        probabilities
+    }
+
+    /// The probability of every legal turn.
+    ///
+    /// Each head is a softmax over the outputs of legal factors, a turn has the product of the
+    /// probabilities of its factors, and the result is normalised over the legal turns.
+    pub fn policy(&self, output: ArrayView1<f64>, legal: &[TurnMask]) -> Vec<f64> {
+        let probabilities = self.head_probabilities(output, &self.legal_mask(legal));
+        let mut policy: Vec<f64> = legal
+            .iter()
+            .map(|turn| {
+                self.indices(turn)
+                    .iter()
+                    .map(|i| probabilities[*i])
+                    .product()
+            })
+            .collect();
+        let total: f64 = policy.iter().sum();
+        if total > 0.0 {
+            policy.iter_mut().for_each(|p| *p /= total);
+        }
+        policy
+    }
+
+    /// The most likely legal turn
+    pub fn decode(&self, output: ArrayView1<f64>, legal: &[TurnMask]) -> Option<TurnMask> {
+        let policy = self.policy(output, legal);
+        legal
+            .iter()
+            .zip(policy)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(turn, _)| *turn)
+    }
+
+    /// The probabilities of the factors of every head under a distribution over legal turns, to
+    /// train the heads towards it
+    pub fn target(&self, legal: &[TurnMask], probabilities: &[f64]) -> Array1<f64> {
+        let mut target = Array1::zeros(self.size());
+        for (turn, probability) in legal.iter().zip(probabilities) {
+            for index in self.indices(turn) {
+                target[index] += probability;
+            }
+        }
+        target
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::game, turn::predict::get_candidates};
+
+    #[test]
+    fn decodes_legal_turns() {
+        let game = game(2);
+        let space = ActionSpace::new(&Encoder::new(&game.board, 2));
+        let legal = get_candidates(&game);
+        let mask = space.legal_mask(&legal);
+
+        for turn in &legal {
+            let factors = space.factors(turn);
+            for head in HEADS {
+                assert!(factors.get(head) < space.head_size(head), "{turn}");
+            }
+
+            // An output that points at the factors of the turn decodes to a turn with them
+            let mut output = Array1::from_elem(space.size(), -10.0);
+            for index in space.indices(turn) {
+                assert_eq!(mask[index], 1.0);
+                output[index] = 10.0;
+            }
+            let decoded = space.decode(output.view(), &legal).unwrap();
+            assert_eq!(space.factors(&decoded), factors);
+        }
+
+        let policy = space.policy(Array1::zeros(space.size()).view(), &legal);
+        assert!((policy.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        let target = space.target(&legal, &policy);
+        assert!((target.sum() - HEADS.len() as f64).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,696 @@
+use std::{collections::VecDeque, io, path::PathBuf};
+
+use ndarray::{Array1, Array2, Axis};
+use rand::{Rng, SeedableRng, distr::weighted::WeightedIndex, prelude::Distribution};
+use rayon::prelude::*;
+
+use crate::{
+    game::{
+        GameRng,
+        game::Game,
+        record::{GameRecord, ReplayError, SeatRecord, TurnRecord},
+    },
+    network::{
+        loss::Loss,
+        optimiser::{Method, Optimiser},
+        policy_value::{PolicyValueNetwork, PolicyValueOptimiser},
+    },
+    turn::{
+        mask::TurnMask,
+        predict::{get_candidates, sample_action},
+    },
+};
+
+use super::{
+    Agent, Observation,
+    actions::ActionSpace,
+    encoder::Encoder,
+    mcts::{is_over, outcome},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchConfig {
+    pub simulations: u32,
+    /// The weight of the prior against the value of a turn
+    pub exploration: f64,
+    /// Positions at this turn are scored as if the game had ended
+    pub max_turns: u32,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            simulations: 100,
+            exploration: 1.5,
+            max_turns: 300,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    mask: Option<TurnMask>,
+    /// Only set once the node is reached, as most children are never visited
+    game: Option<Game>,
+    prior: f64,
+    children: Vec<usize>,
+    expanded: bool,
+    terminal: bool,
+    visits: u32,
+    /// The summed value of every seat, each between 0 and 1 like `outcome`
+    values: Vec<f64>,
+}
+
+impl Node {
+    fn new(mask: Option<TurnMask>, prior: f64, seats: usize) -> Self {
+        Node {
+            mask,
+            game: None,
+            prior,
+            children: Vec::new(),
+            expanded: false,
+            terminal: false,
+            visits: 0,
+            values: vec![0.0; seats],
+        }
+    }
+
+    fn mean(&self, seat: usize) -> f64 {
+        self.values[seat] / f64::from(self.visits.max(1))
+    }
+}
+
+fn is_terminal(game: &Game, config: &SearchConfig) -> bool {
+    is_over(game) || game.turn >= config.max_turns
+}
+
+/// The prior of every candidate turn and the value of every seat, as the network sees them. The
+/// search only expands the restricted turns of `get_candidates`.
+fn evaluate(
+    network: &PolicyValueNetwork,
+    encoder: &Encoder,
+    space: &ActionSpace,
+    game: &Game,
+) -> (Vec<(TurnMask, f64)>, Vec<f64>) {
+    let mover = game.get_active_index();
+    let inputs = encoder.encode(game, mover).insert_axis(Axis(0));
+    let (logits, values) = network.predict_batch(&inputs);
+    let turns = get_candidates(game);
+    let priors = space.policy(logits.row(0), &turns);
+
+    // The encoder puts the mover into the first slot
+    let seats = game.players.len();
+    let values = (0..seats)
+        .map(|seat| values[(0, (seat + seats - mover) % seats)])
+        .collect();
+    (turns.into_iter().zip(priors).collect(), values)
+}
+
+/// The PUCT choice of the seat to move at this node
+fn select(nodes: &[Node], node: usize, exploration: f64) -> Option<usize> {
+    let parent = &nodes[node];
+    let seat = parent
+        .game
+        .as_ref()
+        .expect("expanded nodes are reached")
+        .get_active_index();
+    let scale = exploration * f64::from(parent.visits.max(1)).sqrt();
+    parent.children.iter().copied().max_by(|a, b| {
+        let puct = |child: usize| {
+            let child = &nodes[child];
+            let value = if child.visits == 0 {
+                parent.mean(seat)
+            } else {
+                child.mean(seat)
+            };
+            value + scale * child.prior / f64::from(1 + child.visits)
+        };
+        puct(*a).total_cmp(&puct(*b))
+    })
+}
+
+/// Searches the position guided by the network. Returns the visits of every candidate turn.
+pub fn search(
+    network: &PolicyValueNetwork,
+    encoder: &Encoder,
+    space: &ActionSpace,
+    config: &SearchConfig,
+    game: &Game,
+) -> Vec<(TurnMask, u32)> {
+    let seats = game.players.len();
+    let mut root = Node::new(None, 1.0, seats);
+    root.terminal = is_terminal(game, config);
+    root.game = Some(game.clone());
+    let mut nodes = vec![root];
+
+    for _ in 0..config.simulations.max(1) {
+        let mut node = 0;
+        let mut path = vec![0];
+        while nodes[node].expanded && !nodes[node].terminal {
+            let Some(child) = select(&nodes, node, config.exploration) else {
+                nodes[node].terminal = true;
+                break;
+            };
+            if nodes[child].game.is_none() {
+                let mask = nodes[child].mask.expect("children have turns");
+                let parent = nodes[node].game.as_ref().expect("parents are reached");
+                match parent.apply(&mask) {
+                    Ok(next) => {
+                        nodes[child].terminal = is_terminal(&next, config);
+                        nodes[child].game = Some(next);
+                    }
+                    Err(_) => {
+                        nodes[node].children.retain(|c| *c != child);
+                        continue;
+                    }
+                }
+            }
+            node = child;
+            path.push(node);
+        }
+
+        let game = nodes[node].game.as_ref().expect("the leaf is reached");
+        let values = if nodes[node].terminal {
+            outcome(game)
+        } else {
+            let (priors, values) = evaluate(network, encoder, space, game);
+            for (mask, prior) in priors {
+                let child = nodes.len();
+                nodes.push(Node::new(Some(mask), prior, seats));
+                nodes[node].children.push(child);
+            }
+            nodes[node].expanded = true;
+            values
+        };
+        for node in path {
+            let node = &mut nodes[node];
+            node.visits += 1;
+            node.values
+                .iter_mut()
+                .zip(&values)
+                .for_each(|(v, o)| *v += o);
+        }
+    }
+
+    nodes[0]
+        .children
+        .iter()
+        .filter_map(|child| Some((nodes[*child].mask?, nodes[*child].visits)))
+        .collect()
+}
+
+fn most_visited(visits: &[(TurnMask, u32)]) -> Option<TurnMask> {
+    visits
+        .iter()
+        .filter(|(_, count)| *count > 0)
+        .max_by_key(|(_, count)| *count)
+        .map(|(mask, _)| *mask)
+}
+
+/// Plays the most visited turn of a search guided by a policy and value network
+pub struct AlphaZeroAgent {
+    network: PolicyValueNetwork<'static>,
+    encoder: Encoder,
+    space: ActionSpace,
+    pub search: SearchConfig,
+    rng: GameRng,
+}
+
+impl AlphaZeroAgent {
+    pub fn new(
+        network: PolicyValueNetwork<'static>,
+        encoder: Encoder,
+        search: SearchConfig,
+        rng: &mut GameRng,
+    ) -> Self {
+        AlphaZeroAgent {
+            space: ActionSpace::new(&encoder),
+            network,
+            encoder,
+            search,
+            rng: GameRng::from_rng(rng),
+        }
+    }
+}
+
+impl Agent for AlphaZeroAgent {
+    fn get_action(&mut self, observation: &Observation) -> TurnMask {
+        let game = observation.game();
+        let visits = search(
+            &self.network,
+            &self.encoder,
+            &self.space,
+            &self.search,
+            game,
+        );
+        most_visited(&visits).unwrap_or_else(|| sample_action(game, &mut self.rng))
+    }
+}
+
+/// A position of a self-play game with the targets to train on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub input: Array1<f64>,
+    /// 1 for the factors of the legal turns, see `ActionSpace::legal_mask`
+    pub legal: Array1<f64>,
+    /// The visit distribution of the search over the factors, see `ActionSpace::target`
+    pub policy: Array1<f64>,
+    /// The outcome of the seats in the order of the encoder, which the value head outputs
+    pub values: Array1<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfPlayGame {
+    pub record: GameRecord,
+    pub samples: Vec<Sample>,
+    /// The relative final score of every seat, see `mcts::outcome`
+    pub outcome: Vec<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlphaZeroConfig {
+    pub board: String,
+    pub seats: Vec<SeatRecord>,
+    /// Heights of the shared layers
+    pub trunk: Vec<usize>,
+    pub search: SearchConfig,
+    /// Turns at the start of a game that are drawn by their visits, instead of taking the most
+    /// visited one, so that games differ
+    pub temperature_turns: u32,
+    pub games_per_generation: usize,
+    /// Games that are kept to train on, the oldest are dropped first
+    pub window: usize,
+    pub batch_size: usize,
+    pub train_steps: usize,
+    pub method: Method,
+    pub learning_rate: f64,
+    pub l2: f64,
+    /// The weight of the value loss against the policy loss
+    pub value_weight: f64,
+    pub arena_games: usize,
+    /// The average outcome the trained network needs in the arena to replace the best one
+    pub gate: f64,
+    /// The directory the records of self-play games are written to, if any
+    pub store: Option<PathBuf>,
+}
+
+impl AlphaZeroConfig {
+    pub fn new(board: &str, seats: Vec<SeatRecord>) -> Self {
+        AlphaZeroConfig {
+            board: board.to_string(),
+            seats,
+            trunk: vec![256, 128],
+            search: SearchConfig::default(),
+            temperature_turns: 10,
+            games_per_generation: 16,
+            window: 200,
+            batch_size: 64,
+            train_steps: 200,
+            method: Method::adam(),
+            learning_rate: 1e-3,
+            l2: 1e-4,
+            value_weight: 1.0,
+            arena_games: 10,
+            gate: 0.55,
+            store: None,
+        }
+    }
+
+    fn record(&self, seed: u64) -> GameRecord {
+        GameRecord {
+            board: self.board.clone(),
+            seed,
+            seats: self.seats.clone(),
+            turns: Vec::new(),
+        }
+    }
+}
+
+/// Plays a game with the network of every seat searching for it
+fn play_game(
+    networks: &[&PolicyValueNetwork],
+    encoder: &Encoder,
+    space: &ActionSpace,
+    config: &AlphaZeroConfig,
+    seed: u64,
+) -> SelfPlayGame {
+    let mut rng = GameRng::seed_from_u64(seed);
+    let mut record = config.record(seed);
+    let mut game = record
+        .setup()
+        .expect("the setup is checked when the trainer is created");
+    let mut positions = Vec::new();
+    while !is_terminal(&game, &config.search) {
+        let mover = game.get_active_index();
+        let visits = search(networks[mover], encoder, space, &config.search, &game);
+        let (legal, counts): (Vec<TurnMask>, Vec<u32>) = visits.iter().copied().unzip();
+        let total = f64::from(counts.iter().sum::<u32>());
+        let turn = if total == 0.0 {
+            sample_action(&game, &mut rng)
+        } else {
+            let probabilities: Vec<f64> = counts.iter().map(|c| f64::from(*c) / total).collect();
+            positions.push((
+                encoder.encode(&game, mover),
+                space.legal_mask(&legal),
+                space.target(&legal, &probabilities),
+                mover,
+            ));
+            match WeightedIndex::new(&counts) {
+                Ok(index) if game.turn < config.temperature_turns => legal[index.sample(&mut rng)],
+                _ => most_visited(&visits).expect("there are visits"),
+            }
+        };
+        game = match game.apply(&turn) {
+            Ok(next) => next,
+            Err(_) => break,
+        };
+        record.turns.push(TurnRecord {
+            mask: Some(turn),
+            decisions: Vec::new(),
+        });
+    }
+
+    let outcome = outcome(&game);
+    let seats = outcome.len();
+    let samples = positions
+        .into_iter()
+        .map(|(input, legal, policy, mover)| {
+            let mut values = Array1::zeros(encoder.seats());
+            for slot in 0..seats {
+                values[slot] = outcome[(mover + slot) % seats];
+            }
+            Sample {
+                input,
+                legal,
+                policy,
+                values,
+            }
+        })
+        .collect();
+    SelfPlayGame {
+        record,
+        samples,
+        outcome,
+    }
+}
+
+/// What happened in one generation of training
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerationMetrics {
+    pub generation: u32,
+    pub games: usize,
+    /// The samples of the games in the window
+    pub samples: usize,
+    /// The average cross-entropy of the policy heads, if there was a training step
+    pub policy_loss: Option<f64>,
+    /// The average half squared error of the values, if there was a training step
+    pub value_loss: Option<f64>,
+    /// The average outcome of the trained network against the best one
+    pub arena_score: f64,
+    /// Whether the trained network became the best one
+    pub promoted: bool,
+}
+
+/// Trains a policy and value network on the visit distributions of its own searches.
+///
+/// Every generation the best network plays games against itself in parallel, which are stored
+/// and kept in a window to train on. The trained network then plays an arena against the best
+/// one and replaces it if its average outcome reaches the gate.
+pub struct AlphaZeroTrainer {
+    config: AlphaZeroConfig,
+    encoder: Encoder,
+    space: ActionSpace,
+    best: PolicyValueNetwork<'static>,
+    candidate: PolicyValueNetwork<'static>,
+    optimiser: PolicyValueOptimiser,
+    games: VecDeque<SelfPlayGame>,
+    generation: u32,
+    rng: GameRng,
+}
+
+impl AlphaZeroTrainer {
+    /// Fails if the board or seats of the config cannot be set up
+    pub fn new(config: AlphaZeroConfig, rng: &mut GameRng) -> Result<Self, ReplayError> {
+        let game = config.record(0).setup()?;
+        let encoder = Encoder::new(&game.board, config.seats.len());
+        let space = ActionSpace::new(&encoder);
+
+        let mut trunk = vec![encoder.size()];
+        trunk.extend(&config.trunk);
+        let best = PolicyValueNetwork::new(trunk, space.size(), encoder.seats(), rng);
+
+        let mut optimiser = Optimiser::new(config.method, Loss::MeanSquared, config.learning_rate);
+        optimiser.l2 = config.l2;
+        optimiser.clip = Some(10.0);
+        Ok(AlphaZeroTrainer {
+            candidate: best.clone(),
+            best,
+            optimiser: PolicyValueOptimiser::new(optimiser),
+            games: VecDeque::new(),
+            encoder,
+            space,
+            config,
+            generation: 0,
+            rng: GameRng::from_rng(rng),
+        })
+    }
+
+    pub fn config(&self) -> &AlphaZeroConfig {
+        &self.config
+    }
+
+    pub fn encoder(&self) -> &Encoder {
+        &self.encoder
+    }
+
+    /// The network that plays the self-play games
+    pub fn best(&self) -> &PolicyValueNetwork<'static> {
+        &self.best
+    }
+
+    /// The network that is trained
+    pub fn candidate(&self) -> &PolicyValueNetwork<'static> {
+        &self.candidate
+    }
+
+    /// The games in the window, oldest first
+    pub fn games(&self) -> impl Iterator<Item = &SelfPlayGame> {
+        self.games.iter()
+    }
+
+    /// An agent with a copy of the best network
+    pub fn agent(&mut self) -> AlphaZeroAgent {
+        AlphaZeroAgent::new(
+            self.best.clone(),
+            self.encoder.clone(),
+            self.config.search,
+            &mut self.rng,
+        )
+    }
+
+    /// Plays the games of a generation in parallel, stores them and adds them to the window
+    pub fn self_play(&mut self) -> io::Result<usize> {
+        let seeds: Vec<u64> = (0..self.config.games_per_generation)
+            .map(|_| self.rng.random())
+            .collect();
+        let (best, encoder, space, config) = (&self.best, &self.encoder, &self.space, &self.config);
+        let games: Vec<SelfPlayGame> = seeds
+            .into_par_iter()
+            .map(|seed| {
+                play_game(
+                    &vec![best; config.seats.len()],
+                    encoder,
+                    space,
+                    config,
+                    seed,
+                )
+            })
+            .collect();
+
+        if let Some(directory) = &self.config.store {
+            std::fs::create_dir_all(directory)?;
+            for (i, game) in games.iter().enumerate() {
+                let name = format!("generation-{:04}-game-{i:04}.txt", self.generation);
+                std::fs::write(directory.join(name), game.record.to_string())?;
+            }
+        }
+        let played = games.len();
+        self.games.extend(games);
+        while self.games.len() > self.config.window {
+            self.games.pop_front();
+        }
+        Ok(played)
+    }
+
+    /// Trains the candidate on batches drawn from the window. Returns the average policy and
+    /// value losses, or none if there is nothing to train on.
+    pub fn train(&mut self) -> Option<(f64, f64)> {
+        let samples: Vec<&Sample> = self.games.iter().flat_map(|g| &g.samples).collect();
+        if samples.is_empty() || self.config.batch_size == 0 || self.config.train_steps == 0 {
+            return None;
+        }
+        let (mut policy_loss, mut value_loss) = (0.0, 0.0);
+        for _ in 0..self.config.train_steps {
+            let batch: Vec<&Sample> = (0..self.config.batch_size)
+                .map(|_| samples[self.rng.random_range(0..samples.len())])
+                .collect();
+            let rows = |f: fn(&Sample) -> &Array1<f64>| {
+                let mut rows = Array2::zeros((batch.len(), f(batch[0]).len()));
+                for (mut row, sample) in rows.rows_mut().into_iter().zip(&batch) {
+                    row.assign(f(sample));
+                }
+                rows
+            };
+            let inputs = rows(|s| &s.input);
+            let values = rows(|s| &s.values);
+            let (logits, predicted) = self.candidate.predict_batch(&inputs);
+
+            // The cross-entropy of the softmax of every head over its legal factors
+            let mut policy_gradient = Array2::zeros(logits.raw_dim());
+            for (row, sample) in batch.iter().enumerate() {
+                let probabilities = self
+                    .space
+                    .head_probabilities(logits.row(row), &sample.legal);
+                policy_loss -= sample
+                    .policy
+                    .iter()
+                    .zip(&probabilities)
+                    .filter(|(t, _)| **t > 0.0)
+                    .map(|(t, p)| t * p.max(1e-12).ln())
+                    .sum::<f64>()
+                    / batch.len() as f64;
+                policy_gradient
+                    .row_mut(row)
+                    .assign(&(probabilities - &sample.policy));
+            }
+            value_loss += Loss::MeanSquared.value(&predicted, &values);
+            let value_gradient = (predicted - values) * self.config.value_weight;
+
+            let gradients = self
+                .candidate
+                .backward(&inputs, policy_gradient, value_gradient);
+            self.optimiser.apply(&mut self.candidate, gradients);
+        }
+        let steps = self.config.train_steps as f64;
+        Some((policy_loss / steps, value_loss / steps))
+    }
+
+    /// The average outcome of the candidate against the best network, taking every seat in turn
+    pub fn arena(&mut self) -> f64 {
+        let games = self.config.arena_games;
+        if games == 0 {
+            return 0.0;
+        }
+        let seeds: Vec<u64> = (0..games).map(|_| self.rng.random()).collect();
+        let seats = self.config.seats.len();
+        let (best, candidate) = (&self.best, &self.candidate);
+        let (encoder, space, config) = (&self.encoder, &self.space, &self.config);
+        let scores: Vec<f64> = seeds
+            .into_par_iter()
+            .enumerate()
+            .map(|(i, seed)| {
+                let seat = i % seats;
+                let mut networks = vec![best; seats];
+                networks[seat] = candidate;
+                play_game(&networks, encoder, space, config, seed).outcome[seat]
+            })
+            .collect();
+        scores.iter().sum::<f64>() / games as f64
+    }
+
+    /// Plays, trains and gates one generation
+    pub fn run_generation(&mut self) -> io::Result<GenerationMetrics> {
+        let games = self.self_play()?;
+        let losses = self.train();
+        let arena_score = self.arena();
+        let promoted = arena_score >= self.config.gate;
+        if promoted {
+            self.best = self.candidate.clone();
+        }
+        let metrics = GenerationMetrics {
+            generation: self.generation,
+            games,
+            samples: self.games.iter().map(|g| g.samples.len()).sum(),
+            policy_loss: losses.map(|(policy, _)| policy),
+            value_loss: losses.map(|(_, value)| value),
+            arena_score,
+            promoted,
+        };
+        self.generation += 1;
+        Ok(metrics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{agent::actions::HEADS, test_support::seat};
+
+    #[test]
+    fn generations_are_played_stored_and_gated() {
+        let store = std::env::temp_dir().join(format!("alphazero-{}", std::process::id()));
+        let config = AlphaZeroConfig {
+            trunk: vec![16],
+            search: SearchConfig {
+                simulations: 8,
+                max_turns: 10,
+                ..SearchConfig::default()
+            },
+            temperature_turns: 4,
+            games_per_generation: 2,
+            window: 3,
+            batch_size: 8,
+            train_steps: 3,
+            arena_games: 2,
+            store: Some(store.clone()),
+            ..AlphaZeroConfig::new(
+                "NORMAL",
+                vec![
+                    seat("Rusviet", "Industrial", 0),
+                    seat("Polania", "Agricultural", 1),
+                ],
+            )
+        };
+        let mut rng = GameRng::seed_from_u64(0);
+        let mut trainer = AlphaZeroTrainer::new(config, &mut rng).unwrap();
+
+        let metrics = trainer.run_generation().unwrap();
+        assert_eq!(metrics.games, 2);
+        assert!(metrics.policy_loss.is_some() && metrics.value_loss.is_some());
+        assert!((0.0..=1.0).contains(&metrics.arena_score));
+        assert_eq!(
+            trainer.best().to_bytes().unwrap() == trainer.candidate().to_bytes().unwrap(),
+            metrics.promoted
+        );
+
+        for game in trainer.games() {
+            // Every stored game replays, and its samples hold distributions over every head
+            let replayed = game.record.replay().unwrap();
+            assert_eq!(replayed.turn, 10);
+            assert_eq!(game.samples.len(), 10);
+            for sample in &game.samples {
+                assert!((sample.policy.sum() - HEADS.len() as f64).abs() < 1e-9);
+                // The value targets are outcomes, on the scale of the value head
+                assert!(sample.values.iter().all(|v| (0.0..=1.0).contains(v)));
+                assert!(
+                    sample
+                        .policy
+                        .iter()
+                        .zip(&sample.legal)
+                        .all(|(p, l)| *p == 0.0 || *l == 1.0)
+                );
+            }
+        }
+        let stored = std::fs::read_dir(&store).unwrap().count();
+        assert_eq!(stored, 2);
+
+        trainer.run_generation().unwrap();
+        assert_eq!(trainer.games().count(), 3);
+        std::fs::remove_dir_all(store).unwrap();
+
+        let mut agent = trainer.agent();
+        let game = trainer.config().record(1).setup().unwrap();
+        let turn = agent.get_action(&Observation::new(&game, 0));
+        assert!(game.apply(&turn).is_ok());
+    }
+}
@@ -0,0 +1,116 @@
+use std::io::{self, BufRead, Write};
+
+use crate::turn::{
+    mask::{Primary, TurnMask},
+    predict::{get_fixed_primaries, get_secondaries},
+};
+
+use super::{Agent, Observation};
+
+/// Reads the turns of a human player in move notation from a console
+pub struct ConsoleAgent<I: BufRead, O: Write> {
+    input: I,
+    output: O,
+}
+
+impl ConsoleAgent<io::StdinLock<'static>, io::Stdout> {
+    pub fn stdio() -> Self {
+        ConsoleAgent::new(io::stdin().lock(), io::stdout())
+    }
+}
+
+impl<I: BufRead, O: Write> ConsoleAgent<I, O> {
+    pub fn new(input: I, output: O) -> Self {
+        ConsoleAgent { input, output }
+    }
+
+    fn print_state(&mut self, observation: &Observation) -> io::Result<()> {
+        let player = observation.player();
+        writeln!(
+            self.output,
+            "Turn {} - seat {} with {} coins, {} power, {} popularity, {} stars",
+            observation.game().turn,
+            observation.seat(),
+            player.total_coins(),
+            player.military.power,
+            player.popularity.popularity,
+            player.stars()
+        )
+    }
+
+    /// Lists the primary actions that need no movement, with their secondary actions
+    fn print_help(&mut self, observation: &Observation) -> io::Result<()> {
+        writeln!(
+            self.output,
+            "Enter a turn, e.g. `MOVE char(-1,3)+2oil; w1(0,2); BUILD mill@w2 pay w1:2wood`"
+        )?;
+        let game = observation.game();
+        for primary in get_fixed_primaries(game) {
+            writeln!(self.output, "  {primary}")?;
+            for secondary in get_secondaries(game, &primary) {
+                writeln!(self.output, "  {primary}; {secondary}")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The next legal turn typed in, or none at the end of the input
+    fn read_action(&mut self, observation: &Observation) -> io::Result<Option<TurnMask>> {
+        self.print_state(observation)?;
+        loop {
+            write!(self.output, "> ")?;
+            self.output.flush()?;
+
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.eq_ignore_ascii_case("help") {
+                self.print_help(observation)?;
+                continue;
+            }
+
+            match line.parse::<TurnMask>() {
+                Ok(mask) => match observation.game().apply(&mask) {
+                    Ok(_) => return Ok(Some(mask)),
+                    Err(reason) => writeln!(self.output, "Illegal turn: {reason}")?,
+                },
+                Err(error) => writeln!(self.output, "{}", error.pointer(line))?,
+            }
+        }
+    }
+}
+
+impl<I: BufRead, O: Write> Agent for ConsoleAgent<I, O> {
+    /// Falls back to a tax when the console is closed
+    fn get_action(&mut self, observation: &Observation) -> TurnMask {
+        match self.read_action(observation) {
+            Ok(Some(mask)) => mask,
+            _ => TurnMask::PrimaryOnly(Primary::Tax),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::game;
+
+    #[test]
+    fn retries_until_legal() {
+        let game = game(1);
+
+        let input = "TAXX\nMOVE w1(5,5)\nbolster\n".as_bytes();
+        let mut output = Vec::new();
+        let mask = ConsoleAgent::new(input, &mut output).get_action(&Observation::new(&game, 0));
+        assert_eq!(mask, TurnMask::PrimaryOnly(Primary::Bolster));
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("TAXX\n^ Unknown primary action"));
+        assert!(output.contains("Illegal turn"));
+    }
+}
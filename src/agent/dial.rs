@@ -0,0 +1,231 @@
+//! The simultaneous choice of the combat dials, solved by regret matching
+
+use rand::{Rng, distr::weighted::WeightedIndex, prelude::Distribution};
+
+use crate::{game::player::PlayerState, template::Position};
+
+use super::{Combat, CombatDial, MAX_DIAL, Observation};
+
+/// The values of the combat cards and how often each is in the deck
+const COMBAT_CARDS: [(u8, u32); 4] = [(2, 16), (3, 12), (4, 8), (5, 6)];
+
+/// Characters and mechs of the player on the field
+fn units_at(player: &PlayerState, position: &Position) -> u8 {
+    let character = u8::from(player.character.location.position == *position);
+    let mechs = player
+        .mechs
+        .mechs
+        .iter()
+        .flatten()
+        .filter(|field| field.position == *position)
+        .count() as u8;
+    character + mechs
+}
+
+/// The options of one side of a fight
+fn dial_options(power: u8, cards: u8) -> Vec<CombatDial> {
+    (0..=power.min(MAX_DIAL))
+        .flat_map(|power| (0..=cards).map(move |cards| CombatDial { power, cards }))
+        .collect()
+}
+
+/// The values of a hand drawn from the deck, highest first
+fn draw_hand<R: Rng + ?Sized>(cards: u8, deck: &WeightedIndex<u32>, rng: &mut R) -> Vec<u8> {
+    let mut hand: Vec<u8> = (0..cards)
+        .map(|_| COMBAT_CARDS[deck.sample(rng)].0)
+        .collect();
+    hand.sort_unstable_by(|a, b| b.cmp(a));
+    hand
+}
+
+fn total(dial: &CombatDial, hand: &[u8]) -> u32 {
+    u32::from(dial.power)
+        + hand
+            .iter()
+            .take(dial.cards.into())
+            .map(|v| u32::from(*v))
+            .sum::<u32>()
+}
+
+/// The regret matching strategy of the current regrets
+fn strategy(regrets: &[f64]) -> Vec<f64> {
+    let positive: f64 = regrets.iter().map(|r| r.max(0.0)).sum();
+    if positive > 0.0 {
+        regrets.iter().map(|r| r.max(0.0) / positive).collect()
+    } else {
+        vec![1.0 / regrets.len() as f64; regrets.len()]
+    }
+}
+
+/// The simultaneous choice of both combat dials, solved by regret matching against hands drawn
+/// from the deck for both sides.
+///
+/// Each side scores 1 for a win and loses `power_cost` for every power it spends. The
+/// attacker wins ties. Returns the options of the first side with their average strategy.
+pub fn solve_dial<R: Rng + ?Sized>(
+    (power, cards): (u8, u8),
+    (opponent_power, opponent_cards): (u8, u8),
+    attacking: bool,
+    iterations: u32,
+    power_cost: f64,
+    rng: &mut R,
+) -> (Vec<CombatDial>, Vec<f64>) {
+    let own = dial_options(power, cards);
+    let other = dial_options(opponent_power, opponent_cards);
+    let deck = WeightedIndex::new(COMBAT_CARDS.iter().map(|(_, count)| *count))
+        .expect("the deck has cards");
+
+    let mut regrets = (vec![0.0; own.len()], vec![0.0; other.len()]);
+    let mut average = vec![0.0; own.len()];
+    for _ in 0..iterations {
+        let hands = (
+            draw_hand(cards, &deck, rng),
+            draw_hand(opponent_cards, &deck, rng),
+        );
+        let sigma = (strategy(&regrets.0), strategy(&regrets.1));
+
+        // The chance to win of every pair of options
+        let wins: Vec<Vec<f64>> = own
+            .iter()
+            .map(|a| {
+                other
+                    .iter()
+                    .map(|b| {
+                        let (x, y) = (total(a, &hands.0), total(b, &hands.1));
+                        f64::from(u8::from(if attacking { x >= y } else { x > y }))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let own_utility: Vec<f64> = own
+            .iter()
+            .enumerate()
+            .map(|(i, a)| {
+                let win: f64 = sigma.1.iter().zip(&wins[i]).map(|(s, w)| s * w).sum();
+                win - power_cost * f64::from(a.power)
+            })
+            .collect();
+        let other_utility: Vec<f64> = other
+            .iter()
+            .enumerate()
+            .map(|(j, b)| {
+                let win: f64 = sigma
+                    .0
+                    .iter()
+                    .zip(&wins)
+                    .map(|(s, w)| s * (1.0 - w[j]))
+                    .sum();
+                win - power_cost * f64::from(b.power)
+            })
+            .collect();
+
+        for (regrets, sigma, utility) in [
+            (&mut regrets.0, &sigma.0, &own_utility),
+            (&mut regrets.1, &sigma.1, &other_utility),
+        ] {
+            let expected: f64 = sigma.iter().zip(utility).map(|(s, u)| s * u).sum();
+            regrets
+                .iter_mut()
+                .zip(utility)
+                .for_each(|(r, u)| *r += u - expected);
+        }
+        average.iter_mut().zip(&sigma.0).for_each(|(a, s)| *a += s);
+    }
+
+    let sum: f64 = average.iter().sum();
+    if sum > 0.0 {
+        average.iter_mut().for_each(|a| *a /= sum);
+    }
+    (own, average)
+}
+
+/// A dial of the seat drawn from its strategy in the fight, against an opponent holding at most
+/// one card for each of its characters and mechs on the field
+pub fn choose_dial<R: Rng + ?Sized>(
+    observation: &Observation,
+    combat: &Combat,
+    iterations: u32,
+    power_cost: f64,
+    rng: &mut R,
+) -> CombatDial {
+    let player = observation.player();
+    let attacking = combat.attacker == observation.seat();
+    let opponent = if attacking {
+        combat.defender
+    } else {
+        combat.attacker
+    };
+    let opponent = &observation.game().players[opponent];
+
+    let (options, strategy) = solve_dial(
+        (player.military.power, combat.max_cards.min(player.cards)),
+        (
+            opponent.military.power,
+            units_at(opponent, &combat.position).min(opponent.cards),
+        ),
+        attacking,
+        iterations,
+        power_cost,
+        rng,
+    );
+    match WeightedIndex::new(&strategy) {
+        Ok(index) => options[index.sample(rng)],
+        Err(_) => options[0],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{game::GameRng, test_support::game};
+
+    #[test]
+    fn dial_against_a_weaker_opponent() {
+        let mut rng = GameRng::seed_from_u64(0);
+
+        // Without power or cards the defender cannot win, so the attacker spends nothing
+        let (options, strategy) = solve_dial((5, 0), (0, 0), true, 200, 0.05, &mut rng);
+        let best = options[strategy
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap()
+            .0];
+        assert_eq!(best, CombatDial { power: 0, cards: 0 });
+
+        // A defender facing a stronger dial gives up instead of wasting power
+        let (options, strategy) = solve_dial((3, 0), (7, 2), false, 500, 0.05, &mut rng);
+        let spent: f64 = options
+            .iter()
+            .zip(&strategy)
+            .map(|(o, s)| s * f64::from(o.power))
+            .sum();
+        assert!(spent < 1.0, "{spent}");
+    }
+
+    #[test]
+    fn dials_stay_within_the_limits() {
+        let mut rng = GameRng::seed_from_u64(3);
+        let game = game(2);
+        let combat = Combat {
+            position: game.players[1].character.location.position,
+            attacker: 0,
+            defender: 1,
+            max_cards: 1,
+        };
+        for seat in [0, 1] {
+            let player = &game.players[seat];
+            let observation = Observation::new(&game, seat);
+            let dial = choose_dial(&observation, &combat, 100, 0.05, &mut rng);
+            assert!(
+                dial.power <= player.military.power.min(MAX_DIAL),
+                "{dial:?}"
+            );
+            assert!(dial.cards <= player.cards, "{dial:?}");
+            assert!(dial.cards <= combat.max_cards, "{dial:?}");
+        }
+    }
+}
@@ -0,0 +1,636 @@
+use std::{fmt, io::Write};
+
+use ndarray::{Array1, Array2, ArrayView1};
+use rand::{Rng, SeedableRng};
+
+use crate::{
+    game::{
+        GameRng,
+        game::Game,
+        record::{GameRecord, ReplayError, SeatRecord},
+    },
+    network::{
+        fcnn::{FCNN, MLFunction, Predictor},
+        loss::Loss,
+        optimiser::{Method, Optimiser},
+    },
+    turn::{
+        mask::TurnMask,
+        predict::{get_candidates, sample_action},
+    },
+};
+
+use super::{
+    Agent, Observation,
+    actions::{ActionSpace, HEADS},
+    encoder::Encoder,
+    mcts::is_over,
+};
+
+/// The output of every head that a turn is made of
+pub type ActionIndices = [usize; HEADS.len()];
+
+/// The value of a turn is the sum of the outputs of its factors, so that one prediction values
+/// every legal turn
+fn q_value(output: ArrayView1<f64>, action: &ActionIndices) -> f64 {
+    action.iter().map(|i| output[*i]).sum()
+}
+
+/// One decision of a seat and what happened until its next decision
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub observation: Array1<f64>,
+    pub action: ActionIndices,
+    pub reward: f64,
+    pub next_observation: Array1<f64>,
+    /// The turns the seat could choose from at the next observation
+    pub next_actions: Vec<ActionIndices>,
+    /// The game ended before the seat decided again
+    pub done: bool,
+    /// The game reached the turn cap before the seat decided again; the value of the next
+    /// observation still counts
+    pub truncated: bool,
+}
+
+/// The most recent transitions, of which training batches are drawn
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayBuffer {
+    capacity: usize,
+    transitions: Vec<Transition>,
+    /// Where the next transition replaces the oldest one, once the buffer is full
+    next: usize,
+}
+
+impl ReplayBuffer {
+    pub fn new(capacity: usize) -> Self {
+        ReplayBuffer {
+            capacity: capacity.max(1),
+            transitions: Vec::new(),
+            next: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.transitions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transitions.is_empty()
+    }
+
+    pub fn push(&mut self, transition: Transition) {
+        if self.transitions.len() < self.capacity {
+            self.transitions.push(transition);
+        } else {
+            self.transitions[self.next] = transition;
+        }
+        self.next = (self.next + 1) % self.capacity;
+    }
+
+    /// Transitions drawn uniformly with replacement
+    pub fn sample<'a, R: Rng + ?Sized>(&'a self, count: usize, rng: &mut R) -> Vec<&'a Transition> {
+        if self.transitions.is_empty() {
+            return Vec::new();
+        }
+        (0..count)
+            .map(|_| &self.transitions[rng.random_range(0..self.transitions.len())])
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DqnConfig {
+    pub board: String,
+    pub seats: Vec<SeatRecord>,
+    /// Heights of the hidden layers
+    pub hidden: Vec<usize>,
+    pub gamma: f64,
+    pub method: Method,
+    pub loss: Loss,
+    pub learning_rate: f64,
+    pub batch_size: usize,
+    pub buffer_capacity: usize,
+    pub games_per_epoch: usize,
+    pub train_steps_per_epoch: usize,
+    /// Training steps between copies of the online network to the target network
+    pub target_sync: u64,
+    pub epsilon_start: f64,
+    pub epsilon_end: f64,
+    /// Epochs over which epsilon falls linearly from its start to its end
+    pub epsilon_epochs: u32,
+    /// Reward for every star gained
+    pub star_reward: f64,
+    /// Reward for every coin the seat would score, counting territory and resources
+    pub coin_reward: f64,
+    pub max_turns: u32,
+}
+
+impl DqnConfig {
+    pub fn new(board: &str, seats: Vec<SeatRecord>) -> Self {
+        DqnConfig {
+            board: board.to_string(),
+            seats,
+            hidden: vec![256, 128],
+            gamma: 0.95,
+            method: Method::adam(),
+            loss: Loss::Huber { delta: 1.0 },
+            learning_rate: 1e-3,
+            batch_size: 64,
+            buffer_capacity: 100_000,
+            games_per_epoch: 4,
+            train_steps_per_epoch: 200,
+            target_sync: 500,
+            epsilon_start: 1.0,
+            epsilon_end: 0.05,
+            epsilon_epochs: 50,
+            star_reward: 5.0,
+            coin_reward: 0.2,
+            max_turns: 300,
+        }
+    }
+}
+
+/// Chooses a turn by its value, or a random candidate with probability epsilon
+fn choose<R: Rng + ?Sized>(
+    output: ArrayView1<f64>,
+    game: &Game,
+    turns: &[(TurnMask, ActionIndices)],
+    epsilon: f64,
+    rng: &mut R,
+) -> (TurnMask, Game) {
+    let explore = rng.random_bool(epsilon.clamp(0.0, 1.0));
+    let mut order: Vec<usize> = (0..turns.len()).collect();
+    if explore {
+        order.sort_by_cached_key(|_| rng.random::<u32>());
+    } else {
+        let values: Vec<f64> = turns.iter().map(|(_, a)| q_value(output, a)).collect();
+        order.sort_by(|a, b| values[*b].total_cmp(&values[*a]));
+    }
+    order
+        .into_iter()
+        .find_map(|i| {
+            let turn = turns[i].0;
+            game.apply(&turn).ok().map(|next| (turn, next))
+        })
+        .unwrap_or_else(|| {
+            let turn = sample_action(game, rng);
+            let next = game.apply(&turn).expect("sampled turns are legal");
+            (turn, next)
+        })
+}
+
+/// The candidate turns of the active seat with their factors. These are the restricted turns of
+/// `get_candidates`, so the agent never plays or values any other turn.
+fn candidate_turns(space: &ActionSpace, game: &Game) -> Vec<(TurnMask, ActionIndices)> {
+    get_candidates(game)
+        .into_iter()
+        .map(|turn| (turn, space.indices(&turn)))
+        .collect()
+}
+
+/// Plays the turn with the highest value of a network trained by `DqnTrainer`
+pub struct DqnAgent {
+    network: FCNN<'static>,
+    encoder: Encoder,
+    space: ActionSpace,
+    /// The chance to play a random candidate instead
+    pub epsilon: f64,
+    rng: GameRng,
+}
+
+impl DqnAgent {
+    pub fn new(network: FCNN<'static>, encoder: Encoder, rng: &mut GameRng) -> Self {
+        DqnAgent {
+            space: ActionSpace::new(&encoder),
+            network,
+            encoder,
+            epsilon: 0.0,
+            rng: GameRng::from_rng(rng),
+        }
+    }
+}
+
+impl Agent for DqnAgent {
+    fn get_action(&mut self, observation: &Observation) -> TurnMask {
+        let game = observation.game();
+        let output = self
+            .network
+            .predict(&self.encoder.encode(game, observation.seat()));
+        let turns = candidate_turns(&self.space, game);
+        choose(output.view(), game, &turns, self.epsilon, &mut self.rng).0
+    }
+}
+
+/// What happened in one epoch of training
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpochMetrics {
+    pub epoch: u32,
+    pub epsilon: f64,
+    pub games: usize,
+    /// The average number of turns of a game
+    pub turns: f64,
+    /// The average final coins of a seat
+    pub coins: f64,
+    /// The average shaped reward of a seat over a whole game
+    pub reward: f64,
+    pub transitions: u64,
+    pub buffer: usize,
+    /// The average temporal difference loss, if there was a training step
+    pub loss: Option<f64>,
+    pub target_syncs: u64,
+}
+
+impl EpochMetrics {
+    /// The columns of the metrics, as written before the first epoch
+    pub const HEADER: &str =
+        "epoch,epsilon,games,turns,coins,reward,transitions,buffer,loss,target_syncs";
+}
+
+/// One line of comma separated values, in the order of `HEADER`
+impl fmt::Display for EpochMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{},{:.4},{},{:.1},{:.2},{:.3},{},{},",
+            self.epoch,
+            self.epsilon,
+            self.games,
+            self.turns,
+            self.coins,
+            self.reward,
+            self.transitions,
+            self.buffer
+        )?;
+        if let Some(loss) = self.loss {
+            write!(f, "{loss:.6}")?;
+        }
+        write!(f, ",{}", self.target_syncs)
+    }
+}
+
+/// The decision of a seat that still waits for its outcome
+struct Pending {
+    observation: Array1<f64>,
+    action: ActionIndices,
+    stars: u8,
+    coins: u32,
+}
+
+/// Deep Q-learning from self-play.
+///
+/// Every seat is played by the online network with epsilon-greedy exploration. A transition of a
+/// seat spans from one of its decisions to its next one, so it includes the turns of all
+/// opponents, and is rewarded by the stars and coins the seat gained in between. Targets are
+/// valued by a target network that is synced with the online network periodically.
+pub struct DqnTrainer {
+    config: DqnConfig,
+    encoder: Encoder,
+    space: ActionSpace,
+    online: FCNN<'static>,
+    target: FCNN<'static>,
+    optimiser: Optimiser,
+    buffer: ReplayBuffer,
+    epoch: u32,
+    /// Training steps so far
+    steps: u64,
+    /// Transitions stored so far, including those that were replaced
+    stored: u64,
+    rng: GameRng,
+}
+
+impl DqnTrainer {
+    /// Fails if the board or seats of the config cannot be set up
+    pub fn new(config: DqnConfig, rng: &mut GameRng) -> Result<Self, ReplayError> {
+        let game = DqnTrainer::record(&config, 0).setup()?;
+        let encoder = Encoder::new(&game.board, config.seats.len());
+        let space = ActionSpace::new(&encoder);
+
+        let mut heights = vec![encoder.size()];
+        heights.extend(&config.hidden);
+        heights.push(space.size());
+        let online = FCNN::new(heights, &MLFunction::Linear, rng);
+
+        let mut optimiser = Optimiser::new(config.method, config.loss, config.learning_rate);
+        optimiser.clip = Some(10.0);
+        Ok(DqnTrainer {
+            target: online.clone(),
+            online,
+            optimiser,
+            buffer: ReplayBuffer::new(config.buffer_capacity),
+            encoder,
+            space,
+            config,
+            epoch: 0,
+            steps: 0,
+            stored: 0,
+            rng: GameRng::from_rng(rng),
+        })
+    }
+
+    fn record(config: &DqnConfig, seed: u64) -> GameRecord {
+        GameRecord {
+            board: config.board.clone(),
+            seed,
+            seats: config.seats.clone(),
+            turns: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &DqnConfig {
+        &self.config
+    }
+
+    pub fn network(&self) -> &FCNN<'static> {
+        &self.online
+    }
+
+    pub fn encoder(&self) -> &Encoder {
+        &self.encoder
+    }
+
+    pub fn buffer(&self) -> &ReplayBuffer {
+        &self.buffer
+    }
+
+    /// A greedy agent with a copy of the online network
+    pub fn agent(&mut self) -> DqnAgent {
+        DqnAgent::new(self.online.clone(), self.encoder.clone(), &mut self.rng)
+    }
+
+    /// The exploration rate of the current epoch
+    pub fn epsilon(&self) -> f64 {
+        let config = &self.config;
+        let progress = (f64::from(self.epoch) / f64::from(config.epsilon_epochs.max(1))).min(1.0);
+        config.epsilon_start * (1.0 - progress) + config.epsilon_end * progress
+    }
+
+    fn reward(&self, pending: &Pending, game: &Game, seat: usize) -> f64 {
+        let player = &game.players[seat];
+        self.config.star_reward * (f64::from(player.stars()) - f64::from(pending.stars))
+            + self.config.coin_reward * (f64::from(player.total_coins()) - f64::from(pending.coins))
+    }
+
+    fn store(&mut self, transition: Transition) {
+        self.buffer.push(transition);
+        self.stored += 1;
+    }
+
+    /// Plays a game and stores its transitions. Returns the final game and the total reward of
+    /// every seat.
+    pub fn self_play(&mut self) -> (Game, Vec<f64>) {
+        let epsilon = self.epsilon();
+        let mut game = DqnTrainer::record(&self.config, self.rng.random())
+            .setup()
+            .expect("the setup is checked when the trainer is created");
+        let seats = game.players.len();
+        let mut pending: Vec<Option<Pending>> = (0..seats).map(|_| None).collect();
+        let mut rewards = vec![0.0; seats];
+
+        while game.turn < self.config.max_turns {
+            let seat = game.get_active_index();
+            let observation = self.encoder.encode(&game, seat);
+            let turns = candidate_turns(&self.space, &game);
+            if let Some(previous) = pending[seat].take() {
+                let reward = self.reward(&previous, &game, seat);
+                rewards[seat] += reward;
+                self.store(Transition {
+                    observation: previous.observation,
+                    action: previous.action,
+                    reward,
+                    next_observation: observation.clone(),
+                    next_actions: turns.iter().map(|(_, a)| *a).collect(),
+                    done: false,
+                    truncated: false,
+                });
+            }
+
+            let output = self.online.predict(&observation);
+            let (turn, next) = choose(output.view(), &game, &turns, epsilon, &mut self.rng);
+            let player = &game.players[seat];
+            pending[seat] = Some(Pending {
+                observation,
+                action: self.space.indices(&turn),
+                stars: player.stars(),
+                coins: player.total_coins(),
+            });
+            game = next;
+            if game.players[seat].has_won() {
+                break;
+            }
+        }
+
+        let done = is_over(&game);
+        for (seat, previous) in pending.into_iter().enumerate() {
+            let Some(previous) = previous else {
+                continue;
+            };
+            let reward = self.reward(&previous, &game, seat);
+            rewards[seat] += reward;
+            // A truncated game goes on, so the seat is valued as if it were its turn
+            let next_actions = if done {
+                Vec::new()
+            } else {
+                let offset = (seat + seats - game.get_active_index()) % seats;
+                let next = Game {
+                    turn: game.turn + offset as u32,
+                    ..game.clone()
+                };
+                candidate_turns(&self.space, &next)
+                    .into_iter()
+                    .map(|(_, a)| a)
+                    .collect()
+            };
+            self.store(Transition {
+                observation: previous.observation,
+                action: previous.action,
+                reward,
+                next_observation: self.encoder.encode(&game, seat),
+                next_actions,
+                done,
+                truncated: !done,
+            });
+        }
+        (game, rewards)
+    }
+
+    /// One optimiser step on a batch drawn from the buffer. Returns the temporal difference
+    /// loss before the step, or none while the buffer holds less than a batch.
+    pub fn train_step(&mut self) -> Option<f64> {
+        let size = self.config.batch_size;
+        if size == 0 || self.buffer.len() < size {
+            return None;
+        }
+        let batch = self.buffer.sample(size, &mut self.rng);
+        let rows = |f: fn(&Transition) -> &Array1<f64>| {
+            let mut inputs = Array2::zeros((batch.len(), self.encoder.size()));
+            for (mut row, transition) in inputs.rows_mut().into_iter().zip(&batch) {
+                row.assign(f(transition));
+            }
+            inputs
+        };
+        let (inputs, next_inputs) = (rows(|t| &t.observation), rows(|t| &t.next_observation));
+        let outputs = self.online.predict_batch(&inputs);
+        let next_outputs = self.target.predict_batch(&next_inputs);
+
+        // Only the outputs of the factors of the taken turn are moved towards the target
+        let mut targets = outputs.clone();
+        let (mut values, mut expected) = (Array2::zeros((size, 1)), Array2::zeros((size, 1)));
+        for (row, transition) in batch.iter().enumerate() {
+            let best = transition
+                .next_actions
+                .iter()
+                .map(|a| q_value(next_outputs.row(row), a))
+                .fold(f64::NEG_INFINITY, f64::max);
+            let future = if transition.done || best == f64::NEG_INFINITY {
+                0.0
+            } else {
+                self.config.gamma * best
+            };
+            let value = q_value(outputs.row(row), &transition.action);
+            let target = transition.reward + future;
+            for index in transition.action {
+                targets[(row, index)] -= value - target;
+            }
+            values[(row, 0)] = value;
+            expected[(row, 0)] = target;
+        }
+        let loss = self.optimiser.loss.value(&values, &expected);
+        self.optimiser.step(&mut self.online, &inputs, &targets);
+
+        self.steps += 1;
+        if self.steps.is_multiple_of(self.config.target_sync.max(1)) {
+            self.target = self.online.clone();
+        }
+        Some(loss)
+    }
+
+    /// Plays the games of an epoch, then trains on the buffer
+    pub fn run_epoch(&mut self) -> EpochMetrics {
+        let epsilon = self.epsilon();
+        let stored = self.stored;
+        let games = self.config.games_per_epoch;
+        let (mut turns, mut coins, mut reward) = (0.0, 0.0, 0.0);
+        for _ in 0..games {
+            let (game, rewards) = self.self_play();
+            turns += f64::from(game.turn);
+            coins += game
+                .players
+                .iter()
+                .map(|p| f64::from(p.total_coins()))
+                .sum::<f64>()
+                / game.players.len() as f64;
+            reward += rewards.iter().sum::<f64>() / rewards.len() as f64;
+        }
+
+        let losses: Vec<f64> = (0..self.config.train_steps_per_epoch)
+            .filter_map(|_| self.train_step())
+            .collect();
+        let per_game = |total: f64| total / games.max(1) as f64;
+        let metrics = EpochMetrics {
+            epoch: self.epoch,
+            epsilon,
+            games,
+            turns: per_game(turns),
+            coins: per_game(coins),
+            reward: per_game(reward),
+            transitions: self.stored - stored,
+            buffer: self.buffer.len(),
+            loss: (!losses.is_empty()).then(|| losses.iter().sum::<f64>() / losses.len() as f64),
+            target_syncs: self.steps / self.config.target_sync.max(1),
+        };
+        self.epoch += 1;
+        metrics
+    }
+
+    /// Runs the epochs and writes the metrics of each as soon as it is done
+    pub fn train(&mut self, epochs: u32, metrics: &mut impl Write) -> std::io::Result<()> {
+        writeln!(metrics, "{}", EpochMetrics::HEADER)?;
+        for _ in 0..epochs {
+            writeln!(metrics, "{}", self.run_epoch())?;
+            metrics.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::seat;
+
+    fn transition(reward: f64) -> Transition {
+        Transition {
+            observation: Array1::zeros(2),
+            action: [0; HEADS.len()],
+            reward,
+            next_observation: Array1::zeros(2),
+            next_actions: Vec::new(),
+            done: true,
+            truncated: false,
+        }
+    }
+
+    #[test]
+    fn buffer_replaces_the_oldest() {
+        let mut buffer = ReplayBuffer::new(3);
+        for reward in 0..5 {
+            buffer.push(transition(f64::from(reward)));
+        }
+        assert_eq!(buffer.len(), 3);
+        let mut rng = GameRng::seed_from_u64(0);
+        let sample = buffer.sample(50, &mut rng);
+        assert_eq!(sample.len(), 50);
+        assert!(sample.iter().all(|t| t.reward >= 2.0));
+    }
+
+    #[test]
+    fn short_training_run() {
+        let config = DqnConfig {
+            hidden: vec![16],
+            batch_size: 8,
+            games_per_epoch: 1,
+            train_steps_per_epoch: 5,
+            target_sync: 3,
+            epsilon_epochs: 2,
+            max_turns: 16,
+            ..DqnConfig::new(
+                "NORMAL",
+                vec![
+                    seat("Rusviet", "Industrial", 0),
+                    seat("Polania", "Agricultural", 1),
+                ],
+            )
+        };
+        let mut rng = GameRng::seed_from_u64(0);
+        let mut trainer = DqnTrainer::new(config, &mut rng).unwrap();
+        let mut metrics = Vec::new();
+        trainer.train(2, &mut metrics).unwrap();
+
+        let metrics = String::from_utf8(metrics).unwrap();
+        let lines: Vec<&str> = metrics.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], EpochMetrics::HEADER);
+        assert!(lines[1].starts_with("0,1.0000,1,16.0,"));
+
+        // Every seat decides every other turn, and its last decision reaches the turn cap
+        assert_eq!(trainer.buffer().len(), 32);
+        let transitions = &trainer.buffer().transitions;
+        assert!(transitions.iter().all(|t| !t.done));
+        let truncated: Vec<&Transition> = transitions.iter().filter(|t| t.truncated).collect();
+        assert_eq!(truncated.len(), 4);
+        assert!(truncated.iter().all(|t| !t.next_actions.is_empty()));
+        assert_eq!(trainer.epsilon(), trainer.config().epsilon_end);
+
+        let mut agent = trainer.agent();
+        let game = DqnTrainer::record(trainer.config(), 1).setup().unwrap();
+        let turn = agent.get_action(&Observation::new(&game, 0));
+        assert!(game.apply(&turn).is_ok());
+    }
+
+    #[test]
+    fn missing_factions_are_rejected() {
+        let config = DqnConfig::new("NORMAL", vec![seat("Atlantis", "Industrial", 0)]);
+        let mut rng = GameRng::seed_from_u64(0);
+        assert!(DqnTrainer::new(config, &mut rng).is_err());
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+
+use ndarray::{Array1, Array2, ArrayView1, Axis, concatenate, s};
+
+use crate::{
+    game::{RESOURCES, Tile, board::Board, game::Game, player::PlayerState},
+    template::{Position, SecondaryAction, faction::FACTIONS},
+};
+
+/// Changes whenever the layout of the encoding changes, so that trained networks can be checked
+/// against the encoder they were trained with
+pub const VERSION: u32 = 1;
+
+const TILES: [Tile; 8] = [
+    Tile::Woods,
+    Tile::Tundra,
+    Tile::Mountain,
+    Tile::Farm,
+    Tile::Village,
+    Tile::Lake,
+    Tile::Factory,
+    Tile::Home,
+];
+
+const SECONDARY_ACTIONS: [SecondaryAction; 4] = [
+    SecondaryAction::Upgrade,
+    SecondaryAction::Deploy,
+    SecondaryAction::Build,
+    SecondaryAction::Enlist,
+];
+
+/// Planes of a field: tile type, resources, the encounter token and whether it is a mine
+pub const FIELD_PLANES: usize = TILES.len() + RESOURCES.len() + 2;
+
+/// Planes of every seat: character, workers, mechs and the four buildings
+pub const UNIT_PLANES: usize = 7;
+
+/// Tracks of every seat
+pub const PLAYER_TRACKS: usize = 63;
+
+/// The round and the number of seats
+pub const GAME_TRACKS: usize = 2;
+
+/// Encodes games as fixed size tensors for neural networks.
+///
+/// The board is encoded as planes over its fields, in the order of their positions, and the
+/// players as rows of tracks. Seats are rotated so that the encoded seat comes first, and seats
+/// that are not taken are left at zero.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encoder {
+    positions: Vec<Position>,
+    columns: HashMap<Position, usize>,
+    seats: usize,
+}
+
+impl Encoder {
+    /// An encoder for games on this board with at most this many seats
+    pub fn new(board: &Board, seats: usize) -> Self {
+        let mut positions: Vec<Position> = board.fields.keys().copied().collect();
+        positions.sort();
+        let columns = positions.iter().enumerate().map(|(i, p)| (*p, i)).collect();
+        Encoder {
+            positions,
+            columns,
+            seats,
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        VERSION
+    }
+
+    pub fn seats(&self) -> usize {
+        self.seats
+    }
+
+    /// The positions of the columns of the board planes
+    pub fn positions(&self) -> &[Position] {
+        &self.positions
+    }
+
+    pub fn planes(&self) -> usize {
+        FIELD_PLANES + UNIT_PLANES * self.seats
+    }
+
+    /// The length of the flat encoding
+    pub fn size(&self) -> usize {
+        self.planes() * self.positions.len() + self.seats * PLAYER_TRACKS + GAME_TRACKS
+    }
+
+    /// The seats in encoding order, starting with the given one
+    fn rotation(&self, game: &Game, seat: usize) -> impl Iterator<Item = usize> {
+        let seats = game.players.len();
+        assert!(
+            seats <= self.seats,
+            "the encoder has {} seats, the game {seats}",
+            self.seats
+        );
+        (0..seats).map(move |i| (seat + i) % seats)
+    }
+
+    /// The board as planes of shape `(planes, fields)`
+    pub fn board_planes(&self, game: &Game, seat: usize) -> Array2<f64> {
+        let mut planes = Array2::zeros((self.planes(), self.positions.len()));
+        for (position, field) in &game.board.fields {
+            let Some(&column) = self.columns.get(position) else {
+                continue;
+            };
+            let mut column = planes.column_mut(column);
+            if let Some(tile) = TILES.iter().position(|t| *t == field.tile) {
+                column[tile] = 1.0;
+            }
+            for (i, resource) in RESOURCES.iter().enumerate() {
+                column[TILES.len() + i] = f64::from(field.resources.get(resource));
+            }
+            column[FIELD_PLANES - 2] = f64::from(u8::from(field.encounter_token));
+            column[FIELD_PLANES - 1] = f64::from(u8::from(field.tunnelable));
+        }
+
+        for (slot, player) in self.rotation(game, seat).enumerate() {
+            let player = &game.players[player];
+            let offset = FIELD_PLANES + UNIT_PLANES * slot;
+            let buildings = &player.buildings;
+            let units = [
+                (0, Some(&player.character.location)),
+                (3, buildings.tunnel.as_ref()),
+                (4, buildings.mill.as_ref()),
+                (5, buildings.armory.as_ref()),
+                (6, buildings.monument.as_ref()),
+            ]
+            .into_iter()
+            .chain(player.production.workers.iter().map(|w| (1, w.as_ref())))
+            .chain(player.mechs.mechs.iter().map(|m| (2, m.as_ref())));
+            for (plane, field) in units {
+                if let Some(&column) = field.and_then(|f| self.columns.get(&f.position)) {
+                    planes[[offset + plane, column]] += 1.0;
+                }
+            }
+        }
+        planes
+    }
+
+    /// The tracks of every seat as rows of shape `(seats, PLAYER_TRACKS)`
+    pub fn player_tracks(&self, game: &Game, seat: usize) -> Array2<f64> {
+        let mut tracks = Array2::zeros((self.seats, PLAYER_TRACKS));
+        for (slot, player) in self.rotation(game, seat).enumerate() {
+            let values = player_tracks(&game.players[player]);
+            tracks.row_mut(slot).assign(&Array1::from(values));
+        }
+        tracks
+    }
+
+    /// The whole game as one vector of length `size`, as seen by the seat
+    pub fn encode(&self, game: &Game, seat: usize) -> Array1<f64> {
+        let planes = self.board_planes(game, seat);
+        let tracks = self.player_tracks(game, seat);
+        let round = [f64::from(game.get_round()), game.players.len() as f64];
+        let planes = planes
+            .to_shape(planes.len())
+            .expect("planes are contiguous");
+        let tracks = tracks
+            .to_shape(tracks.len())
+            .expect("tracks are contiguous");
+        concatenate(
+            Axis(0),
+            &[
+                planes.view(),
+                tracks.view(),
+                Array1::from(round.to_vec()).view(),
+            ],
+        )
+        .expect("all parts are vectors")
+    }
+
+    /// The tracks of the encoded seat within an encoding
+    pub fn own_tracks<'a>(&self, encoding: &'a Array1<f64>) -> ArrayView1<'a, f64> {
+        let start = self.planes() * self.positions.len();
+        encoding.slice(s![start..start + PLAYER_TRACKS])
+    }
+}
+
+fn flag(value: bool) -> f64 {
+    f64::from(u8::from(value))
+}
+
+/// The tracks of a player, starting with a 1 to tell taken seats apart
+fn player_tracks(player: &PlayerState) -> Vec<f64> {
+    let upgrades = &player.upgrades;
+    let recruits = &player.recruits;
+    let mut tracks = vec![
+        1.0,
+        f64::from(player.coins),
+        f64::from(player.cards),
+        f64::from(player.combat_wins),
+        f64::from(player.military.power),
+        f64::from(player.popularity.popularity),
+        f64::from(player.stars()),
+        player.production.deployed_workers as f64,
+        f64::from(player.total_coins()),
+    ];
+    tracks.extend(
+        [
+            upgrades.popularity_evolved,
+            upgrades.power_evolved,
+            upgrades.card_evolved,
+            upgrades.move_evolved,
+            upgrades.tax_evolved,
+            upgrades.produce_evolved,
+        ]
+        .map(flag),
+    );
+    tracks.extend(
+        [
+            upgrades.upgrade_base_cost,
+            upgrades.upgrade_evolution_cost,
+            upgrades.deploy_base_cost,
+            upgrades.deploy_evolution_cost,
+            upgrades.build_base_cost,
+            upgrades.build_evolution_cost,
+            upgrades.enlist_base_cost,
+            upgrades.enlist_evolution_cost,
+        ]
+        .map(f64::from),
+    );
+    tracks.extend(
+        [
+            upgrades.upgrade_coins,
+            upgrades.deploy_coins,
+            upgrades.build_coins,
+            upgrades.enlist_coins,
+        ]
+        .map(f64::from),
+    );
+    tracks.extend(
+        [
+            recruits.secondary_military_recruited,
+            recruits.secondary_coin_recruited,
+            recruits.secondary_popularity_recruited,
+            recruits.secondary_card_recruited,
+            recruits.onetime_military_recruited,
+            recruits.onetime_coin_recruited,
+            recruits.onetime_popularity_recruited,
+            recruits.onetime_card_recruited,
+        ]
+        .map(flag),
+    );
+    tracks.extend(
+        [
+            upgrades.star,
+            player.mechs.star,
+            player.buildings.star,
+            recruits.star,
+            player.production.star,
+            player.military.star,
+            player.popularity.star,
+        ]
+        .map(flag),
+    );
+    tracks.extend(
+        FACTIONS
+            .iter()
+            .map(|faction| flag(faction.faction_ability == player.faction_ability)),
+    );
+    for secondary in [
+        player.move_secondary,
+        player.trade_secondary,
+        player.produce_secondary,
+        player.bolster_secondary,
+    ] {
+        tracks.extend(SECONDARY_ACTIONS.iter().map(|s| flag(*s == secondary)));
+    }
+    debug_assert_eq!(tracks.len(), PLAYER_TRACKS);
+    tracks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::game;
+
+    #[test]
+    fn rotation_and_padding() {
+        let game = game(3);
+        let encoder = Encoder::new(&game.board, 5);
+        assert_eq!(encoder.encode(&game, 0).len(), encoder.size());
+
+        let (own, next) = (
+            encoder.player_tracks(&game, 0),
+            encoder.player_tracks(&game, 1),
+        );
+        assert_eq!(own.row(1), next.row(0));
+        assert_eq!(own.row(0), next.row(2));
+        assert!(own.row(3).iter().chain(own.row(4)).all(|v| *v == 0.0));
+
+        // Every taken seat has its character on exactly one field
+        let planes = encoder.board_planes(&game, 2);
+        for slot in 0..5 {
+            let characters = planes.row(FIELD_PLANES + UNIT_PLANES * slot).sum();
+            assert_eq!(characters, if slot < 3 { 1.0 } else { 0.0 });
+        }
+        let encoding = encoder.encode(&game, 2);
+        assert_eq!(encoder.own_tracks(&encoding), next.row(1));
+    }
+}
@@ -0,0 +1,454 @@
+use std::{error::Error, fmt, io, path::Path, str::FromStr, sync::Arc};
+
+use crate::{
+    game::{
+        buildings::BUILDINGS, game::Game, military, popularity, production::WORKERS,
+        recruits::RECRUITS,
+    },
+    turn::check::check_reachable,
+};
+
+type Evaluate = dyn Fn(&Game, usize) -> f64 + Send + Sync;
+
+/// A scoring function of a seat in a position, shared by the threads of a search
+#[derive(Clone)]
+pub struct Evaluator(Arc<Evaluate>);
+
+impl Evaluator {
+    pub fn new(evaluate: impl Fn(&Game, usize) -> f64 + Send + Sync + 'static) -> Self {
+        Evaluator(Arc::new(evaluate))
+    }
+
+    pub fn evaluate(&self, game: &Game, seat: usize) -> f64 {
+        (self.0)(game, seat)
+    }
+}
+
+impl fmt::Debug for Evaluator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Evaluator")
+    }
+}
+
+impl From<Weights> for Evaluator {
+    fn from(weights: Weights) -> Self {
+        Evaluator::new(move |game, seat| weights.evaluate(game, seat))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Feature {
+    Coins,
+    Stars,
+    /// Progress towards the star of each track, between 0 and 1
+    UpgradeProgress,
+    MechProgress,
+    BuildingProgress,
+    RecruitProgress,
+    WorkerProgress,
+    PopularityProgress,
+    PowerProgress,
+    CombatProgress,
+    /// Coins for the controlled fields at the current popularity
+    Territory,
+    /// Coins for the resources on controlled fields at the current popularity
+    Resources,
+    /// Upgrades that are still possible
+    UpgradePotential,
+    /// Fields the mechs can reach with a single step
+    MechMobility,
+    /// Units that an enemy character or mech can reach with a single step
+    ThreatExposure,
+}
+
+pub const FEATURES: [Feature; 15] = [
+    Feature::Coins,
+    Feature::Stars,
+    Feature::UpgradeProgress,
+    Feature::MechProgress,
+    Feature::BuildingProgress,
+    Feature::RecruitProgress,
+    Feature::WorkerProgress,
+    Feature::PopularityProgress,
+    Feature::PowerProgress,
+    Feature::CombatProgress,
+    Feature::Territory,
+    Feature::Resources,
+    Feature::UpgradePotential,
+    Feature::MechMobility,
+    Feature::ThreatExposure,
+];
+
+impl Feature {
+    /// The name in weight files
+    pub fn name(&self) -> &'static str {
+        match self {
+            Feature::Coins => "coins",
+            Feature::Stars => "stars",
+            Feature::UpgradeProgress => "upgrade_progress",
+            Feature::MechProgress => "mech_progress",
+            Feature::BuildingProgress => "building_progress",
+            Feature::RecruitProgress => "recruit_progress",
+            Feature::WorkerProgress => "worker_progress",
+            Feature::PopularityProgress => "popularity_progress",
+            Feature::PowerProgress => "power_progress",
+            Feature::CombatProgress => "combat_progress",
+            Feature::Territory => "territory",
+            Feature::Resources => "resources",
+            Feature::UpgradePotential => "upgrade_potential",
+            Feature::MechMobility => "mech_mobility",
+            Feature::ThreatExposure => "threat_exposure",
+        }
+    }
+
+    fn index(&self) -> usize {
+        FEATURES
+            .iter()
+            .position(|f| f == self)
+            .expect("all features are listed")
+    }
+
+    /// The value of the feature for the seat
+    pub fn value(&self, game: &Game, seat: usize) -> f64 {
+        let player = &game.players[seat];
+        let fraction = |done: usize, total: usize| done.min(total) as f64 / total as f64;
+        match self {
+            Feature::Coins => f64::from(player.coins),
+            Feature::Stars => f64::from(player.stars()),
+            Feature::UpgradeProgress => fraction(evolved(game, seat), 6),
+            Feature::MechProgress => fraction(player.mechs.get_deployed().iter().count(), 4),
+            Feature::BuildingProgress => fraction(
+                BUILDINGS
+                    .iter()
+                    .filter(|b| player.buildings.is_build(**b))
+                    .count(),
+                4,
+            ),
+            Feature::RecruitProgress => fraction(
+                RECRUITS
+                    .iter()
+                    .filter(|r| player.recruits.is_secondary_recruited(**r))
+                    .count(),
+                4,
+            ),
+            Feature::WorkerProgress => fraction(player.production.deployed_workers, WORKERS.len()),
+            Feature::PopularityProgress => {
+                fraction(player.popularity.popularity.into(), popularity::MAX.into())
+            }
+            Feature::PowerProgress => fraction(player.military.power.into(), military::MAX.into()),
+            Feature::CombatProgress => fraction(player.combat_wins.into(), 2),
+            Feature::Territory => f64::from(
+                player.territory_size(None) * u32::from(player.popularity.fields_multiplier()),
+            ),
+            Feature::Resources => f64::from(
+                player.recources(None).total() / 2
+                    * u32::from(player.popularity.resources_multiplier()),
+            ),
+            Feature::UpgradePotential => {
+                let upgrades = &player.upgrades;
+                let cubes = upgrades.upgrade_evolution_cost
+                    + upgrades.deploy_evolution_cost
+                    + upgrades.build_evolution_cost
+                    + upgrades.enlist_evolution_cost;
+                (6 - evolved(game, seat)).min(cubes.into()) as f64
+            }
+            Feature::MechMobility => {
+                let fields = game.board.get_fields();
+                player
+                    .mechs
+                    .mechs
+                    .iter()
+                    .flatten()
+                    .map(|from| {
+                        fields
+                            .iter()
+                            .filter(|to| check_reachable(game, player, from, to, false).is_none())
+                            .count()
+                    })
+                    .sum::<usize>() as f64
+            }
+            Feature::ThreatExposure => {
+                let units = std::iter::once(&player.character.location)
+                    .chain(player.production.workers.iter().flatten())
+                    .chain(player.mechs.mechs.iter().flatten());
+                let threatened = units.filter(|field| {
+                    game.players
+                        .iter()
+                        .enumerate()
+                        .filter(|(s, _)| *s != seat)
+                        .any(|(_, enemy)| {
+                            std::iter::once(&enemy.character.location)
+                                .chain(enemy.mechs.mechs.iter().flatten())
+                                .any(|from| {
+                                    from.position == field.position
+                                        || check_reachable(game, enemy, from, field, false)
+                                            .is_none()
+                                })
+                        })
+                });
+                threatened.count() as f64
+            }
+        }
+    }
+}
+
+/// The number of evolved primary actions of the seat
+fn evolved(game: &Game, seat: usize) -> usize {
+    let upgrades = &game.players[seat].upgrades;
+    [
+        upgrades.popularity_evolved,
+        upgrades.power_evolved,
+        upgrades.card_evolved,
+        upgrades.move_evolved,
+        upgrades.tax_evolved,
+        upgrades.produce_evolved,
+    ]
+    .into_iter()
+    .filter(|evolved| *evolved)
+    .count()
+}
+
+pub fn feature_by_name(name: &str) -> Option<Feature> {
+    FEATURES
+        .into_iter()
+        .find(|feature| feature.name().eq_ignore_ascii_case(name))
+}
+
+/// A weighted sum of features.
+///
+/// Weight files hold one `<feature> = <weight>` per line, `#` starts a comment. Features that
+/// are not listed keep their default weight.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weights {
+    weights: [f64; FEATURES.len()],
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        let mut weights = Weights {
+            weights: [0.0; FEATURES.len()],
+        };
+        for (feature, weight) in [
+            (Feature::Coins, 1.0),
+            (Feature::Stars, 4.0),
+            (Feature::UpgradeProgress, 2.0),
+            (Feature::MechProgress, 2.0),
+            (Feature::BuildingProgress, 2.0),
+            (Feature::RecruitProgress, 2.0),
+            (Feature::WorkerProgress, 2.0),
+            (Feature::PopularityProgress, 2.0),
+            (Feature::PowerProgress, 2.0),
+            (Feature::CombatProgress, 2.0),
+            (Feature::Territory, 1.0),
+            (Feature::Resources, 1.0),
+            (Feature::UpgradePotential, 0.5),
+            (Feature::MechMobility, 0.1),
+            (Feature::ThreatExposure, -0.5),
+        ] {
+            weights.set(feature, weight);
+        }
+        weights
+    }
+}
+
+impl Weights {
+    pub fn get(&self, feature: Feature) -> f64 {
+        self.weights[feature.index()]
+    }
+
+    pub fn set(&mut self, feature: Feature, weight: f64) {
+        self.weights[feature.index()] = weight;
+    }
+
+    /// The weighted sum of the features of the seat. Features without weight are not computed.
+    pub fn evaluate(&self, game: &Game, seat: usize) -> f64 {
+        FEATURES
+            .iter()
+            .zip(self.weights)
+            .filter(|(_, weight)| *weight != 0.0)
+            .map(|(feature, weight)| weight * feature.value(game, seat))
+            .sum()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Weights, WeightsError> {
+        std::fs::read_to_string(path)
+            .map_err(WeightsError::Io)?
+            .parse()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for Weights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (feature, weight) in FEATURES.iter().zip(self.weights) {
+            writeln!(f, "{} = {weight}", feature.name())?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Weights {
+    type Err = WeightsError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut weights = Weights::default();
+        let mut seen = Vec::new();
+        for (line, text) in text.lines().enumerate().map(|(i, l)| (i + 1, l)) {
+            let text = text.split_once('#').map_or(text, |(text, _)| text).trim();
+            if text.is_empty() {
+                continue;
+            }
+            let parse_error = |message: String| WeightsError::Parse { line, message };
+            let (name, weight) = text
+                .split_once('=')
+                .ok_or_else(|| parse_error("Expected `<feature> = <weight>`".to_string()))?;
+            let (name, weight) = (name.trim(), weight.trim());
+            let feature = feature_by_name(name)
+                .ok_or_else(|| parse_error(format!("Unknown feature `{name}`")))?;
+            if seen.contains(&feature) {
+                return Err(parse_error(format!("Feature `{name}` is given twice")));
+            }
+            // NaN and infinite weights would poison every evaluation
+            let weight = weight
+                .parse()
+                .ok()
+                .filter(|weight: &f64| weight.is_finite())
+                .ok_or_else(|| parse_error(format!("Invalid weight `{weight}`")))?;
+            weights.set(feature, weight);
+            seen.push(feature);
+        }
+        Ok(weights)
+    }
+}
+
+#[derive(Debug)]
+pub enum WeightsError {
+    Io(io::Error),
+    Parse {
+        /// The line number, starting at 1
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for WeightsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WeightsError::Io(error) => write!(f, "The weights could not be read: {error}"),
+            WeightsError::Parse { line, message } => write!(f, "Line {line}: {message}"),
+        }
+    }
+}
+
+impl Error for WeightsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WeightsError::Io(error) => Some(error),
+            WeightsError::Parse { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{
+        game::{Resource, board::Field},
+        template::Position,
+        test_support::game,
+    };
+
+    fn set_popularity(game: &mut Game, seat: usize, popularity: u8) {
+        Rc::make_mut(&mut game.players[seat]).popularity.popularity = popularity;
+    }
+
+    fn field(game: &Game, x: i8, y: i8) -> Rc<Field> {
+        game.board.get_field(&Position::new(x, y)).unwrap().clone()
+    }
+
+    #[test]
+    fn territory_and_resources_follow_the_popularity() {
+        let mut game = game(2);
+        game.update_field(&Position::new(3, 0), |field| {
+            field.resources.add_resource(&Resource::Wood, 4)
+        });
+
+        // The home, the village and the mountain are controlled
+        for (popularity, territory, resources) in [(0, 6.0, 2.0), (7, 9.0, 4.0), (14, 12.0, 6.0)] {
+            set_popularity(&mut game, 0, popularity);
+            assert_eq!(
+                Feature::Territory.value(&game, 0),
+                territory,
+                "{popularity}"
+            );
+            assert_eq!(
+                Feature::Resources.value(&game, 0),
+                resources,
+                "{popularity}"
+            );
+        }
+    }
+
+    #[test]
+    fn mech_mobility() {
+        let mut game = game(2);
+        assert_eq!(Feature::MechMobility.value(&game, 0), 0.0);
+
+        let village = field(&game, 2, 1);
+        Rc::make_mut(&mut game.players[0]).mechs.mechs[2] = Some(village.clone());
+        let reach = Feature::MechMobility.value(&game, 0);
+        assert!(reach > 0.0 && reach <= 6.0, "{reach}");
+
+        // Every mech counts its own fields
+        Rc::make_mut(&mut game.players[0]).mechs.mechs[3] = Some(village);
+        assert_eq!(Feature::MechMobility.value(&game, 0), 2.0 * reach);
+    }
+
+    #[test]
+    fn threat_exposure() {
+        let mut game = game(2);
+        assert_eq!(Feature::ThreatExposure.value(&game, 0), 0.0);
+        assert_eq!(Feature::ThreatExposure.value(&game, 1), 0.0);
+
+        // An enemy character on the village threatens the worker there and the one on the
+        // neighbouring mountain, but not the character on its home base
+        let village = field(&game, 2, 1);
+        Rc::make_mut(&mut game.players[1]).character.location = village;
+        assert_eq!(Feature::ThreatExposure.value(&game, 0), 2.0);
+        // In turn the Rusviet character next to it threatens the enemy character
+        assert_eq!(Feature::ThreatExposure.value(&game, 1), 1.0);
+    }
+
+    #[test]
+    fn weights_round_trip() {
+        let mut weights = Weights::default();
+        weights.set(Feature::ThreatExposure, -1.25);
+        assert_eq!(weights.to_string().parse::<Weights>().unwrap(), weights);
+
+        let partial: Weights = "# only coins\nCOINS = 2 # doubled\n\n".parse().unwrap();
+        assert_eq!(partial.get(Feature::Coins), 2.0);
+        assert_eq!(
+            partial.get(Feature::Stars),
+            Weights::default().get(Feature::Stars)
+        );
+
+        for (text, line) in [
+            ("coins 2", 1),
+            ("\nluck = 1", 2),
+            ("coins = x", 1),
+            ("coins = nan", 1),
+            ("\nstars = -inf", 2),
+        ] {
+            match text.parse::<Weights>() {
+                Err(WeightsError::Parse { line: l, .. }) => assert_eq!(l, line, "{text}"),
+                other => panic!("{text}: {other:?}"),
+            }
+        }
+        assert!("coins = 1\ncoins = 2".parse::<Weights>().is_err());
+    }
+}
@@ -1,20 +1,27 @@
-use core::panic;
-
-use crate::agent::Agent;
-use crate::game::turnhelper::turn;
-use crate::game::turnmask::{Tile, TurnMask};
-use crate::game::turnpredictor::get_actions;
-use crate::game_state::PlayerState;
-use crate::network::fcnn::{MLFunction, Predictor, Trainer, FCNN};
-
-use ndarray::Array1;
+use crate::agent::encoder::Encoder;
+use crate::agent::{Agent, Observation};
+use crate::game::GameRng;
+use crate::game::game::Game;
+use crate::network::fcnn::{FCNN, MLFunction, Predictor, Trainer};
+use crate::network::model::ModelError;
+use crate::turn::mask::TurnMask;
+use crate::turn::predict::{get_candidates, sample_action};
+
+use ndarray::{Array1, Array2};
+use rand::SeedableRng;
+use std::path::Path;
+
+/// Candidates that are scored on one thread, larger batches are split between threads
+const PARALLEL_ROWS: usize = 64;
 
 pub struct PredictiveQAgent<'a> {
     coin_network: FCNN<'a>,
+    encoder: Encoder,
+    rng: GameRng,
 }
 
 impl PredictiveQAgent<'_> {
-    pub fn new(hidden: usize, layout: Layout) -> Self {
+    pub fn new(encoder: Encoder, hidden: usize, layout: Layout, rng: &mut GameRng) -> Self {
         let layout = match layout {
             Layout::Fixed { height } => vec![height; hidden],
             Layout::Convolution {
@@ -28,49 +35,89 @@ impl PredictiveQAgent<'_> {
         };
 
         let mut heights = Vec::with_capacity(hidden + 2);
-        heights.push(VECTOR_SIZE);
+        heights.push(encoder.size());
         heights.extend(layout);
         heights.push(1);
 
         PredictiveQAgent {
-            coin_network: FCNN::new(heights, &MLFunction::ELU),
+            coin_network: FCNN::new(heights, &MLFunction::ELU, rng),
+            encoder,
+            rng: GameRng::from_rng(rng),
+        }
+    }
+
+    /// Continues with a saved network, which has to take the encoding of the encoder. Forks its
+    /// own generator from the given one.
+    pub fn load(
+        encoder: Encoder,
+        path: impl AsRef<Path>,
+        rng: &mut GameRng,
+    ) -> Result<Self, ModelError> {
+        let coin_network = FCNN::load(path)?;
+        let (expected, found) = (encoder.size(), coin_network.heights()[0]);
+        if expected != found {
+            return Err(ModelError::IncompatibleInput { expected, found });
         }
+        Ok(PredictiveQAgent {
+            coin_network,
+            encoder,
+            rng: GameRng::from_rng(rng),
+        })
     }
 
-    pub fn predict(&self, state: &PlayerState) -> f64 {
-        let vector = transform_state(state);
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ModelError> {
+        self.coin_network.save(path)
+    }
+
+    /// Predicts the final coins of the player in this seat
+    pub fn predict(&self, game: &Game, seat: usize) -> f64 {
+        let vector = self.encoder.encode(game, seat);
         let coin = self.coin_network.predict(&vector);
         coin[0]
     }
 
-    pub fn train(&mut self, state: &PlayerState, gamma: f64, learning_rate: f64) {
+    pub fn train(&mut self, game: &Game, gamma: f64, learning_rate: f64) {
         // Search the best prediction of the next move
-        let (action, best_prediction) = self.max_turn(state);
-        let new_state = turn(*state, &action);
+        let seat = game.get_active_index();
+        let (_, new_state, best_prediction) = self.max_turn(game);
 
         // Update the prediction by the Bellman equation with the best prediction
-        let total_coins = new_state.total_coins() as f64;
+        let total_coins = new_state.players[seat].total_coins() as f64;
         let new_prediction = total_coins + gamma * best_prediction;
 
         // Train the network with the new prediction. the leaning rate is replacing the alpha in the Bellman equation
-        let input = transform_state(state);
+        let input = self.encoder.encode(game, seat);
         let target = Array1::from_elem(1, new_prediction);
         self.coin_network.train(&input, &target, learning_rate)
     }
 
-    fn max_turn(&mut self, state: &PlayerState) -> (TurnMask, f64) {
-        match get_actions(state)
-            .iter()
-            .map(|action| {
-                let new_state = turn(state.clone(), action);
-                let new_coin = self.predict(&new_state);
-                (action, new_coin)
-            })
-            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
-        {
-            Some((mask, coins)) => (*mask, coins),
-            None => panic!("No actions available!"),
+    /// The candidate turn with the highest predicted coins. These are the restricted turns of
+    /// `get_candidates`, a sampled turn is played if there are none.
+    fn max_turn(&mut self, game: &Game) -> (TurnMask, Game, f64) {
+        let seat = game.get_active_index();
+        let candidates: Vec<(TurnMask, Game)> = get_candidates(game)
+            .into_iter()
+            .filter_map(|action| Some((action, game.apply(&action).ok()?)))
+            .collect();
+        if candidates.is_empty() {
+            let action = sample_action(game, &mut self.rng);
+            let new_state = game.apply(&action).expect("sampled turns are legal");
+            let prediction = self.predict(&new_state, seat);
+            return (action, new_state, prediction);
+        }
+
+        // All candidates are scored with one batch
+        let mut inputs = Array2::zeros((candidates.len(), self.encoder.size()));
+        for (mut row, (_, new_state)) in inputs.rows_mut().into_iter().zip(&candidates) {
+            row.assign(&self.encoder.encode(new_state, seat));
         }
+        let coins = self.coin_network.predict_parallel(&inputs, PARALLEL_ROWS);
+        candidates
+            .into_iter()
+            .zip(coins.column(0))
+            .map(|((action, new_state), coin)| (action, new_state, *coin))
+            .max_by(|(_, _, a), (_, _, b)| a.total_cmp(b))
+            .expect("there are candidates")
     }
 }
 
@@ -86,98 +133,29 @@ pub enum Layout {
 }
 
 impl Agent for PredictiveQAgent<'_> {
-    fn get_action(&mut self, state: &PlayerState) -> TurnMask {
-        let (action, _) = self.max_turn(state);
+    fn get_action(&mut self, observation: &Observation) -> TurnMask {
+        let (action, _, _) = self.max_turn(observation.game());
         action
     }
 }
 
-const VECTOR_SIZE: usize = 65;
-
-fn transform_state(state: &PlayerState) -> Array1<f64> {
-    let mut result = Array1::zeros(VECTOR_SIZE);
-
-    // basic stats
-    result[0] = state.coins as f64;
-    result[1] = state.resources.wood as f64;
-    result[2] = state.resources.metal as f64;
-    result[3] = state.resources.oil as f64;
-    result[4] = state.resources.food as f64;
-    result[5] = state.fields as f64;
-    result[6] = state.cards as f64;
-    result[7] = state.turns as f64;
-
-    // upgrades
-    result[8] = state.upgrades.popularity_evolved as u8 as f64;
-    result[9] = state.upgrades.power_evolved as u8 as f64;
-    result[10] = state.upgrades.card_evolved as u8 as f64;
-    result[11] = state.upgrades.move_evolved as u8 as f64;
-    result[12] = state.upgrades.tax_evolved as u8 as f64;
-    result[13] = state.upgrades.produce_evolved as u8 as f64;
-    result[14] = state.upgrades.star as u8 as f64;
-    result[15] = state.upgrades.upgrade_base_cost as f64;
-    result[16] = state.upgrades.upgrade_evolution_cost as f64;
-    result[17] = state.upgrades.upgrade_coins as f64;
-    result[18] = state.upgrades.deploy_base_cost as f64;
-    result[19] = state.upgrades.deploy_evolution_cost as f64;
-    result[20] = state.upgrades.deploy_coins as f64;
-    result[21] = state.upgrades.build_base_cost as f64;
-    result[22] = state.upgrades.build_evolution_cost as f64;
-    result[23] = state.upgrades.build_coins as f64;
-    result[24] = state.upgrades.enlist_base_cost as f64;
-    result[25] = state.upgrades.enlist_evolution_cost as f64;
-    result[26] = state.upgrades.enlist_coins as f64;
-
-    // mechs
-    result[27] = state.mechs.first_deployed as u8 as f64;
-    result[28] = state.mechs.second_deployed as u8 as f64;
-    result[29] = state.mechs.third_deployed as u8 as f64;
-    result[30] = state.mechs.fourth_deployed as u8 as f64;
-    result[31] = state.mechs.star as u8 as f64;
-
-    // buildings
-    result[32] = state.buildings.mine_built as u8 as f64;
-    result[33] = state.buildings.mill_built as u8 as f64;
-    result[34] = state.buildings.armory_built as u8 as f64;
-    result[35] = state.buildings.monument_built as u8 as f64;
-    result[36] = state.buildings.star as u8 as f64;
-    result[37] = state.buildings.mill_location.is_none() as u8 as f64;
-    result[38] = (state.buildings.mill_location == Some(Tile::Woods)) as u8 as f64;
-    result[39] = (state.buildings.mill_location == Some(Tile::Mountain)) as u8 as f64;
-    result[40] = (state.buildings.mill_location == Some(Tile::Tundra)) as u8 as f64;
-    result[41] = (state.buildings.mill_location == Some(Tile::Farm)) as u8 as f64;
-    result[42] = (state.buildings.mill_location == Some(Tile::Village)) as u8 as f64;
-
-    // recruits
-    result[43] = state.recruits.secondary_military_recruited as u8 as f64;
-    result[44] = state.recruits.secondary_coin_recruited as u8 as f64;
-    result[45] = state.recruits.secondary_popularity_recruited as u8 as f64;
-    result[46] = state.recruits.secondary_card_recruited as u8 as f64;
-    result[47] = state.recruits.onetime_military_recruited as u8 as f64;
-    result[48] = state.recruits.onetime_coin_recruited as u8 as f64;
-    result[49] = state.recruits.onetime_popularity_recruited as u8 as f64;
-    result[50] = state.recruits.onetime_card_recruited as u8 as f64;
-    result[51] = state.recruits.star as u8 as f64;
-
-    // military
-    result[52] = state.military.power as f64;
-    result[53] = state.military.star as u8 as f64;
-
-    // popularity
-    result[54] = state.popularity.popularity as f64;
-    result[55] = state.popularity.star as u8 as f64;
-    result[56] = state.popularity.star_multiplier() as u8 as f64;
-    result[57] = state.popularity.fields_multiplier() as u8 as f64;
-    result[58] = state.popularity.resources_multiplier() as u8 as f64;
-
-    // production
-    result[59] = state.production.get(&Tile::Woods) as f64;
-    result[60] = state.production.get(&Tile::Mountain) as f64;
-    result[61] = state.production.get(&Tile::Tundra) as f64;
-    result[62] = state.production.get(&Tile::Farm) as f64;
-    result[63] = state.production.get(&Tile::Village) as f64;
-
-    result[64] = state.stars() as f64;
-
-    result
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::game;
+
+    #[test]
+    fn plays_and_trains_on_candidate_turns() {
+        let mut game = game(2);
+        let encoder = Encoder::new(&game.board, 2);
+        let layout = Layout::Fixed { height: 8 };
+        let mut agent = PredictiveQAgent::new(encoder, 1, layout, &mut GameRng::seed_from_u64(0));
+        for _ in 0..4 {
+            let seat = game.get_active_index();
+            let mask = agent.get_action(&Observation::new(&game, seat));
+            assert!(get_candidates(&game).contains(&mask), "{mask:?}");
+            agent.train(&game, 0.9, 0.01);
+            game = game.apply(&mask).unwrap();
+        }
+    }
 }
@@ -1,16 +1,23 @@
 use crate::{
-    template::{PrimaryAction, SecondaryAction},
     game::{
-        turnhelper::{check_secondary_cost, map_primary, map_tile_resource},
-        turnmask::{
-            Building, Mech, Move, Primary, PrimaryUpgrade, Produce, Recruit, Resource, Secondary,
-            SecondaryUpgrade, Tile, TurnMask,
-        },
+        Tile,
+        buildings::Building,
+        game::Game,
+        mechs::Mech,
+        player::PlayerState,
+        recruits::Recruit,
+        upgrades::{PrimaryUpgrade, SecondaryUpgrade},
+    },
+    template::{Position, PrimaryAction, SecondaryAction},
+    turn::{
+        check::History,
+        execute::{map_primary, map_secondary_resource, map_tile_resource},
+        mask::{Move, Movement, Primary, Secondary, Trade, TurnMask, UnitMovement},
+        predict::{Cargo, get_fixed_primaries, get_secondaries, get_unit_movements},
     },
-    game_state::PlayerState,
 };
 
-use super::Agent;
+use super::{Agent, Observation};
 
 #[derive(Debug)]
 pub enum Step {
@@ -30,7 +37,8 @@ pub struct PriorityAgent {
     pub mill_tile: Tile,
 }
 impl PriorityAgent {
-    fn choose_primary(&self, state: &PlayerState) -> Primary {
+    fn choose_primary(&self, game: &Game, primaries: &[Primary]) -> Primary {
+        let state = game.get_active_player();
         let step = self.priority.iter().find(|step| match step {
             Step::Population => !state.production.star,
             Step::Power => !state.military.star,
@@ -41,305 +49,242 @@ impl PriorityAgent {
             Step::Recruit => !state.recruits.star,
         });
 
-        match step {
-            Some(Step::Population) => move_produce(state, &Tile::Village, 8),
-            Some(Step::Power) => create_boster(state),
-            Some(Step::Popularity) => Primary::Promote,
+        let primary = match step {
+            Some(Step::Population) => move_produce(game, primaries, &Tile::Village, 8),
+            Some(Step::Power) => find(primaries, PrimaryAction::Bolster),
+            Some(Step::Popularity) => find(primaries, PrimaryAction::Promote),
             Some(Step::Upgrade) => {
-                let cost = state.upgrades.get_upgrade_cost(&SecondaryAction::Upgrade);
-                if cost <= state.resources.get(Resource::Oil) {
-                    // choose the primary action that enables upgrading as the secondary action
-                    create_primary_linked(state, SecondaryAction::Upgrade, &Tile::Tundra)
-                } else {
-                    // try to produce the wanted amount
-                    move_produce(state, &Tile::Tundra, cost)
-                }
+                secondary_step(game, primaries, SecondaryAction::Upgrade, &Tile::Tundra)
             }
             Some(Step::Deploy) => {
-                let cost = state.upgrades.get_upgrade_cost(&SecondaryAction::Deploy);
-                if cost <= state.resources.get(Resource::Metal) {
-                    // choose the primary action that enables deploying as the secondary action
-                    create_primary_linked(state, SecondaryAction::Deploy, &Tile::Mountain)
-                } else {
-                    // try to produce the wanted amount
-                    move_produce(state, &Tile::Mountain, cost)
-                }
+                secondary_step(game, primaries, SecondaryAction::Deploy, &Tile::Mountain)
             }
             Some(Step::Build) => {
-                let cost = state.upgrades.get_upgrade_cost(&SecondaryAction::Build);
-                if cost <= state.resources.get(Resource::Wood) {
-                    // choose the primary action that enables building as the secondary action
-                    create_primary_linked(state, SecondaryAction::Build, &Tile::Woods)
-                } else {
-                    // try to produce the wanted amount
-                    move_produce(state, &Tile::Woods, cost)
-                }
+                secondary_step(game, primaries, SecondaryAction::Build, &Tile::Woods)
             }
             Some(Step::Recruit) => {
-                let cost = state.upgrades.get_upgrade_cost(&SecondaryAction::Enlist);
-                if cost <= state.resources.get(Resource::Food) {
-                    // choose the primary action that enables enlisting as the secondary action
-                    create_primary_linked(state, SecondaryAction::Enlist, &Tile::Farm)
-                } else {
-                    // try to produce the wanted amount
-                    move_produce(state, &Tile::Farm, cost)
-                }
+                secondary_step(game, primaries, SecondaryAction::Enlist, &Tile::Farm)
             }
             _ => match self.final_step {
-                PrimaryAction::Move => match create_move(state, &Tile::Woods) {
-                    Some(m) => Primary::Move(m),
-                    None => Primary::Move(Move::Move1((Tile::Woods, Tile::Mountain))),
-                },
-                PrimaryAction::Tax => Primary::Tax,
-                PrimaryAction::Trade => create_trade(state, Resource::Wood),
-                PrimaryAction::Promote => create_promote(state),
-                PrimaryAction::Bolster => create_bolster(state),
-                PrimaryAction::Enforce => create_enforce(state),
-                PrimaryAction::Produce => create_produce(state, &Tile::Woods),
+                PrimaryAction::Move => create_move(game, &Tile::Woods),
+                PrimaryAction::Produce => create_produce(game, primaries, &Tile::Woods),
+                PrimaryAction::Trade => primaries
+                    .iter()
+                    .find(|p| matches!(p, Primary::Trade(Trade::Trade1(_))))
+                    .copied(),
+                action => find(primaries, action),
             },
-        }
-    }
-
-    fn choose_secondary(
-        &self,
-        state: &PlayerState,
-        secondary: SecondaryAction,
-    ) -> Option<Secondary> {
-        match secondary {
-            SecondaryAction::Upgrade => {
-                let primary_upgrade = if !state.upgrades.move_evolved {
-                    Some(PrimaryUpgrade::Move)
-                } else if !state.upgrades.tax_evolved {
-                    Some(PrimaryUpgrade::Tax)
-                } else if !state.upgrades.popularity_evolved {
-                    Some(PrimaryUpgrade::Promote)
-                } else if !state.upgrades.produce_evolved {
-                    Some(PrimaryUpgrade::Produce)
-                } else if !state.upgrades.power_evolved {
-                    Some(PrimaryUpgrade::Bolster)
-                } else if !state.upgrades.card_evolved {
-                    Some(PrimaryUpgrade::Enforce)
-                } else {
-                    return None;
-                };
-
-                let secondary_upgrade = if state.upgrades.upgrade_evolution_cost > 0 {
-                    Some(SecondaryUpgrade::Upgrade)
-                } else if state.upgrades.deploy_evolution_cost > 0 {
-                    Some(SecondaryUpgrade::Deploy)
-                } else if state.upgrades.build_evolution_cost > 0 {
-                    Some(SecondaryUpgrade::Build)
-                } else if state.upgrades.enlist_evolution_cost > 0 {
-                    Some(SecondaryUpgrade::Enlist)
-                } else {
-                    return None;
-                };
-
-                match (primary_upgrade, secondary_upgrade) {
-                    (Some(primary), Some(secondary)) => {
-                        Some(Secondary::Upgrade(primary, secondary))
-                    }
-                    _ => None,
-                }
-            }
-            SecondaryAction::Deploy => {
-                let mech = if !state.mechs.first_deployed {
-                    Some(Mech::First)
-                } else if !state.mechs.second_deployed {
-                    Some(Mech::Second)
-                } else if !state.mechs.third_deployed {
-                    Some(Mech::Third)
-                } else if !state.mechs.fourth_deployed {
-                    Some(Mech::Fourth)
-                } else {
-                    return None;
-                };
-
-                match mech {
-                    Some(mech) => Some(Secondary::Deploy(mech)),
-                    _ => None,
-                }
-            }
-            SecondaryAction::Build => {
-                let building = if !state.buildings.mill_built {
-                    Some(Building::Mill(self.mill_tile))
-                } else if !state.buildings.armory_built {
-                    Some(Building::Armory)
-                } else if !state.buildings.monument_built {
-                    Some(Building::Monument)
-                } else if !state.buildings.mine_built {
-                    Some(Building::Tunnel)
-                } else {
-                    return None;
-                };
+        };
 
-                match building {
-                    Some(building) => Some(Secondary::Build(building)),
-                    _ => None,
-                }
-            }
-            SecondaryAction::Enlist => {
-                let secondary = if !state.recruits.secondary_coin_recruited {
-                    Some(Recruit::Coin)
-                } else if !state.recruits.secondary_military_recruited {
-                    Some(Recruit::Power)
-                } else if !state.recruits.secondary_popularity_recruited {
-                    Some(Recruit::Popularity)
-                } else if !state.recruits.secondary_card_recruited {
-                    Some(Recruit::Card)
-                } else {
-                    return None;
-                };
+        primary.unwrap_or(Primary::Tax)
+    }
 
-                let onetime = if !state.recruits.onetime_coin_recruited {
-                    Some(Recruit::Coin)
-                } else if !state.recruits.onetime_military_recruited {
-                    Some(Recruit::Power)
-                } else if !state.recruits.onetime_popularity_recruited {
-                    Some(Recruit::Popularity)
-                } else if !state.recruits.onetime_card_recruited {
-                    Some(Recruit::Card)
-                } else {
-                    return None;
-                };
+    fn choose_secondary(&self, game: &Game, secondaries: &[Secondary]) -> Option<Secondary> {
+        let state = game.get_active_player();
+        secondaries
+            .iter()
+            .min_by_key(|secondary| self.rank_secondary(state, secondary))
+            .copied()
+    }
 
-                match (secondary, onetime) {
-                    (Some(secondary), Some(onetime)) => Some(Secondary::Enlist(secondary, onetime)),
-                    _ => None,
-                }
+    /// Lower ranks are preferred
+    fn rank_secondary(&self, state: &PlayerState, secondary: &Secondary) -> (usize, usize) {
+        match secondary {
+            Secondary::Upgrade(primary, secondary, _) => (
+                rank(&UPGRADE_PRIORITY, primary),
+                rank(&EVOLUTION_PRIORITY, secondary),
+            ),
+            Secondary::Deploy(mech, _, _) => (rank(&MECH_PRIORITY, mech), 0),
+            Secondary::Build(building, worker, _) => {
+                let on_mill_tile = state
+                    .production
+                    .get(*worker)
+                    .is_some_and(|field| field.tile == self.mill_tile);
+                (
+                    rank(&BUILDING_PRIORITY, building),
+                    usize::from(!on_mill_tile),
+                )
             }
+            Secondary::Enlist(secondary, onetime, _) => (
+                rank(&RECRUIT_PRIORITY, secondary),
+                rank(&RECRUIT_PRIORITY, onetime),
+            ),
         }
     }
 }
 
-fn create_enforce(state: &PlayerState) -> Primary {
-    if state.coins <= 0 {
-        Primary::Tax
-    } else {
-        Primary::Enforce
-    }
-}
+const UPGRADE_PRIORITY: [PrimaryUpgrade; 6] = [
+    PrimaryUpgrade::Move,
+    PrimaryUpgrade::Tax,
+    PrimaryUpgrade::Promote,
+    PrimaryUpgrade::Produce,
+    PrimaryUpgrade::Bolster,
+    PrimaryUpgrade::Enforce,
+];
 
-fn create_trade(state: &PlayerState, resource: Resource) -> Primary {
-    if state.coins <= 0 {
-        Primary::Tax
-    } else {
-        Primary::Trade(resource, resource)
-    }
-}
+const EVOLUTION_PRIORITY: [SecondaryUpgrade; 4] = [
+    SecondaryUpgrade::Upgrade,
+    SecondaryUpgrade::Deploy,
+    SecondaryUpgrade::Build,
+    SecondaryUpgrade::Enlist,
+];
 
-fn create_boster(state: &PlayerState) -> Primary {
-    if state.coins <= 0 {
-        Primary::Tax
-    } else {
-        Primary::Bolster
-    }
-}
+const MECH_PRIORITY: [Mech; 4] = [Mech::First, Mech::Second, Mech::Third, Mech::Fourth];
 
-fn create_primary_linked(state: &PlayerState, action: SecondaryAction, tile: &Tile) -> Primary {
-    match state.get_primary(action) {
-        PrimaryAction::Move => Primary::Tax,
-        PrimaryAction::Tax => Primary::Tax,
-        PrimaryAction::Trade => create_promote(state),
-        PrimaryAction::Promote => create_promote(state),
-        PrimaryAction::Bolster => create_bolster(state),
-        PrimaryAction::Enforce => create_bolster(state),
-        PrimaryAction::Produce => create_produce(state, tile),
-    }
+const BUILDING_PRIORITY: [Building; 4] = [
+    Building::Mill,
+    Building::Armory,
+    Building::Monument,
+    Building::Tunnel,
+];
+
+const RECRUIT_PRIORITY: [Recruit; 4] = [
+    Recruit::Coin,
+    Recruit::Power,
+    Recruit::Popularity,
+    Recruit::Card,
+];
+
+fn rank<T: PartialEq>(priority: &[T], item: &T) -> usize {
+    priority
+        .iter()
+        .position(|p| p == item)
+        .unwrap_or(priority.len())
 }
 
-fn create_bolster(state: &PlayerState) -> Primary {
-    if state.coins <= 0 {
-        Primary::Tax
-    } else {
-        Primary::Bolster
-    }
+/// The first legal primary action of this kind
+fn find(primaries: &[Primary], action: PrimaryAction) -> Option<Primary> {
+    primaries
+        .iter()
+        .find(|primary| map_primary(primary) == action)
+        .copied()
 }
 
-fn create_promote(state: &PlayerState) -> Primary {
-    if state.coins <= 0 {
-        Primary::Tax
+/// Takes the linked primary action if the secondary action is affordable, otherwise gathers the resource
+fn secondary_step(
+    game: &Game,
+    primaries: &[Primary],
+    action: SecondaryAction,
+    tile: &Tile,
+) -> Option<Primary> {
+    let state = game.get_active_player();
+    let cost = u32::from(state.upgrades.get_upgrade_cost(&action));
+    let resource = map_secondary_resource(&action);
+    if cost <= state.recources(None).get(&resource) {
+        // choose the primary action that enables the secondary action
+        match state.get_primary(action) {
+            PrimaryAction::Produce => create_produce(game, primaries, tile),
+            primary => find(primaries, primary),
+        }
     } else {
-        Primary::Promote
+        // try to produce the wanted amount
+        move_produce(game, primaries, tile, cost)
     }
 }
 
-fn move_produce(state: &PlayerState, target: &Tile, wanted: i32) -> Primary {
-    let current = match map_tile_resource(target) {
-        Some(resource) => state.resources.get(resource),
-        None => state.production.total(),
+/// Produces on the target tile if that reaches the wanted amount, otherwise moves workers there first
+fn move_produce(game: &Game, primaries: &[Primary], target: &Tile, wanted: u32) -> Option<Primary> {
+    let state = game.get_active_player();
+    let current = match target {
+        Tile::Village => state.production.deployed_workers as u32,
+        _ => map_tile_resource(target).map_or(0, |resource| state.recources(None).get(&resource)),
     };
-    if wanted - current <= state.production.get(target) {
-        return create_produce(state, target);
-    }
-    match create_move(state, target) {
-        Some(m) => return Primary::Move(m),
-        None => create_produce(state, target),
+    let production = workers_on(state, target);
+    if wanted.saturating_sub(current) <= production {
+        return create_produce(game, primaries, target);
     }
+    create_move(game, target).or_else(|| create_produce(game, primaries, target))
 }
 
-fn create_move(state: &PlayerState, target: &Tile) -> Option<Move> {
-    let mut source = Vec::new();
-    for tile in vec![
-        &Tile::Woods,
-        &Tile::Mountain,
-        &Tile::Tundra,
-        &Tile::Farm,
-        &Tile::Village,
-    ] {
-        if tile == target {
-            continue;
-        }
+fn workers_on(state: &PlayerState, tile: &Tile) -> u32 {
+    state
+        .production
+        .workers
+        .iter()
+        .flatten()
+        .filter(|field| field.tile == *tile)
+        .count() as u32
+}
 
-        let count = state.production.get(tile) as usize;
-        source.extend(vec![tile; count]);
-    }
-    if source.len() >= 3 && state.upgrades.move_evolved {
-        Some(Move::Move3(
-            (source[0], *target),
-            (source[1], *target),
-            (source[2], *target),
-        ))
-    } else if source.len() >= 2 {
-        Some(Move::Move2((source[0], *target), (source[1], *target)))
-    } else if source.len() >= 1 {
-        Some(Move::Move1((source[0], *target)))
-    } else {
-        None
+/// Moves workers onto the target tile one after another, as long as that brings more workers there
+fn create_move(game: &Game, target: &Tile) -> Option<Primary> {
+    let state = game.get_active_player();
+    let slots = if state.upgrades.move_evolved { 3 } else { 2 };
+    let tile_at = |position: &Position| game.board.get_field(position).map(|field| field.tile);
+
+    let mut history = History::new();
+    let mut sequence = Vec::with_capacity(slots);
+    for _ in 0..slots {
+        let best = get_unit_movements(game, state, &history, Cargo::Empty)
+            .into_iter()
+            .find(|(movement, _)| match movement {
+                UnitMovement::Worker(worker, Movement::Single((position, _))) => {
+                    tile_at(position) == Some(*target)
+                        && state
+                            .production
+                            .get(*worker)
+                            .is_some_and(|field| field.tile != *target)
+                }
+                _ => false,
+            });
+        match best {
+            Some((movement, next)) => {
+                sequence.push(movement);
+                history = next;
+            }
+            None => break,
+        }
     }
+
+    Move::from_movements(&sequence).map(Primary::Move)
 }
 
-fn create_produce(state: &PlayerState, target: &Tile) -> Primary {
-    let total = state.production.total();
-    if total >= 8 && state.coins <= 0 {
-        Primary::Tax
-    } else if total >= 6 && state.popularity.popularity <= 0 {
-        if state.coins <= 0 {
-            Primary::Tax
-        } else {
-            Primary::Promote
-        }
-    } else if total >= 4 && state.military.power <= 0 {
-        if state.coins <= 0 {
-            Primary::Tax
+/// The production with the most workers on the target tile, or whatever can pay for the production
+fn create_produce(game: &Game, primaries: &[Primary], target: &Tile) -> Option<Primary> {
+    let state = game.get_active_player();
+    if !state.can_produce() {
+        return if state.popularity.popularity == 0 {
+            find(primaries, PrimaryAction::Promote)
         } else {
-            Primary::Bolster
-        }
-    } else {
-        Primary::Produce(Produce::Produce1(*target))
+            find(primaries, PrimaryAction::Bolster)
+        };
     }
+
+    primaries
+        .iter()
+        .filter_map(|primary| match primary {
+            Primary::Produce(produce) => {
+                let score = produce
+                    .workers()
+                    .iter()
+                    .filter(|worker| {
+                        state
+                            .production
+                            .get(**worker)
+                            .is_some_and(|field| field.tile == *target)
+                    })
+                    .count();
+                Some((*primary, score))
+            }
+            _ => None,
+        })
+        .fold(
+            None,
+            |best: Option<(Primary, usize)>, (primary, score)| match best {
+                Some((_, best_score)) if best_score >= score => best,
+                _ => Some((primary, score)),
+            },
+        )
+        .map(|(primary, _)| primary)
 }
 
 impl Agent for PriorityAgent {
-    fn get_action(&mut self, state: &PlayerState) -> TurnMask {
-        let primary = self.choose_primary(state);
-        let secondary = state.get_secondary(map_primary(&primary));
-        if check_secondary_cost(state, &secondary) {
-            if let Some(secondary) = self.choose_secondary(state, secondary) {
-                return TurnMask::PrimaryAndSecondary(primary, secondary);
-            }
+    fn get_action(&mut self, observation: &Observation) -> TurnMask {
+        let game = observation.game();
+        let primary = self.choose_primary(game, &get_fixed_primaries(game));
+        match self.choose_secondary(game, &get_secondaries(game, &primary)) {
+            Some(secondary) => TurnMask::PrimaryAndSecondary(primary, secondary),
+            None => TurnMask::PrimaryOnly(primary),
         }
-
-        TurnMask::PrimaryOnly(primary)
     }
 }
@@ -0,0 +1,488 @@
+use std::time::{Duration, Instant};
+
+use rand::SeedableRng;
+use rayon::prelude::*;
+
+use crate::{
+    game::{
+        GameRng,
+        detached::DetachedGame,
+        game::Game,
+        zobrist::{update_hash, zobrist_hash},
+    },
+    turn::{mask::TurnMask, predict::sample_action},
+};
+
+use super::{Agent, Combat, CombatDial, Observation, dial::choose_dial, evaluation::Evaluator};
+
+/// When the search stops
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Budget {
+    /// Iterations of every tree
+    Iterations(u32),
+    Time(Duration),
+}
+
+/// How a rollout chooses the turns after the tree is left
+#[derive(Debug, Clone)]
+pub enum RolloutPolicy {
+    Random,
+    /// Plays the best of some sampled turns for the active seat
+    Heuristic {
+        samples: u32,
+        evaluate: Evaluator,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct MctsConfig {
+    pub budget: Budget,
+    /// The UCT exploration constant
+    pub exploration: f64,
+    /// Turns played in a rollout before the position is scored
+    pub rollout_depth: u32,
+    pub rollout: RolloutPolicy,
+    /// A node gets a new child while it has fewer than `widening * sqrt(visits + 1)` children
+    pub widening: f64,
+    /// Independent trees searched in parallel, whose root statistics are merged
+    pub threads: usize,
+    /// Keeps the subtree of the reached position for the next turn
+    pub reuse_tree: bool,
+    /// Iterations of regret matching for a combat dial
+    pub dial_iterations: u32,
+    /// The value of one power compared to winning a fight
+    pub power_cost: f64,
+}
+
+impl Default for MctsConfig {
+    fn default() -> Self {
+        MctsConfig {
+            budget: Budget::Iterations(1000),
+            exploration: std::f64::consts::SQRT_2,
+            rollout_depth: 20,
+            rollout: RolloutPolicy::Random,
+            widening: 2.0,
+            threads: 1,
+            reuse_tree: true,
+            dial_iterations: 300,
+            power_cost: 0.05,
+        }
+    }
+}
+
+/// The relative final scores of all seats, between 0 for the last and 1 for the leader
+pub fn outcome(game: &Game) -> Vec<f64> {
+    let scores: Vec<f64> = game
+        .players
+        .iter()
+        .map(|p| f64::from(p.total_coins()))
+        .collect();
+    let min = scores.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = scores.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if max > min {
+        scores.iter().map(|s| (s - min) / (max - min)).collect()
+    } else {
+        vec![0.5; scores.len()]
+    }
+}
+
+/// The final score of the seat with each star counted as a promise of more coins
+pub fn progress(game: &Game, seat: usize) -> f64 {
+    let player = &game.players[seat];
+    f64::from(player.total_coins()) + 4.0 * f64::from(player.stars())
+}
+
+/// Whether a player has six stars
+pub fn is_over(game: &Game) -> bool {
+    game.players.iter().any(|p| p.has_won())
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    /// The turn that led here from the parent
+    mask: Option<TurnMask>,
+    hash: u64,
+    /// The seat choosing among the children
+    seat: usize,
+    terminal: bool,
+    children: Vec<usize>,
+    visits: u32,
+    /// The summed outcome of every seat
+    values: Vec<f64>,
+}
+
+impl Node {
+    fn new(mask: Option<TurnMask>, hash: u64, game: &Game) -> Self {
+        Node {
+            mask,
+            hash,
+            seat: game.get_active_index(),
+            terminal: is_over(game),
+            children: Vec::new(),
+            visits: 0,
+            values: vec![0.0; game.players.len()],
+        }
+    }
+
+    fn mean(&self, seat: usize) -> f64 {
+        self.values[seat] / f64::from(self.visits.max(1))
+    }
+}
+
+/// A search tree whose nodes only hold turns, so it can be moved between threads
+#[derive(Debug, Clone)]
+struct Tree {
+    nodes: Vec<Node>,
+}
+
+impl Tree {
+    fn new(game: &Game, hash: u64) -> Self {
+        Tree {
+            nodes: vec![Node::new(None, hash, game)],
+        }
+    }
+
+    fn search(&mut self, root: &Game, rng: &mut GameRng, config: &MctsConfig, deadline: Instant) {
+        let mut iterations = 0;
+        loop {
+            match config.budget {
+                Budget::Iterations(limit) if iterations >= limit => break,
+                Budget::Time(_) if Instant::now() >= deadline => break,
+                _ => {}
+            }
+            self.iterate(root, rng, config);
+            iterations += 1;
+        }
+    }
+
+    /// Selects or adds a path from the root, plays a rollout and backs its outcome up
+    fn iterate(&mut self, root: &Game, rng: &mut GameRng, config: &MctsConfig) {
+        let mut game = root.clone();
+        let mut node = 0;
+        let mut path = vec![0];
+        while !self.nodes[node].terminal {
+            let (visits, children) = (self.nodes[node].visits, self.nodes[node].children.len());
+            let allowed = (config.widening * f64::from(visits + 1).sqrt()).ceil() as usize;
+            if children < allowed.max(1) {
+                let mask = sample_action(&game, rng);
+                match self.find_child(node, &mask) {
+                    Some(child) => {
+                        game = match game.apply(&mask) {
+                            Ok(next) => next,
+                            Err(_) => break,
+                        };
+                        node = child;
+                        path.push(node);
+                        continue;
+                    }
+                    None => {
+                        if let Ok(next) = game.apply(&mask) {
+                            let hash = update_hash(&game, self.nodes[node].hash, &next);
+                            let child = self.nodes.len();
+                            self.nodes.push(Node::new(Some(mask), hash, &next));
+                            self.nodes[node].children.push(child);
+                            path.push(child);
+                            game = next;
+                            break;
+                        }
+                    }
+                }
+            }
+
+            let Some(child) = self.select(node, config.exploration) else {
+                break;
+            };
+            let mask = self.nodes[child].mask.expect("children have turns");
+            game = match game.apply(&mask) {
+                Ok(next) => next,
+                Err(_) => break,
+            };
+            node = child;
+            path.push(node);
+        }
+
+        let values = rollout(game, rng, config);
+        for node in path {
+            let node = &mut self.nodes[node];
+            node.visits += 1;
+            node.values
+                .iter_mut()
+                .zip(&values)
+                .for_each(|(v, o)| *v += o);
+        }
+    }
+
+    /// The UCT choice of the seat to move at this node
+    fn select(&self, node: usize, exploration: f64) -> Option<usize> {
+        let parent = &self.nodes[node];
+        let log_visits = f64::from(parent.visits.max(1)).ln();
+        parent.children.iter().copied().max_by(|a, b| {
+            let uct = |child: usize| {
+                let child = &self.nodes[child];
+                child.mean(parent.seat)
+                    + exploration * (log_visits / f64::from(child.visits.max(1))).sqrt()
+            };
+            uct(*a).total_cmp(&uct(*b))
+        })
+    }
+
+    fn find_child(&self, node: usize, mask: &TurnMask) -> Option<usize> {
+        self.nodes[node]
+            .children
+            .iter()
+            .copied()
+            .find(|child| self.nodes[*child].mask.as_ref() == Some(mask))
+    }
+
+    /// The visits of every turn at the root
+    fn root_visits(&self) -> impl Iterator<Item = (TurnMask, u32)> + '_ {
+        self.nodes[0]
+            .children
+            .iter()
+            .filter_map(|child| Some((self.nodes[*child].mask?, self.nodes[*child].visits)))
+    }
+
+    /// The subtree of a node reached within this many turns that has the hash
+    fn find_position(&self, hash: u64, depth: usize) -> Option<usize> {
+        let mut layer = vec![0];
+        for _ in 0..=depth {
+            if let Some(node) = layer.iter().copied().find(|n| self.nodes[*n].hash == hash) {
+                return Some(node);
+            }
+            layer = layer
+                .iter()
+                .flat_map(|n| self.nodes[*n].children.iter().copied())
+                .collect();
+        }
+        None
+    }
+
+    /// Makes the node the new root and drops everything outside its subtree
+    fn reroot(&self, root: usize) -> Tree {
+        let mut nodes = Vec::new();
+        let mut stack = vec![(root, None)];
+        while let Some((old, parent)) = stack.pop() {
+            let index = nodes.len();
+            let mut node = self.nodes[old].clone();
+            node.children = Vec::new();
+            nodes.push(node);
+            if let Some(parent) = parent {
+                let parent: &mut Node = &mut nodes[parent];
+                parent.children.push(index);
+            }
+            stack.extend(
+                self.nodes[old]
+                    .children
+                    .iter()
+                    .rev()
+                    .map(|c| (*c, Some(index))),
+            );
+        }
+        nodes[0].mask = None;
+        Tree { nodes }
+    }
+}
+
+/// Plays on from the position and scores where it ends
+fn rollout(mut game: Game, rng: &mut GameRng, config: &MctsConfig) -> Vec<f64> {
+    for _ in 0..config.rollout_depth {
+        if is_over(&game) {
+            break;
+        }
+        let mask = match &config.rollout {
+            RolloutPolicy::Random => sample_action(&game, rng),
+            RolloutPolicy::Heuristic { samples, evaluate } => {
+                let seat = game.get_active_index();
+                let mut best: Option<(Game, f64)> = None;
+                for _ in 0..(*samples).max(1) {
+                    if let Ok(next) = game.apply(&sample_action(&game, rng)) {
+                        let score = evaluate.evaluate(&next, seat);
+                        if best.as_ref().is_none_or(|(_, s)| score > *s) {
+                            best = Some((next, score));
+                        }
+                    }
+                }
+                match best {
+                    Some((next, _)) => {
+                        game = next;
+                        continue;
+                    }
+                    None => break,
+                }
+            }
+        };
+        match game.apply(&mask) {
+            Ok(next) => game = next,
+            Err(_) => break,
+        }
+    }
+    outcome(&game)
+}
+
+/// Monte Carlo tree search with UCT selection.
+///
+/// The turns of a node are sampled from the legal turns and added as the node is visited more
+/// often, since late positions have far too many turns to list them all. Combat dials are drawn
+/// from the regret matching strategy of `dial::choose_dial`.
+pub struct MctsAgent {
+    pub config: MctsConfig,
+    rng: GameRng,
+    trees: Vec<Tree>,
+}
+
+impl MctsAgent {
+    /// Forks its own generator from the given one
+    pub fn new(config: MctsConfig, rng: &mut GameRng) -> Self {
+        MctsAgent {
+            config,
+            rng: GameRng::from_rng(rng),
+            trees: Vec::new(),
+        }
+    }
+
+    /// The trees to continue from, reusing the subtrees of the position if they reached it
+    fn take_trees(&mut self, game: &Game, hash: u64) -> Vec<Tree> {
+        let threads = self.config.threads.max(1);
+        let mut old = std::mem::take(&mut self.trees).into_iter();
+        (0..threads)
+            .map(|_| {
+                old.next()
+                    .filter(|_| self.config.reuse_tree)
+                    .and_then(|tree| {
+                        let node = tree.find_position(hash, game.players.len())?;
+                        Some(tree.reroot(node))
+                    })
+                    .unwrap_or_else(|| Tree::new(game, hash))
+            })
+            .collect()
+    }
+}
+
+impl Agent for MctsAgent {
+    fn get_action(&mut self, observation: &Observation) -> TurnMask {
+        let game = observation.game();
+        let mut trees = self.take_trees(game, zobrist_hash(game));
+
+        let deadline = match self.config.budget {
+            Budget::Time(time) => Instant::now() + time,
+            Budget::Iterations(_) => Instant::now(),
+        };
+        let searches: Vec<(DetachedGame, GameRng)> = trees
+            .iter()
+            .map(|_| (DetachedGame::new(game), GameRng::from_rng(&mut self.rng)))
+            .collect();
+        let config = &self.config;
+        trees
+            .par_iter_mut()
+            .zip(searches)
+            .for_each(|(tree, (root, mut rng))| {
+                tree.search(&root.into_game(), &mut rng, config, deadline);
+            });
+
+        // The most visited turn over all trees
+        let mut visits: Vec<(TurnMask, u32)> = Vec::new();
+        for (mask, count) in trees.iter().flat_map(Tree::root_visits) {
+            match visits.iter_mut().find(|(m, _)| *m == mask) {
+                Some((_, total)) => *total += count,
+                None => visits.push((mask, count)),
+            }
+        }
+        let best = visits
+            .iter()
+            .fold(
+                None,
+                |best: Option<(TurnMask, u32)>, (mask, count)| match best {
+                    Some((_, most)) if most >= *count => best,
+                    _ => Some((*mask, *count)),
+                },
+            )
+            .map(|(mask, _)| mask)
+            .unwrap_or_else(|| sample_action(game, &mut self.rng));
+
+        if self.config.reuse_tree {
+            self.trees = trees
+                .into_iter()
+                .filter_map(|tree| Some(tree.reroot(tree.find_child(0, &best)?)))
+                .collect();
+        }
+        best
+    }
+
+    fn combat_dial(&mut self, observation: &Observation, combat: &Combat) -> CombatDial {
+        choose_dial(
+            observation,
+            combat,
+            self.config.dial_iterations,
+            self.config.power_cost,
+            &mut self.rng,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::game;
+
+    #[test]
+    fn five_seats_in_parallel() {
+        let config = MctsConfig {
+            budget: Budget::Iterations(40),
+            rollout_depth: 6,
+            threads: 2,
+            ..MctsConfig::default()
+        };
+        let mut rng = GameRng::seed_from_u64(4);
+        let mut agents: Vec<MctsAgent> = (0..5)
+            .map(|_| MctsAgent::new(config.clone(), &mut rng))
+            .collect();
+
+        let mut game = game(5);
+        for _ in 0..15 {
+            let seat = game.get_active_index();
+            let mask = agents[seat].get_action(&Observation::new(&game, seat));
+            game = game.apply(&mask).unwrap();
+        }
+        assert!(agents.iter().all(|agent| !agent.trees.is_empty()));
+    }
+
+    #[test]
+    fn reuses_the_subtree() {
+        let config = MctsConfig {
+            budget: Budget::Iterations(50),
+            rollout_depth: 4,
+            ..MctsConfig::default()
+        };
+        let mut agent = MctsAgent::new(config, &mut GameRng::seed_from_u64(1));
+        let mut game = game(2);
+        for _ in 0..6 {
+            let seat = game.get_active_index();
+            let mask = agent.get_action(&Observation::new(&game, seat));
+            game = game.apply(&mask).unwrap();
+
+            // Playing every seat, the agent always finds the position in its tree
+            let tree = &agent.trees[0];
+            assert_eq!(tree.nodes[0].hash, zobrist_hash(&game));
+            assert!(tree.nodes[0].visits > 0);
+        }
+    }
+
+    #[test]
+    fn heuristic_rollouts() {
+        let config = MctsConfig {
+            budget: Budget::Iterations(30),
+            rollout_depth: 4,
+            rollout: RolloutPolicy::Heuristic {
+                samples: 3,
+                evaluate: Evaluator::new(progress),
+            },
+            ..MctsConfig::default()
+        };
+        let game = game(2);
+        let mut agent = MctsAgent::new(config, &mut GameRng::seed_from_u64(0));
+        let mask = agent.get_action(&Observation::new(&game, 0));
+        assert!(game.apply(&mask).is_ok());
+        let visits: u32 = agent.trees[0].nodes[0].visits;
+        assert!(visits > 0);
+    }
+}
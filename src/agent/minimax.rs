@@ -0,0 +1,412 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    game::{
+        game::Game,
+        zobrist::{update_hash, zobrist_hash},
+    },
+    turn::{
+        mask::{Primary, TurnMask},
+        predict::get_candidates,
+    },
+};
+
+use super::{
+    Agent, Observation,
+    evaluation::Evaluator,
+    mcts::progress,
+    transposition::{Bound, Entry, TranspositionTable},
+};
+
+/// How games of more than two seats are searched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplayer {
+    /// Every seat maximises its own score
+    MaxN,
+    /// All other seats play against the searching seat, which allows pruning
+    Paranoid,
+}
+
+#[derive(Debug, Clone)]
+pub struct MinimaxConfig {
+    /// The deepest search in turns, counting the turns of all seats
+    pub depth: u32,
+    /// Stops deepening once the time is used up. Without it the search is deterministic.
+    pub time: Option<Duration>,
+    pub multiplayer: Multiplayer,
+    /// The most turns searched at a position, the best ordered ones first
+    pub branching: usize,
+    /// Slots of the transposition table
+    pub table_size: usize,
+    /// The score of a seat in a position
+    pub evaluate: Evaluator,
+}
+
+impl Default for MinimaxConfig {
+    fn default() -> Self {
+        MinimaxConfig {
+            depth: 3,
+            time: None,
+            multiplayer: Multiplayer::Paranoid,
+            branching: 12,
+            table_size: 1 << 16,
+            evaluate: Evaluator::new(progress),
+        }
+    }
+}
+
+fn is_over(game: &Game) -> bool {
+    game.players.iter().any(|p| p.has_won())
+}
+
+/// A turn with the position and hash it leads to
+struct Child {
+    mask: TurnMask,
+    game: Game,
+    hash: u64,
+}
+
+/// The score of the seat against its strongest opponent
+fn relative(game: &Game, seat: usize, evaluate: &Evaluator) -> f64 {
+    let best_opponent = (0..game.players.len())
+        .filter(|s| *s != seat)
+        .map(|s| evaluate.evaluate(game, s))
+        .fold(f64::NEG_INFINITY, f64::max);
+    let own = evaluate.evaluate(game, seat);
+    if best_opponent.is_finite() {
+        own - best_opponent
+    } else {
+        own
+    }
+}
+
+/// A single search from one root position
+struct Search<'a> {
+    config: &'a MinimaxConfig,
+    root: usize,
+    deadline: Option<Instant>,
+    scores: &'a mut TranspositionTable<f64>,
+    values: &'a mut TranspositionTable<Vec<f64>>,
+}
+
+impl Search<'_> {
+    fn expired(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// The candidate turns in search order.
+    ///
+    /// Turns whose secondary action completes a star come first, then the turns the mover scores
+    /// highest. The best turn of an earlier search is tried first if it is among the kept ones.
+    fn children(&self, game: &Game, hash: u64, best: Option<TurnMask>) -> Vec<Child> {
+        let seat = game.get_active_index();
+        let stars = game.players[seat].stars();
+        let mut children: Vec<(bool, f64, Child)> = get_candidates(game)
+            .into_iter()
+            .filter_map(|mask| {
+                let next = game.apply(&mask).ok()?;
+                let star = matches!(mask, TurnMask::PrimaryAndSecondary(..))
+                    && next.players[seat].stars() > stars;
+                let score = self.config.evaluate.evaluate(&next, seat);
+                let hash = update_hash(game, hash, &next);
+                Some((
+                    star,
+                    score,
+                    Child {
+                        mask,
+                        game: next,
+                        hash,
+                    },
+                ))
+            })
+            .collect();
+        children.sort_by(|(s1, v1, _), (s2, v2, _)| s2.cmp(s1).then(v2.total_cmp(v1)));
+        children.truncate(self.config.branching.max(1));
+
+        let mut children: Vec<Child> = children.into_iter().map(|(_, _, child)| child).collect();
+        if let Some(index) = children.iter().position(|c| Some(c.mask) == best) {
+            let child = children.remove(index);
+            children.insert(0, child);
+        }
+        children
+    }
+
+    /// Alpha-beta search of the score of the root seat against its strongest opponent, which all
+    /// other seats minimise. Returns `None` once the time is up.
+    fn paranoid(
+        &mut self,
+        game: &Game,
+        hash: u64,
+        depth: u32,
+        mut alpha: f64,
+        mut beta: f64,
+    ) -> Option<(f64, Option<TurnMask>)> {
+        if self.expired() {
+            return None;
+        }
+        if depth == 0 || is_over(game) {
+            return Some((relative(game, self.root, &self.config.evaluate), None));
+        }
+
+        let mut best = None;
+        if let Some(entry) = self.scores.get(hash) {
+            best = entry.best;
+            if entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return Some((entry.score, entry.best)),
+                    Bound::Lower => alpha = alpha.max(entry.score),
+                    Bound::Upper => beta = beta.min(entry.score),
+                }
+                if alpha >= beta {
+                    return Some((entry.score, entry.best));
+                }
+            }
+        }
+
+        let children = self.children(game, hash, best);
+        if children.is_empty() {
+            return Some((relative(game, self.root, &self.config.evaluate), None));
+        }
+
+        let maximising = game.get_active_index() == self.root;
+        let window = (alpha, beta);
+        let mut best: Option<(f64, TurnMask)> = None;
+        for child in children {
+            let (score, _) = self.paranoid(&child.game, child.hash, depth - 1, alpha, beta)?;
+            let better = best.is_none_or(|(b, _)| if maximising { score > b } else { score < b });
+            if better {
+                best = Some((score, child.mask));
+            }
+            if maximising {
+                alpha = alpha.max(score);
+            } else {
+                beta = beta.min(score);
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let (score, mask) = best.expect("there is a child");
+        let bound = if score <= window.0 {
+            Bound::Upper
+        } else if score >= window.1 {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.scores.store(Entry {
+            hash,
+            depth,
+            score,
+            bound,
+            best: Some(mask),
+        });
+        Some((score, Some(mask)))
+    }
+
+    /// Max-n search of the scores of all seats, where every seat maximises its own. Returns
+    /// `None` once the time is up.
+    fn max_n(
+        &mut self,
+        game: &Game,
+        hash: u64,
+        depth: u32,
+    ) -> Option<(Vec<f64>, Option<TurnMask>)> {
+        if self.expired() {
+            return None;
+        }
+        let scores = || {
+            (0..game.players.len())
+                .map(|seat| self.config.evaluate.evaluate(game, seat))
+                .collect()
+        };
+        if depth == 0 || is_over(game) {
+            return Some((scores(), None));
+        }
+
+        let mut best = None;
+        if let Some(entry) = self.values.get(hash) {
+            if entry.depth >= depth {
+                return Some((entry.score.clone(), entry.best));
+            }
+            best = entry.best;
+        }
+
+        let children = self.children(game, hash, best);
+        if children.is_empty() {
+            return Some((scores(), None));
+        }
+
+        let seat = game.get_active_index();
+        let mut best: Option<(Vec<f64>, TurnMask)> = None;
+        for child in children {
+            let (values, _) = self.max_n(&child.game, child.hash, depth - 1)?;
+            if best.as_ref().is_none_or(|(b, _)| values[seat] > b[seat]) {
+                best = Some((values, child.mask));
+            }
+        }
+
+        let (values, mask) = best.expect("there is a child");
+        self.values.store(Entry {
+            hash,
+            depth,
+            score: values.clone(),
+            bound: Bound::Exact,
+            best: Some(mask),
+        });
+        Some((values, Some(mask)))
+    }
+
+    /// The best turn of a search to this depth
+    fn best(&mut self, game: &Game, hash: u64, depth: u32) -> Option<Option<TurnMask>> {
+        if game.players.len() <= 2 || self.config.multiplayer == Multiplayer::Paranoid {
+            let (_, mask) = self.paranoid(game, hash, depth, f64::NEG_INFINITY, f64::INFINITY)?;
+            Some(mask)
+        } else {
+            let (_, mask) = self.max_n(game, hash, depth)?;
+            Some(mask)
+        }
+    }
+}
+
+/// Depth limited search with iterative deepening.
+///
+/// Two seats are searched with alpha-beta, more seats with max-n or paranoid search. Without a
+/// time limit the agent is deterministic, which makes it a fixed opponent for benchmarks.
+pub struct MinimaxAgent {
+    pub config: MinimaxConfig,
+    /// The seat the paranoid scores were computed for
+    seat: Option<usize>,
+    scores: TranspositionTable<f64>,
+    values: TranspositionTable<Vec<f64>>,
+    /// The depth of the last completed search
+    depth: u32,
+}
+
+impl MinimaxAgent {
+    pub fn new(config: MinimaxConfig) -> Self {
+        MinimaxAgent {
+            seat: None,
+            scores: TranspositionTable::new(config.table_size),
+            values: TranspositionTable::new(config.table_size),
+            config,
+            depth: 0,
+        }
+    }
+
+    /// The depth of the last completed search
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+}
+
+impl Agent for MinimaxAgent {
+    fn get_action(&mut self, observation: &Observation) -> TurnMask {
+        let game = observation.game();
+        let seat = observation.seat();
+        if self.seat != Some(seat) {
+            self.scores.clear();
+            self.seat = Some(seat);
+        }
+
+        let hash = zobrist_hash(game);
+        let start = Instant::now();
+        let mut best = None;
+        self.depth = 0;
+        for depth in 1..=self.config.depth.max(1) {
+            // The first depth always completes, so that there is a turn to play
+            let deadline = self
+                .config
+                .time
+                .filter(|_| depth > 1)
+                .map(|time| start + time);
+            let mut search = Search {
+                config: &self.config,
+                root: seat,
+                deadline,
+                scores: &mut self.scores,
+                values: &mut self.values,
+            };
+            match search.best(game, hash, depth) {
+                Some(mask) => {
+                    best = mask.or(best);
+                    self.depth = depth;
+                }
+                None => break,
+            }
+        }
+
+        best.or_else(|| get_candidates(game).into_iter().next())
+            .unwrap_or(TurnMask::PrimaryOnly(Primary::Tax))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::game;
+
+    /// Plain minimax over the same turns as the search
+    fn minimax(search: &Search, game: &Game, hash: u64, depth: u32) -> f64 {
+        if depth == 0 || is_over(game) {
+            return relative(game, search.root, &search.config.evaluate);
+        }
+        let scores = search
+            .children(game, hash, None)
+            .into_iter()
+            .map(|child| minimax(search, &child.game, child.hash, depth - 1));
+        if game.get_active_index() == search.root {
+            scores.fold(f64::NEG_INFINITY, f64::max)
+        } else {
+            scores.fold(f64::INFINITY, f64::min)
+        }
+    }
+
+    #[test]
+    fn alpha_beta_matches_minimax() {
+        let config = MinimaxConfig {
+            branching: 5,
+            ..MinimaxConfig::default()
+        };
+        let (mut scores, mut values) =
+            (TranspositionTable::new(1 << 12), TranspositionTable::new(1));
+        let mut search = Search {
+            config: &config,
+            root: 0,
+            deadline: None,
+            scores: &mut scores,
+            values: &mut values,
+        };
+        let game = game(2);
+        let hash = zobrist_hash(&game);
+        let (score, _) = search
+            .paranoid(&game, hash, 3, f64::NEG_INFINITY, f64::INFINITY)
+            .unwrap();
+        assert_eq!(score, minimax(&search, &game, hash, 3));
+    }
+
+    #[test]
+    fn deterministic_opponent() {
+        for (seats, multiplayer) in [(2, Multiplayer::Paranoid), (3, Multiplayer::MaxN)] {
+            let config = MinimaxConfig {
+                depth: 2,
+                branching: 6,
+                multiplayer,
+                ..MinimaxConfig::default()
+            };
+            let mut game = game(seats);
+            for _ in 0..seats {
+                let seat = game.get_active_index();
+                let observation = Observation::new(&game, seat);
+                let mask = MinimaxAgent::new(config.clone()).get_action(&observation);
+                assert_eq!(
+                    mask,
+                    MinimaxAgent::new(config.clone()).get_action(&observation)
+                );
+                game = game.apply(&mask).unwrap();
+            }
+        }
+    }
+}
@@ -1,9 +1,168 @@
-use crate::{game_state::PlayerState, turn::turnmask::TurnMask};
+use std::rc::Rc;
 
+use rand::SeedableRng;
+
+use crate::{
+    game::{GameRng, board::Board, game::Game, player::PlayerState},
+    template::Position,
+    turn::{mask::TurnMask, predict::get_actions},
+};
+
+pub mod actions;
+pub mod alphazero;
+pub mod console;
+pub mod dial;
+pub mod dqn;
+pub mod encoder;
+pub mod evaluation;
 pub mod fcnn;
 pub mod human;
+pub mod mcts;
+pub mod minimax;
 pub mod random;
+pub mod runner;
+pub mod transposition;
+
+/// The highest power that can be committed on the combat dial
+pub const MAX_DIAL: u8 = 7;
+
+/// The game as seen from one seat
+#[derive(Debug, Clone, Copy)]
+pub struct Observation<'a> {
+    game: &'a Game,
+    seat: usize,
+}
+
+impl<'a> Observation<'a> {
+    pub fn new(game: &'a Game, seat: usize) -> Self {
+        Observation { game, seat }
+    }
+
+    pub fn seat(&self) -> usize {
+        self.seat
+    }
+
+    /// The full game, including its generator.
+    ///
+    /// The generator decides every random event still to come, which no seat could know at a
+    /// table. Search agents that simulate the game should do so on the public game.
+    pub fn game(&self) -> &'a Game {
+        self.game
+    }
+
+    /// The game as this seat may know it, with the generator drawn anew from the given one
+    pub fn public_game(&self, rng: &mut GameRng) -> Game {
+        Game {
+            rng: GameRng::from_rng(rng),
+            ..self.game.clone()
+        }
+    }
+
+    pub fn board(&self) -> &'a Board {
+        &self.game.board
+    }
+
+    pub fn player(&self) -> &'a Rc<PlayerState> {
+        &self.game.players[self.seat]
+    }
+
+    /// The public state of all other players with their seats
+    pub fn opponents(&self) -> impl Iterator<Item = (usize, &'a Rc<PlayerState>)> {
+        let seat = self.seat;
+        self.game
+            .players
+            .iter()
+            .enumerate()
+            .filter(move |(i, _)| *i != seat)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.game.get_active_index() == self.seat
+    }
+
+    /// All legal turns, if this seat is the active one
+    pub fn get_actions(&self) -> Vec<TurnMask> {
+        if self.is_active() {
+            get_actions(self.game)
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/// A fight between two players on a field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Combat {
+    pub position: Position,
+    pub attacker: usize,
+    pub defender: usize,
+    /// The number of combat cards this seat may add to the dial
+    pub max_cards: u8,
+}
+
+/// The power and combat cards committed to a fight
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CombatDial {
+    pub power: u8,
+    pub cards: u8,
+}
+
+/// The options of an encounter card
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Encounter {
+    pub position: Position,
+    pub options: u8,
+}
 
 pub trait Agent {
-    fn get_action(&mut self, state: &PlayerState) -> TurnMask;
+    /// Chooses the turn of the active seat
+    fn get_action(&mut self, observation: &Observation) -> TurnMask;
+
+    /// Chooses the power and cards committed to a fight
+    fn combat_dial(&mut self, observation: &Observation, combat: &Combat) -> CombatDial {
+        let _ = combat;
+        CombatDial {
+            power: observation.player().military.power.min(MAX_DIAL),
+            cards: 0,
+        }
+    }
+
+    /// Chooses the index of an option of the encounter card
+    fn encounter_choice(&mut self, observation: &Observation, encounter: &Encounter) -> u8 {
+        let _ = (observation, encounter);
+        0
+    }
+
+    /// Chooses the index of one of the drawn factory cards
+    fn factory_card(&mut self, observation: &Observation, cards: u8) -> u8 {
+        let _ = (observation, cards);
+        0
+    }
+
+    fn game_start(&mut self, observation: &Observation) {
+        let _ = observation;
+    }
+
+    fn game_end(&mut self, observation: &Observation) {
+        let _ = observation;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{game::zobrist::zobrist_hash, test_support::game};
+
+    #[test]
+    fn public_game_hides_the_generator() {
+        let game = game(2);
+        let observation = Observation::new(&game, 1);
+        let public = observation.public_game(&mut GameRng::seed_from_u64(1));
+        assert_eq!(zobrist_hash(&public), zobrist_hash(&game));
+        assert_ne!(public.rng, game.rng);
+
+        // The same draw gives the same game
+        let again = observation.public_game(&mut GameRng::seed_from_u64(1));
+        assert_eq!(again.rng, public.rng);
+    }
 }
@@ -0,0 +1,380 @@
+use std::{error::Error, fmt};
+
+use rand::{Rng, SeedableRng};
+
+use crate::{
+    agent::{Agent, Observation},
+    game::{
+        GameRng,
+        game::{Game, PlayerInfo, SetupError},
+        record::{GameRecord, SeatRecord, TurnRecord},
+    },
+    template::BoardTemplate,
+    turn::{check::IllegalMove, mask::TurnMask, predict::sample_action},
+};
+
+/// How the runner reacts to an illegal action of an agent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IllegalActionPolicy {
+    /// The seat drops out and all its further turns are skipped
+    Forfeit,
+    /// The agent is asked again up to this many times before it forfeits
+    Retry(u32),
+    /// A random legal action is played instead
+    Random,
+}
+
+/// Why a turn was not played by the agent itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Violation {
+    pub turn: u32,
+    pub seat: usize,
+    pub reason: IllegalMove,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameResult {
+    /// The final coins of every seat
+    pub scores: Vec<u32>,
+    /// The stars of every seat after each turn
+    pub stars: Vec<Vec<u8>>,
+    pub turns: u32,
+    /// The seat that ended the game with its sixth star
+    pub winner: Option<usize>,
+    pub forfeited: Vec<bool>,
+    pub violations: Vec<Violation>,
+    /// The setup and all turns, to replay the game
+    pub record: GameRecord,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunnerError {
+    /// The seats cannot start on the board
+    Setup(SetupError),
+}
+
+impl fmt::Display for RunnerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunnerError::Setup(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Error for RunnerError {}
+
+/// Plays full games with one agent per seat
+pub struct GameRunner<'a, const F: usize, const R: usize, const P: usize> {
+    pub board: &'a BoardTemplate<F, R, P>,
+    pub seats: Vec<(PlayerInfo<'a>, Box<dyn Agent + 'a>)>,
+    pub max_turns: u32,
+    pub policy: IllegalActionPolicy,
+    rng: GameRng,
+}
+
+impl<'a, const F: usize, const R: usize, const P: usize> GameRunner<'a, F, R, P> {
+    pub fn new(
+        board: &'a BoardTemplate<F, R, P>,
+        seats: Vec<(PlayerInfo<'a>, Box<dyn Agent + 'a>)>,
+        max_turns: u32,
+        policy: IllegalActionPolicy,
+        rng: &mut GameRng,
+    ) -> Self {
+        GameRunner {
+            board,
+            seats,
+            max_turns,
+            policy,
+            rng: GameRng::from_rng(rng),
+        }
+    }
+
+    /// Plays a game until a player has six stars or the turn cap is reached
+    pub fn run(&mut self) -> Result<GameResult, RunnerError> {
+        let mut record = GameRecord {
+            board: self.board.name.to_string(),
+            seed: self.rng.random(),
+            seats: self
+                .seats
+                .iter()
+                .map(|(info, _)| seat_record(info))
+                .collect(),
+            turns: Vec::new(),
+        };
+        let infos: Vec<&PlayerInfo> = self.seats.iter().map(|(info, _)| info).collect();
+        let mut game = Game::new(self.board, &infos, &mut GameRng::seed_from_u64(record.seed))
+            .map_err(RunnerError::Setup)?;
+
+        for (seat, (_, agent)) in self.seats.iter_mut().enumerate() {
+            agent.game_start(&Observation::new(&game, seat));
+        }
+
+        let mut forfeited = vec![false; self.seats.len()];
+        let mut violations = Vec::new();
+        let mut stars = Vec::new();
+        let mut winner = None;
+        while game.turn < self.max_turns && winner.is_none() && forfeited.contains(&false) {
+            let seat = game.get_active_index();
+            if forfeited[seat] {
+                game.turn += 1;
+                record.turns.push(TurnRecord {
+                    mask: None,
+                    decisions: Vec::new(),
+                });
+                stars.push(game.players.iter().map(|p| p.stars()).collect());
+                continue;
+            }
+
+            let mask = match self.play_turn(&game, seat, &mut violations) {
+                Some((mask, next)) => {
+                    game = next;
+                    Some(mask)
+                }
+                None => {
+                    forfeited[seat] = true;
+                    game.turn += 1;
+                    None
+                }
+            };
+            record.turns.push(TurnRecord {
+                mask,
+                decisions: Vec::new(),
+            });
+            stars.push(game.players.iter().map(|p| p.stars()).collect());
+            if game.players[seat].has_won() {
+                winner = Some(seat);
+            }
+        }
+
+        for (seat, (_, agent)) in self.seats.iter_mut().enumerate() {
+            agent.game_end(&Observation::new(&game, seat));
+        }
+
+        Ok(GameResult {
+            scores: game.players.iter().map(|p| p.total_coins()).collect(),
+            stars,
+            turns: game.turn,
+            winner,
+            forfeited,
+            violations,
+            record,
+        })
+    }
+
+    /// The played turn and the game after it, or none if the seat forfeits
+    fn play_turn(
+        &mut self,
+        game: &Game,
+        seat: usize,
+        violations: &mut Vec<Violation>,
+    ) -> Option<(TurnMask, Game)> {
+        let attempts = match self.policy {
+            IllegalActionPolicy::Retry(retries) => retries + 1,
+            _ => 1,
+        };
+        let agent = &mut self.seats[seat].1;
+        for _ in 0..attempts {
+            let action = agent.get_action(&Observation::new(game, seat));
+            match game.apply(&action) {
+                Ok(next) => return Some((action, next)),
+                Err(reason) => violations.push(Violation {
+                    turn: game.turn,
+                    seat,
+                    reason,
+                }),
+            }
+        }
+
+        match self.policy {
+            IllegalActionPolicy::Random => {
+                let action = sample_action(game, &mut self.rng);
+                game.apply(&action).ok().map(|next| (action, next))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn seat_record(info: &PlayerInfo) -> SeatRecord {
+    let player = &info.template.player;
+    SeatRecord {
+        name: player.name.to_string(),
+        faction: info.template.faction.name.to_string(),
+        player_mat: info.template.player_mat.name.to_string(),
+        start_location_index: info.start_location_index,
+        bonus_starting_coins: player.bonus_starting_coins,
+        bonus_starting_power: player.bonus_starting_power,
+        bonus_starting_popularity: player.bonus_starting_popularity,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        agent::random::RandomAgent,
+        game::{board::ResourceField, production::Worker},
+        template::{
+            Position,
+            board::NORMAL,
+            faction::{POLANIA, RUSVIET},
+            player_mat::{AGRICULTURAL, INDUSTRIAL},
+        },
+        test_support::player,
+        turn::{
+            check::MoveError,
+            mask::{Move, Movement, Primary, TurnMask, UnitMovement, UnitPosition},
+        },
+    };
+
+    /// Always moves the first worker of Rusviet onto its home base
+    struct HomeAgent;
+
+    impl Agent for HomeAgent {
+        fn get_action(&mut self, _: &Observation) -> TurnMask {
+            let home = Position::new(3, 1);
+            TurnMask::PrimaryOnly(Primary::Move(Move::Move1(UnitMovement::Worker(
+                Worker::First,
+                Movement::Single((home, ResourceField::empty())),
+            ))))
+        }
+    }
+
+    fn runner<'a>(
+        first: Box<dyn Agent + 'a>,
+        max_turns: u32,
+        policy: IllegalActionPolicy,
+        rng: &mut GameRng,
+    ) -> GameRunner<'a, 47, 30, 7> {
+        let seats: Vec<(PlayerInfo, Box<dyn Agent>)> = vec![
+            (player(RUSVIET, INDUSTRIAL, 0), first),
+            (
+                player(POLANIA, AGRICULTURAL, 1),
+                Box::new(RandomAgent::new(rng)),
+            ),
+        ];
+        GameRunner::new(&NORMAL, seats, max_turns, policy, rng)
+    }
+
+    fn run(seed: u64) -> GameResult {
+        let mut rng = GameRng::seed_from_u64(seed);
+        let first = Box::new(RandomAgent::new(&mut rng));
+        runner(first, 60, IllegalActionPolicy::Forfeit, &mut rng)
+            .run()
+            .unwrap()
+    }
+
+    #[test]
+    fn random_agents_play_until_the_turn_cap() {
+        let result = run(7);
+        assert_eq!(result.turns, 60);
+        assert_eq!(result.stars.len(), 60);
+        assert_eq!(result.scores.len(), 2);
+        assert_eq!(result.forfeited, vec![false, false]);
+        assert!(result.violations.is_empty());
+    }
+
+    #[test]
+    fn same_seed_same_game() {
+        assert_eq!(run(7), run(7));
+    }
+
+    #[test]
+    fn illegal_actions_follow_the_policy() {
+        let mut rng = GameRng::seed_from_u64(0);
+        let violation = |turn| Violation {
+            turn,
+            seat: 0,
+            reason: IllegalMove::Movement {
+                index: 0,
+                unit: UnitPosition::Worker(Worker::First),
+                reason: MoveError::HomeBase(Position::new(3, 1)),
+            },
+        };
+
+        let forfeit = runner(
+            Box::new(HomeAgent),
+            10,
+            IllegalActionPolicy::Forfeit,
+            &mut rng,
+        )
+        .run()
+        .unwrap();
+        assert_eq!(forfeit.turns, 10);
+        assert_eq!(forfeit.forfeited, vec![true, false]);
+        // Skipped turns of the forfeited seat still have their stars
+        assert_eq!(forfeit.stars.len(), forfeit.record.turns.len());
+        assert_eq!(forfeit.violations, vec![violation(0)]);
+
+        let retry = runner(
+            Box::new(HomeAgent),
+            10,
+            IllegalActionPolicy::Retry(2),
+            &mut rng,
+        )
+        .run()
+        .unwrap();
+        assert_eq!(retry.forfeited, vec![true, false]);
+        assert_eq!(retry.violations, vec![violation(0); 3]);
+
+        let random = runner(
+            Box::new(HomeAgent),
+            10,
+            IllegalActionPolicy::Random,
+            &mut rng,
+        )
+        .run()
+        .unwrap();
+        assert_eq!(random.forfeited, vec![false, false]);
+        let turns: Vec<(u32, usize)> = random
+            .violations
+            .iter()
+            .map(|violation| (violation.turn, violation.seat))
+            .collect();
+        assert_eq!(turns, vec![(0, 0), (2, 0), (4, 0), (6, 0), (8, 0)]);
+    }
+
+    #[test]
+    fn invalid_start_locations_are_rejected() {
+        let mut rng = GameRng::seed_from_u64(0);
+        let seats: Vec<(PlayerInfo, Box<dyn Agent>)> = vec![(
+            player(RUSVIET, INDUSTRIAL, NORMAL.starting_locations.len()),
+            Box::new(RandomAgent::new(&mut rng)),
+        )];
+        let mut runner =
+            GameRunner::new(&NORMAL, seats, 10, IllegalActionPolicy::Forfeit, &mut rng);
+        assert_eq!(
+            runner.run().unwrap_err(),
+            RunnerError::Setup(SetupError::InvalidStartLocation { seat: 0 })
+        );
+
+        let seats: Vec<(PlayerInfo, Box<dyn Agent>)> = vec![
+            (
+                player(RUSVIET, INDUSTRIAL, 0),
+                Box::new(RandomAgent::new(&mut rng)),
+            ),
+            (
+                player(POLANIA, AGRICULTURAL, 0),
+                Box::new(RandomAgent::new(&mut rng)),
+            ),
+        ];
+        let mut runner =
+            GameRunner::new(&NORMAL, seats, 10, IllegalActionPolicy::Forfeit, &mut rng);
+        assert_eq!(
+            runner.run().unwrap_err(),
+            RunnerError::Setup(SetupError::DuplicateStartLocation { seat: 1 })
+        );
+    }
+
+    #[test]
+    fn record_replays() {
+        let result = run(3);
+        let record: GameRecord = result.record.to_string().parse().unwrap();
+        assert_eq!(record, result.record);
+
+        let game = record.replay().unwrap();
+        assert_eq!(game.turn, result.turns);
+        let scores: Vec<u32> = game.players.iter().map(|p| p.total_coins()).collect();
+        assert_eq!(scores, result.scores);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::turn::mask::TurnMask;
+
+/// How the stored score relates to the true score of the position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    /// The true score is at least the stored one
+    Lower,
+    /// The true score is at most the stored one
+    Upper,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry<S> {
+    /// The full Zobrist hash, to tell positions in the same slot apart
+    pub hash: u64,
+    pub depth: u32,
+    pub score: S,
+    pub bound: Bound,
+    pub best: Option<TurnMask>,
+}
+
+/// A fixed size table of search results keyed by the Zobrist hash of a position.
+///
+/// The score is generic, so that max-n searches can store one value per seat.
+#[derive(Debug, Clone)]
+pub struct TranspositionTable<S> {
+    slots: Vec<Option<Entry<S>>>,
+    hits: u64,
+    misses: u64,
+}
+
+impl<S> TranspositionTable<S> {
+    /// A table with the next power of two of slots
+    pub fn new(capacity: usize) -> Self {
+        let slots = capacity.max(1).next_power_of_two();
+        TranspositionTable {
+            slots: (0..slots).map(|_| None).collect(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    fn index(&self, hash: u64) -> usize {
+        hash as usize & (self.slots.len() - 1)
+    }
+
+    /// The entry of this position, if it was not replaced
+    pub fn get(&mut self, hash: u64) -> Option<&Entry<S>> {
+        let index = self.index(hash);
+        match &self.slots[index] {
+            Some(entry) if entry.hash == hash => {
+                self.hits += 1;
+                self.slots[index].as_ref()
+            }
+            _ => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Stores the entry unless its slot holds a deeper search of the same position
+    pub fn store(&mut self, entry: Entry<S>) {
+        let index = self.index(entry.hash);
+        let slot = &mut self.slots[index];
+        let keep = slot
+            .as_ref()
+            .is_some_and(|old| old.hash == entry.hash && old.depth > entry.depth);
+        if !keep {
+            *slot = Some(entry);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.iter_mut().for_each(|slot| *slot = None);
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// The number of filled slots
+    pub fn len(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The share of lookups that found their position
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(hash: u64, depth: u32, score: i32) -> Entry<i32> {
+        Entry {
+            hash,
+            depth,
+            score,
+            bound: Bound::Exact,
+            best: None,
+        }
+    }
+
+    #[test]
+    fn store_and_get() {
+        let mut table = TranspositionTable::new(6);
+        assert_eq!(table.capacity(), 8);
+        assert!(table.is_empty());
+
+        table.store(entry(3, 2, 10));
+        assert_eq!(table.get(3), Some(&entry(3, 2, 10)));
+        assert_eq!(table.get(4), None);
+        assert_eq!(table.len(), 1);
+
+        table.clear();
+        assert!(table.is_empty());
+        assert_eq!(table.get(3), None);
+    }
+
+    #[test]
+    fn other_positions_replace_the_slot() {
+        let mut table = TranspositionTable::new(8);
+        table.store(entry(3, 5, 10));
+        // 11 shares the slot of 3 and wins regardless of its depth
+        table.store(entry(11, 1, 20));
+        assert_eq!(table.get(3), None);
+        assert_eq!(table.get(11), Some(&entry(11, 1, 20)));
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn deeper_searches_of_a_position_are_kept() {
+        let mut table = TranspositionTable::new(8);
+        table.store(entry(3, 4, 10));
+        table.store(entry(3, 2, 20));
+        assert_eq!(table.get(3), Some(&entry(3, 4, 10)));
+
+        table.store(entry(3, 4, 30));
+        assert_eq!(table.get(3), Some(&entry(3, 4, 30)));
+        table.store(entry(3, 6, 40));
+        assert_eq!(table.get(3), Some(&entry(3, 6, 40)));
+    }
+
+    #[test]
+    fn hit_rate() {
+        let mut table = TranspositionTable::new(8);
+        assert_eq!(table.hit_rate(), 0.0);
+
+        table.store(entry(3, 1, 10));
+        table.get(3);
+        table.get(3);
+        table.get(4);
+        table.get(11);
+        assert_eq!(table.hit_rate(), 0.5);
+
+        table.clear();
+        assert_eq!(table.hit_rate(), 0.0);
+    }
+}
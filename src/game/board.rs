@@ -10,6 +10,14 @@ use crate::{
 };
 
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        into = "crate::game::snapshot::BoardData",
+        from = "crate::game::snapshot::BoardData"
+    )
+)]
 pub struct Board {
     pub fields: HashMap<Position, Rc<Field>>,
     pub rivers: Vec<(Rc<Field>, Rc<Field>)>,
@@ -57,32 +65,36 @@ impl Board {
 
         let mut rivers = Vec::with_capacity(R);
         for (p1, p2) in template.rivers.iter() {
-            if let Some(f1) = fields.get(p1) {
-                if let Some(f2) = fields.get(p2) {
-                    rivers.push((f1.clone(), f2.clone()));
-                }
+            if let (Some(f1), Some(f2)) = (fields.get(p1), fields.get(p2)) {
+                rivers.push((f1.clone(), f2.clone()));
             }
         }
 
-        Board {
-            fields: fields,
-            rivers: rivers,
-        }
+        Board { fields, rivers }
     }
 
     pub fn get_field(&self, position: &Position) -> Option<&Rc<Field>> {
         self.fields.get(position)
     }
 
+    /// All fields ordered by their position
+    pub fn get_fields(&self) -> Vec<&Rc<Field>> {
+        let mut fields: Vec<&Rc<Field>> = self.fields.values().collect();
+        fields.sort_by_key(|f| f.position);
+        fields
+    }
+
     pub fn is_river(&self, from: &Field, to: &Field) -> bool {
-        // TODO maybe need to pass &Rc<Field> here
-        self.rivers
-            .iter()
-            .any(|(f1, f2)| (**f1 == *from && **f2 == *to) || (**f2 == *from && **f1 == *to))
+        // Fields are replaced when their resources change, so compare by position
+        self.rivers.iter().any(|(f1, f2)| {
+            (f1.position == from.position && f2.position == to.position)
+                || (f2.position == from.position && f1.position == to.position)
+        })
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Field {
     pub encounter_token: bool,
     pub tunnelable: bool,
@@ -92,6 +104,7 @@ pub struct Field {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ResourceField {
     pub wood: u32,
     pub metal: u32,
@@ -116,6 +129,30 @@ impl ResourceField {
             .saturating_add(self.food)
     }
 
+    pub fn single(resource: &Resource, amount: u32) -> Self {
+        let mut field = ResourceField::empty();
+        field.add_resource(resource, amount);
+        field
+    }
+
+    pub fn get(&self, resource: &Resource) -> u32 {
+        match resource {
+            Resource::Wood => self.wood,
+            Resource::Metal => self.metal,
+            Resource::Oil => self.oil,
+            Resource::Food => self.food,
+        }
+    }
+
+    pub fn add_resource(&mut self, resource: &Resource, amount: u32) {
+        match resource {
+            Resource::Wood => self.wood = self.wood.saturating_add(amount),
+            Resource::Metal => self.metal = self.metal.saturating_add(amount),
+            Resource::Oil => self.oil = self.oil.saturating_add(amount),
+            Resource::Food => self.food = self.food.saturating_add(amount),
+        }
+    }
+
     pub fn has(&self, resource: &Resource, amount: u32) -> bool {
         match resource {
             Resource::Wood => self.wood >= amount,
@@ -147,3 +184,16 @@ impl Add for ResourceField {
         }
     }
 }
+
+impl Sub for ResourceField {
+    type Output = ResourceField;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        ResourceField {
+            wood: self.wood.saturating_sub(rhs.wood),
+            metal: self.metal.saturating_sub(rhs.metal),
+            oil: self.oil.saturating_sub(rhs.oil),
+            food: self.food.saturating_sub(rhs.food),
+        }
+    }
+}
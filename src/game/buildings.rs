@@ -10,13 +10,25 @@ pub enum Building {
     Mill,
 }
 
+pub const BUILDINGS: [Building; 4] = [
+    Building::Armory,
+    Building::Monument,
+    Building::Tunnel,
+    Building::Mill,
+];
+
 pub type BuildingEntity = Rc<Field>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BuildingsState {
+    #[cfg_attr(feature = "serde", serde(with = "crate::game::snapshot::optional_field_ref"))]
     pub tunnel: Option<BuildingEntity>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::game::snapshot::optional_field_ref"))]
     pub mill: Option<BuildingEntity>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::game::snapshot::optional_field_ref"))]
     pub armory: Option<BuildingEntity>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::game::snapshot::optional_field_ref"))]
     pub monument: Option<BuildingEntity>,
 
     pub star: bool,
@@ -79,3 +91,9 @@ impl BuildingsState {
         }
     }
 }
+
+impl Default for BuildingsState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
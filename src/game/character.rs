@@ -3,6 +3,8 @@ use std::rc::Rc;
 use crate::game::board::Field;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CharacterEntity {
+    #[cfg_attr(feature = "serde", serde(with = "crate::game::snapshot::field_ref"))]
     pub location: Rc<Field>,
 }
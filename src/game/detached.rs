@@ -0,0 +1,234 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    game::{
+        GameRng, Tile,
+        board::{Board, Field},
+        buildings::BuildingsState,
+        character::CharacterEntity,
+        game::Game,
+        mechs::MechsState,
+        military::MilitaryState,
+        player::PlayerState,
+        popularity::PopularityState,
+        production::ProductionState,
+        recruits::RecruitsState,
+        upgrades::UpgradesState,
+    },
+    template::{CombatPower, FactionAbility, MobilityPower, Position, SecondaryAction},
+};
+
+/// Where the units and buildings of a player stand
+#[derive(Debug, Clone)]
+struct Units {
+    character: Position,
+    workers: [Option<Position>; 8],
+    mechs: [Option<Position>; 4],
+    /// Tunnel, mill, armory and monument
+    buildings: [Option<Position>; 4],
+}
+
+/// A player whose units and buildings are kept as positions
+#[derive(Debug, Clone)]
+struct DetachedPlayer {
+    move_secondary: SecondaryAction,
+    trade_secondary: SecondaryAction,
+    produce_secondary: SecondaryAction,
+    bolster_secondary: SecondaryAction,
+    riverwalk: [Tile; 2],
+    mobility_power: MobilityPower,
+    combat_power: CombatPower,
+    faction_ability: FactionAbility,
+    upgrades: UpgradesState,
+    recruits: RecruitsState,
+    military: MilitaryState,
+    popularity: PopularityState,
+    mechs_star: bool,
+    buildings_star: bool,
+    deployed_workers: usize,
+    production_star: bool,
+    coins: u32,
+    cards: u8,
+    combat_wins: u8,
+    units: Units,
+}
+
+/// A copy of a game without any `Rc`, so that it can be sent to another thread. Units and
+/// buildings are kept as the positions of their fields and linked to a new board by `into_game`.
+#[derive(Debug, Clone)]
+pub struct DetachedGame {
+    fields: Vec<Field>,
+    rivers: Vec<(Position, Position)>,
+    players: Vec<DetachedPlayer>,
+    turn: u32,
+    rng: GameRng,
+}
+
+impl DetachedGame {
+    pub fn new(game: &Game) -> Self {
+        let position = |field: &Option<Rc<Field>>| field.as_ref().map(|f| f.position);
+        let players = game
+            .players
+            .iter()
+            .map(|player| {
+                let buildings = &player.buildings;
+                DetachedPlayer {
+                    move_secondary: player.move_secondary,
+                    trade_secondary: player.trade_secondary,
+                    produce_secondary: player.produce_secondary,
+                    bolster_secondary: player.bolster_secondary,
+                    riverwalk: player.riverwalk,
+                    mobility_power: player.mobility_power,
+                    combat_power: player.combat_power,
+                    faction_ability: player.faction_ability,
+                    upgrades: player.upgrades,
+                    recruits: player.recruits,
+                    military: player.military,
+                    popularity: player.popularity,
+                    mechs_star: player.mechs.star,
+                    buildings_star: buildings.star,
+                    deployed_workers: player.production.deployed_workers,
+                    production_star: player.production.star,
+                    coins: player.coins,
+                    cards: player.cards,
+                    combat_wins: player.combat_wins,
+                    units: Units {
+                        character: player.character.location.position,
+                        workers: player.production.workers.each_ref().map(position),
+                        mechs: player.mechs.mechs.each_ref().map(position),
+                        buildings: [
+                            &buildings.tunnel,
+                            &buildings.mill,
+                            &buildings.armory,
+                            &buildings.monument,
+                        ]
+                        .map(position),
+                    },
+                }
+            })
+            .collect();
+
+        DetachedGame {
+            fields: game.board.get_fields().into_iter().map(|f| **f).collect(),
+            rivers: game
+                .board
+                .rivers
+                .iter()
+                .map(|(f1, f2)| (f1.position, f2.position))
+                .collect(),
+            players,
+            turn: game.turn,
+            rng: game.rng.clone(),
+        }
+    }
+
+    /// The game with a new board, whose fields are shared by its units and buildings only
+    pub fn into_game(self) -> Game {
+        let fields: HashMap<Position, Rc<Field>> = self
+            .fields
+            .into_iter()
+            .map(|field| (field.position, Rc::new(field)))
+            .collect();
+        let field = |position: &Position| fields[position].clone();
+        let optional = |position: &Option<Position>| position.as_ref().map(field);
+        let players = self
+            .players
+            .into_iter()
+            .map(|player| {
+                let units = player.units;
+                let [tunnel, mill, armory, monument] = units.buildings.each_ref().map(optional);
+                Rc::new(PlayerState {
+                    move_secondary: player.move_secondary,
+                    trade_secondary: player.trade_secondary,
+                    produce_secondary: player.produce_secondary,
+                    bolster_secondary: player.bolster_secondary,
+                    riverwalk: player.riverwalk,
+                    mobility_power: player.mobility_power,
+                    combat_power: player.combat_power,
+                    faction_ability: player.faction_ability,
+                    upgrades: player.upgrades,
+                    mechs: MechsState {
+                        mechs: units.mechs.each_ref().map(optional),
+                        star: player.mechs_star,
+                    },
+                    buildings: BuildingsState {
+                        tunnel,
+                        mill,
+                        armory,
+                        monument,
+                        star: player.buildings_star,
+                    },
+                    recruits: player.recruits,
+                    military: player.military,
+                    popularity: player.popularity,
+                    production: ProductionState {
+                        workers: units.workers.each_ref().map(optional),
+                        deployed_workers: player.deployed_workers,
+                        star: player.production_star,
+                    },
+                    character: CharacterEntity {
+                        location: field(&units.character),
+                    },
+                    coins: player.coins,
+                    cards: player.cards,
+                    combat_wins: player.combat_wins,
+                })
+            })
+            .collect();
+        let rivers = self
+            .rivers
+            .iter()
+            .map(|(p1, p2)| (field(p1), field(p2)))
+            .collect();
+
+        Game {
+            board: Board { fields, rivers },
+            players,
+            turn: self.turn,
+            rng: self.rng,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{game::zobrist::zobrist_hash, test_support::game, turn::predict::sample_action};
+
+    #[test]
+    fn rebuilds_the_game_on_another_thread() {
+        let mut game = game(3);
+        let mut rng = GameRng::seed_from_u64(5);
+        for _ in 0..20 {
+            game = game.apply(&sample_action(&game, &mut rng)).unwrap();
+        }
+
+        let detached = DetachedGame::new(&game);
+        let rebuilt = std::thread::spawn(move || zobrist_hash(&detached.into_game()))
+            .join()
+            .unwrap();
+        assert_eq!(rebuilt, zobrist_hash(&game));
+
+        let rebuilt = DetachedGame::new(&game).into_game();
+        assert_eq!(
+            format!("{:?}", rebuilt.players),
+            format!("{:?}", game.players)
+        );
+        assert_eq!(
+            format!("{:?}", rebuilt.board.get_fields()),
+            format!("{:?}", game.board.get_fields())
+        );
+        assert_eq!((rebuilt.turn, &rebuilt.rng), (game.turn, &game.rng));
+
+        // Every unit points at the field of the new board
+        for player in &rebuilt.players {
+            let location = &player.character.location;
+            assert!(Rc::ptr_eq(
+                location,
+                &rebuilt.board.fields[&location.position]
+            ));
+        }
+    }
+}
@@ -1,92 +1,196 @@
-use std::rc::Rc;
+use std::{error::Error, fmt, rc::Rc};
+
+use rand::SeedableRng;
 
 use crate::{
     game::{
+        GameRng,
         board::{Board, Field},
         buildings::Building,
         player::{PlayerState, PlayerTemplate},
     },
-    template::BoardTemplate,
+    template::{BoardTemplate, Position},
+    turn::{
+        check::{IllegalMove, check_primary, check_secondary},
+        execute::{execute_primary, execute_secondary},
+        mask::TurnMask,
+    },
 };
 
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "crate::game::snapshot::GameData")
+)]
 pub struct Game {
     pub board: Board,
     pub players: Vec<Rc<PlayerState>>,
     pub turn: u32,
+    /// Draws every random event of the game, so that it can be replayed from the setup
+    pub rng: GameRng,
+}
+
+/// Why a game could not be set up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetupError {
+    /// The seat's start location does not exist on the board
+    InvalidStartLocation { seat: usize },
+    /// The seat's start location is already taken by an earlier seat
+    DuplicateStartLocation { seat: usize },
 }
 
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetupError::InvalidStartLocation { seat } => {
+                write!(f, "Seat {seat} has no valid start location")
+            }
+            SetupError::DuplicateStartLocation { seat } => {
+                write!(f, "Seat {seat} starts on the location of an earlier seat")
+            }
+        }
+    }
+}
+
+impl Error for SetupError {}
+
 impl Game {
-    pub fn new<const F: usize, const R: usize, const P: usize, const C: usize>(
+    /// Places every seat on its start location, which must be on the board and not shared
+    pub fn new<const F: usize, const R: usize, const P: usize>(
         board_template: &BoardTemplate<F, R, P>,
-        player_templates: [&PlayerInfo; C],
-    ) -> Self {
+        player_templates: &[&PlayerInfo],
+        rng: &mut GameRng,
+    ) -> Result<Self, SetupError> {
         let board = Board::from_template(board_template);
 
-        let mut starting_locations = Vec::with_capacity(P);
-        for loc in board_template.starting_locations.iter() {
-            let home = board.get_field(&loc.position);
-            let start1 = board.get_field(&loc.start1);
-            let start2 = board.get_field(&loc.start2);
-
-            if let Some(h) = home {
-                if let Some(s1) = start1 {
-                    if let Some(s2) = start2 {
-                        starting_locations.push((h, s1, s2));
-                    }
-                }
+        let mut players = Vec::with_capacity(player_templates.len());
+        for (seat, info) in player_templates.iter().enumerate() {
+            let index = info.start_location_index;
+            let fields = board_template
+                .starting_locations
+                .get(index)
+                .map(|loc| [loc.position, loc.start1, loc.start2].map(|p| board.get_field(&p)));
+            let Some([Some(h), Some(s1), Some(s2)]) = fields else {
+                return Err(SetupError::InvalidStartLocation { seat });
+            };
+            if player_templates[..seat]
+                .iter()
+                .any(|other| other.start_location_index == index)
+            {
+                return Err(SetupError::DuplicateStartLocation { seat });
             }
-        }
 
-        let mut players = Vec::with_capacity(C);
-        for info in player_templates {
-            let loc = starting_locations.get(info.start_location_index);
-            if let Some((h, s1, s2)) = loc {
-                let new_player = PlayerState::new(&info.template, h, s1, s2);
-                players.push(Rc::new(new_player));
-            }
+            let new_player = PlayerState::new(&info.template, h, s1, s2);
+            players.push(Rc::new(new_player));
         }
 
-        Game {
-            board: board,
-            players: players,
+        Ok(Game {
+            board,
+            players,
             turn: 0,
+            rng: GameRng::from_rng(rng),
+        })
+    }
+
+    /// Validates the turn of the active player and returns the game after it was played
+    pub fn apply(&self, mask: &TurnMask) -> Result<Game, IllegalMove> {
+        let (primary, secondary) = match mask {
+            TurnMask::PrimaryOnly(primary) => (primary, None),
+            TurnMask::PrimaryAndSecondary(primary, secondary) => (primary, Some(secondary)),
+        };
+
+        if let Some(reason) = check_primary(self, primary) {
+            return Err(reason);
+        }
+        let mut game = self.clone();
+        execute_primary(&mut game, primary);
+
+        if let Some(secondary) = secondary {
+            if let Some(reason) = check_secondary(&game, primary, secondary) {
+                return Err(reason);
+            }
+            execute_secondary(&mut game, secondary);
         }
+
+        game.turn += 1;
+        Ok(game)
     }
 
     pub fn get_round(&self) -> u32 {
         self.turn / self.players.len() as u32
     }
 
+    pub fn get_active_index(&self) -> usize {
+        self.turn as usize % self.players.len()
+    }
+
     pub fn get_active_player(&self) -> &Rc<PlayerState> {
-        let index = self.turn as usize % self.players.len();
         self.players
-            .get(index)
+            .get(self.get_active_index())
             .expect("Vector index should never be out of scope due to modulus")
     }
 
+    pub fn get_active_player_mut(&mut self) -> &mut PlayerState {
+        let index = self.get_active_index();
+        Rc::make_mut(&mut self.players[index])
+    }
+
+    /// Replaces the field at the position and points all units, buildings and rivers to the new field
+    pub fn update_field<F: FnOnce(&mut Field)>(
+        &mut self,
+        position: &Position,
+        update: F,
+    ) -> Option<Rc<Field>> {
+        let old = self.board.get_field(position)?.clone();
+        let mut field = *old;
+        update(&mut field);
+        let new = Rc::new(field);
+
+        self.board.fields.insert(*position, new.clone());
+        for (f1, f2) in self.board.rivers.iter_mut() {
+            if Rc::ptr_eq(f1, &old) {
+                *f1 = new.clone();
+            }
+            if Rc::ptr_eq(f2, &old) {
+                *f2 = new.clone();
+            }
+        }
+        for player in self.players.iter_mut() {
+            if player.references(&old) {
+                Rc::make_mut(player).relocate(&old, &new);
+            }
+        }
+        Some(new)
+    }
+
+    pub fn has_building(&self, field: &Rc<Field>) -> bool {
+        self.get_building_owner(field).is_some()
+    }
+
     pub fn get_player_control(&self, field: &Rc<Field>) -> Option<&Rc<PlayerState>> {
         // Check character, mechs and workers
         for player in self.players.iter() {
             if Rc::ptr_eq(&player.character.location, field) {
                 return Some(player);
             }
-            for ele in player.mechs.mechs.iter() {
-                if let Some(f) = ele {
-                    if Rc::ptr_eq(f, field) {
-                        return Some(player);
-                    }
+            for f in player.mechs.mechs.iter().flatten() {
+                if Rc::ptr_eq(f, field) {
+                    return Some(player);
                 }
             }
-            for ele in player.production.workers.iter() {
-                if let Some(f) = ele {
-                    if Rc::ptr_eq(&f, field) {
-                        return Some(player);
-                    }
+            for f in player.production.workers.iter().flatten() {
+                if Rc::ptr_eq(f, field) {
+                    return Some(player);
                 }
             }
         }
 
+        self.get_building_owner(field)
+    }
+
+    /// The player with a building on this field
+    pub fn get_building_owner(&self, field: &Rc<Field>) -> Option<&Rc<PlayerState>> {
         for player in self.players.iter() {
             for b in [
                 Building::Armory,
@@ -94,10 +198,10 @@ impl Game {
                 Building::Monument,
                 Building::Tunnel,
             ] {
-                if let Some(f) = player.buildings.get(b) {
-                    if Rc::ptr_eq(f, field) {
-                        return Some(player);
-                    }
+                if let Some(f) = player.buildings.get(b)
+                    && Rc::ptr_eq(f, field)
+                {
+                    return Some(player);
                 }
             }
         }
@@ -106,7 +210,136 @@ impl Game {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PlayerInfo<'a> {
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub template: PlayerTemplate<'a>,
     pub start_location_index: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        game::{Resource, board::ResourceField, production::Worker},
+        template::{
+            board::NORMAL,
+            faction::{POLANIA, RUSVIET},
+            player_mat::{AGRICULTURAL, INDUSTRIAL},
+        },
+        test_support::{game, player},
+        turn::{
+            check::MoveError,
+            mask::{Move, Movement, Primary, ResourceCost, Secondary, UnitMovement, UnitPosition},
+        },
+    };
+
+    fn worker_move(worker: Worker, (x, y): (i8, i8), cargo: ResourceField) -> Primary {
+        Primary::Move(Move::Move1(UnitMovement::Worker(
+            worker,
+            Movement::Single((Position::new(x, y), cargo)),
+        )))
+    }
+
+    fn build_mill(cost: ResourceCost) -> Secondary {
+        Secondary::Build(Building::Mill, Worker::First, cost)
+    }
+
+    #[test]
+    fn apply_plays_the_turn_of_the_active_player() {
+        let game = game(2);
+        let next = game.apply(&TurnMask::PrimaryOnly(Primary::Tax)).unwrap();
+        assert_eq!(next.turn, 1);
+        assert_eq!(next.get_active_index(), 1);
+        assert_eq!(next.players[0].coins, game.players[0].coins + 1);
+        assert!(Rc::ptr_eq(&next.players[1], &game.players[1]));
+        assert_eq!(game.turn, 0);
+
+        let after = next.apply(&TurnMask::PrimaryOnly(Primary::Tax)).unwrap();
+        assert_eq!(after.get_active_index(), 0);
+        assert_eq!(after.players[1].coins, game.players[1].coins + 1);
+    }
+
+    #[test]
+    fn secondary_is_played_after_the_primary() {
+        let mut game = game(2);
+        game.update_field(&Position::new(3, 0), |field| {
+            field.resources.add_resource(&Resource::Wood, 3)
+        });
+
+        // The wood only reaches the builder with the move
+        let w1 = UnitPosition::Worker(Worker::First);
+        let build = TurnMask::PrimaryAndSecondary(
+            worker_move(
+                Worker::Second,
+                (2, 1),
+                ResourceField::single(&Resource::Wood, 3),
+            ),
+            build_mill(ResourceCost::Three(w1, w1, w1)),
+        );
+        let next = game.apply(&build).unwrap();
+        let mill = next.players[0].buildings.get(Building::Mill).unwrap();
+        assert_eq!(mill.position, Position::new(2, 1));
+        assert_eq!(mill.resources.wood, 0);
+        assert_eq!(next.turn, 1);
+    }
+
+    #[test]
+    fn apply_rejects_illegal_turns() {
+        let game = game(2);
+        let home = worker_move(Worker::First, (3, 1), ResourceField::empty());
+        assert_eq!(
+            game.apply(&TurnMask::PrimaryOnly(home)).unwrap_err(),
+            IllegalMove::Movement {
+                index: 0,
+                unit: UnitPosition::Worker(Worker::First),
+                reason: MoveError::HomeBase(Position::new(3, 1)),
+            }
+        );
+
+        let w1 = UnitPosition::Worker(Worker::First);
+        let build = build_mill(ResourceCost::Two(w1, w1));
+        assert_eq!(
+            game.apply(&TurnMask::PrimaryAndSecondary(Primary::Tax, build))
+                .unwrap_err(),
+            IllegalMove::ResourceCostMismatch {
+                expected: 3,
+                given: 2,
+            }
+        );
+
+        let build = build_mill(ResourceCost::Three(w1, w1, w1));
+        assert_eq!(
+            game.apply(&TurnMask::PrimaryAndSecondary(Primary::Tax, build))
+                .unwrap_err(),
+            IllegalMove::NotEnoughResources {
+                unit: w1,
+                position: Position::new(2, 1),
+                missing: ResourceField::single(&Resource::Wood, 3),
+            }
+        );
+    }
+
+    #[test]
+    fn new_rejects_invalid_and_shared_start_locations() {
+        let mut rng = GameRng::seed_from_u64(0);
+        let rusviet = player(RUSVIET, INDUSTRIAL, 0);
+        let polania = |index| player(POLANIA, AGRICULTURAL, index);
+
+        let game = Game::new(&NORMAL, &[&rusviet, &polania(1)], &mut rng).unwrap();
+        assert_eq!(
+            game.players[1].character.location.position,
+            Position::new(-1, 4)
+        );
+
+        let outside = polania(NORMAL.starting_locations.len());
+        assert_eq!(
+            Game::new(&NORMAL, &[&rusviet, &outside], &mut rng).unwrap_err(),
+            SetupError::InvalidStartLocation { seat: 1 }
+        );
+        assert_eq!(
+            Game::new(&NORMAL, &[&rusviet, &polania(0)], &mut rng).unwrap_err(),
+            SetupError::DuplicateStartLocation { seat: 1 }
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use crate::{
+    game::game::Game,
+    turn::{check::IllegalMove, mask::TurnMask},
+};
+
+/// A position in the history tree
+#[derive(Debug, Clone)]
+struct Node {
+    game: Game,
+    parent: Option<usize>,
+    /// The turn that led from the parent to this position
+    mask: Option<TurnMask>,
+    children: Vec<usize>,
+    /// The child that redo continues with
+    selected: Option<usize>,
+}
+
+/// A game with an undo and redo stack, where playing a different turn after an undo keeps the
+/// old line as a variation.
+///
+/// Every position is kept as it was, so undo restores the exact prior state.
+#[derive(Debug, Clone)]
+pub struct GameHistory {
+    nodes: Vec<Node>,
+    current: usize,
+}
+
+impl GameHistory {
+    pub fn new(game: Game) -> Self {
+        GameHistory {
+            nodes: vec![Node {
+                game,
+                parent: None,
+                mask: None,
+                children: Vec::new(),
+                selected: None,
+            }],
+            current: 0,
+        }
+    }
+
+    /// The current position
+    pub fn game(&self) -> &Game {
+        &self.nodes[self.current].game
+    }
+
+    /// The starting position
+    pub fn root(&self) -> &Game {
+        &self.nodes[0].game
+    }
+
+    /// Plays the turn, or follows it if it was already played from this position
+    pub fn apply(&mut self, mask: &TurnMask) -> Result<(), IllegalMove> {
+        if let Some(child) = self.find_child(mask) {
+            self.nodes[self.current].selected = Some(child);
+            self.current = child;
+            return Ok(());
+        }
+
+        let game = self.game().apply(mask)?;
+        let child = self.nodes.len();
+        self.nodes.push(Node {
+            game,
+            parent: Some(self.current),
+            mask: Some(*mask),
+            children: Vec::new(),
+            selected: None,
+        });
+        let node = &mut self.nodes[self.current];
+        node.children.push(child);
+        node.selected = Some(child);
+        self.current = child;
+        Ok(())
+    }
+
+    /// Takes back the last turn and returns it
+    pub fn undo(&mut self) -> Option<TurnMask> {
+        let node = &self.nodes[self.current];
+        let (parent, mask) = (node.parent?, node.mask);
+        self.current = parent;
+        mask
+    }
+
+    /// Plays the last undone turn again
+    pub fn redo(&mut self) -> Option<TurnMask> {
+        let child = self.nodes[self.current].selected?;
+        self.current = child;
+        self.nodes[child].mask
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.nodes[self.current].parent.is_some()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.nodes[self.current].selected.is_some()
+    }
+
+    /// Takes back all turns
+    pub fn undo_all(&mut self) {
+        self.current = 0;
+    }
+
+    /// The turns already played from this position, in the order they were first played
+    pub fn variations(&self) -> Vec<TurnMask> {
+        self.nodes[self.current]
+            .children
+            .iter()
+            .filter_map(|child| self.nodes[*child].mask)
+            .collect()
+    }
+
+    /// Makes the variation starting with this turn the one that redo continues with
+    pub fn select_variation(&mut self, mask: &TurnMask) -> bool {
+        match self.find_child(mask) {
+            Some(child) => {
+                self.nodes[self.current].selected = Some(child);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The turns from the starting position to the current one
+    pub fn line(&self) -> Vec<TurnMask> {
+        let mut line = Vec::new();
+        let mut node = &self.nodes[self.current];
+        while let (Some(parent), Some(mask)) = (node.parent, node.mask) {
+            line.push(mask);
+            node = &self.nodes[parent];
+        }
+        line.reverse();
+        line
+    }
+
+    fn find_child(&self, mask: &TurnMask) -> Option<usize> {
+        self.nodes[self.current]
+            .children
+            .iter()
+            .copied()
+            .find(|child| self.nodes[*child].mask.as_ref() == Some(mask))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{game::GameRng, test_support::game, turn::predict::sample_action};
+
+    fn same_position(a: &Game, b: &Game) -> bool {
+        a.turn == b.turn
+            && a.players
+                .iter()
+                .zip(&b.players)
+                .all(|(p, q)| Rc::ptr_eq(p, q))
+            && a.board
+                .get_fields()
+                .iter()
+                .zip(b.board.get_fields())
+                .all(|(f, g)| Rc::ptr_eq(f, g))
+    }
+
+    #[test]
+    fn undo_redo_and_variations() {
+        let start = game(2);
+        let mut history = GameHistory::new(start.clone());
+        let mut rng = GameRng::seed_from_u64(1);
+
+        let mut line = Vec::new();
+        for _ in 0..6 {
+            let mask = sample_action(history.game(), &mut rng);
+            history.apply(&mask).unwrap();
+            line.push(mask);
+        }
+        let end = history.game().clone();
+        assert_eq!(history.line(), line);
+
+        for mask in line.iter().rev() {
+            assert_eq!(history.undo(), Some(*mask));
+        }
+        assert!(!history.can_undo());
+        assert!(same_position(history.game(), &start));
+
+        while history.redo().is_some() {}
+        assert!(same_position(history.game(), &end));
+
+        // A different turn after an undo starts a variation
+        history.undo();
+        let last = line[5];
+        let other = (0..100)
+            .map(|_| sample_action(history.game(), &mut rng))
+            .find(|mask| *mask != last)
+            .unwrap();
+        history.apply(&other).unwrap();
+        history.undo();
+        assert_eq!(history.variations(), vec![last, other]);
+        assert_eq!(history.redo(), Some(other));
+
+        history.undo();
+        assert!(history.select_variation(&last));
+        history.redo();
+        assert!(same_position(history.game(), &end));
+
+        // Illegal turns leave the history as it was
+        history.undo_all();
+        let unknown_worker: TurnMask = "PRODUCE w8".parse().unwrap();
+        assert!(history.apply(&unknown_worker).is_err());
+        assert!(same_position(history.game(), &start));
+        assert_eq!(history.variations(), vec![line[0]]);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{iter::zip, rc::Rc};
+use std::rc::Rc;
 
 use crate::{game::board::Field, turn::mask::MechMask};
 
@@ -10,10 +10,14 @@ pub enum Mech {
     Fourth = 3,
 }
 
+pub const MECHS: [Mech; 4] = [Mech::First, Mech::Second, Mech::Third, Mech::Fourth];
+
 pub type MechEntity = Rc<Field>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MechsState {
+    #[cfg_attr(feature = "serde", serde(with = "crate::game::snapshot::field_refs"))]
     pub mechs: [Option<MechEntity>; 4],
     pub star: bool,
 }
@@ -44,7 +48,7 @@ impl MechsState {
     pub fn amount(&self, field: &Rc<Field>) -> u8 {
         self.mechs.iter().fold(0, |acc, m| {
             acc + match m {
-                Some(t) if Rc::ptr_eq(field, &t) => 1,
+                Some(t) if Rc::ptr_eq(field, t) => 1,
                 _ => 0,
             }
         })
@@ -54,21 +58,39 @@ impl MechsState {
         self.mechs[mech as usize].as_ref()
     }
 
+    pub fn set(&mut self, mech: Mech, tile: &Rc<Field>) {
+        if let Some(f) = self.mechs[mech as usize].as_mut() {
+            *f = tile.clone();
+        }
+    }
+
     pub fn get_deployed(&self) -> MechMask {
-        zip(self.mechs.iter(), [MechMask::all()]).fold(MechMask::empty(), |mask, (mech, m)| {
-            match mech {
-                Some(_) => mask | m, // if the mech is deployed
-                _ => mask,
-            }
-        })
+        self.mechs
+            .iter()
+            .zip(MECHS)
+            .fold(MechMask::empty(), |mask, (mech, m)| {
+                match mech {
+                    Some(_) => mask | MechMask::get_mech(m), // if the mech is deployed
+                    _ => mask,
+                }
+            })
     }
 
     pub fn at(&self, field: &Rc<Field>) -> MechMask {
-        zip(self.mechs.iter(), [MechMask::all()]).fold(MechMask::empty(), |mask, (mech, m)| {
-            match mech {
-                Some(f) if Rc::ptr_eq(field, &f) => mask | m, // if the mech is deployed and at this field
-                _ => mask,
-            }
-        })
+        self.mechs
+            .iter()
+            .zip(MECHS)
+            .fold(MechMask::empty(), |mask, (mech, m)| {
+                match mech {
+                    Some(f) if Rc::ptr_eq(field, f) => mask | MechMask::get_mech(m), // if the mech is deployed and at this field
+                    _ => mask,
+                }
+            })
+    }
+}
+
+impl Default for MechsState {
+    fn default() -> Self {
+        Self::new()
     }
 }
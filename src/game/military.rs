@@ -1,6 +1,5 @@
-use std::u8;
-
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MilitaryState {
     pub power: u8,
     pub star: bool,
@@ -1,16 +1,27 @@
 pub mod board;
 pub mod buildings;
+pub mod character;
+pub mod detached;
+#[allow(clippy::module_inception)]
 pub mod game;
+pub mod history;
 pub mod mechs;
 pub mod military;
 pub mod player;
 pub mod popularity;
 pub mod production;
+pub mod record;
 pub mod recruits;
+#[cfg(feature = "serde")]
+pub mod snapshot;
 pub mod upgrades;
-pub mod character;
+pub mod zobrist;
+
+/// The seedable random number generator used for the whole game
+pub type GameRng = rand_chacha::ChaCha12Rng;
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Tile {
     Woods,
     Tundra,
@@ -23,9 +34,17 @@ pub enum Tile {
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Resource {
     Wood,
     Metal,
     Oil,
     Food,
 }
+
+pub const RESOURCES: [Resource; 4] = [
+    Resource::Wood,
+    Resource::Metal,
+    Resource::Oil,
+    Resource::Food,
+];
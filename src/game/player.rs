@@ -1,6 +1,7 @@
 use std::{cmp::min, rc::Rc};
 
 use crate::{
+    game::Tile,
     game::{
         board::{Field, ResourceField},
         buildings::BuildingsState,
@@ -12,17 +13,26 @@ use crate::{
         recruits::RecruitsState,
         upgrades::UpgradesState,
     },
-    template::{Faction, Player, PlayerMat, PrimaryAction, SecondaryAction},
+    template::{
+        CombatPower, Faction, FactionAbility, MobilityPower, Player, PlayerMat, PrimaryAction,
+        SecondaryAction,
+    },
     turn::mask::UnitPosition,
 };
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PlayerState {
     pub move_secondary: SecondaryAction, // for move and tax primary actions
     pub trade_secondary: SecondaryAction, // for trade and promote primary actions
     pub produce_secondary: SecondaryAction, // for produce primary action
     pub bolster_secondary: SecondaryAction, // for bolster and enforce primary actions
 
+    pub riverwalk: [Tile; 2],
+    pub mobility_power: MobilityPower,
+    pub combat_power: CombatPower,
+    pub faction_ability: FactionAbility,
+
     pub upgrades: UpgradesState,
     pub mechs: MechsState,
     pub buildings: BuildingsState,
@@ -38,9 +48,13 @@ pub struct PlayerState {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PlayerTemplate<'a> {
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub player: Player<'a>,
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub faction: Faction<'a>,
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub player_mat: PlayerMat<'a>,
 }
 
@@ -63,6 +77,14 @@ impl PlayerState {
             produce_secondary: template.player_mat.produce_secondary,
             bolster_secondary: template.player_mat.bolster_secondary,
 
+            riverwalk: [
+                template.faction.riverwalk_tile1,
+                template.faction.riverwalk_tile2,
+            ],
+            mobility_power: template.faction.mobility_power,
+            combat_power: template.faction.combat_power,
+            faction_ability: template.faction.faction_ability,
+
             upgrades: UpgradesState::new(&template.player_mat),
             mechs: MechsState::new(),
             buildings: BuildingsState::new(),
@@ -73,7 +95,7 @@ impl PlayerState {
             popularity: PopularityState::new(
                 template.player_mat.starting_popularity + template.player.bonus_starting_popularity,
             ),
-            production: production,
+            production,
             character: CharacterEntity {
                 location: home.clone(),
             },
@@ -129,11 +151,9 @@ impl PlayerState {
         // TODO buildings only count towards controlled territory, if no enemy unit is currently on that fields
 
         let mut territory = Vec::new();
-        for ele in fields {
-            if let Some(tile) = ele {
-                if !territory.contains(&tile) {
-                    territory.push(tile);
-                }
+        for tile in fields.into_iter().flatten() {
+            if !territory.contains(&tile) {
+                territory.push(tile);
             }
         }
 
@@ -177,13 +197,13 @@ impl PlayerState {
 
     pub fn get_primary(&self, secondary: SecondaryAction) -> PrimaryAction {
         if secondary == self.move_secondary {
-            return PrimaryAction::Tax;
+            PrimaryAction::Tax
         } else if secondary == self.trade_secondary {
-            return PrimaryAction::Promote;
+            PrimaryAction::Promote
         } else if secondary == self.produce_secondary {
-            return PrimaryAction::Produce;
+            PrimaryAction::Produce
         } else if secondary == self.bolster_secondary {
-            return PrimaryAction::Bolster;
+            PrimaryAction::Bolster
         } else {
             panic!("Invalid secondary action: {:?} is not linked", secondary);
         }
@@ -203,13 +223,13 @@ impl PlayerState {
 
     pub fn can_produce(&self) -> bool {
         let total = self.production.deployed_workers;
-        if total >= 8 && self.coins <= 0 {
+        if total >= 8 && self.coins == 0 {
             return false;
         }
-        if total >= 6 && self.popularity.popularity <= 0 {
+        if total >= 6 && self.popularity.popularity == 0 {
             return false;
         }
-        if total >= 4 && self.military.power <= 0 {
+        if total >= 4 && self.military.power == 0 {
             return false;
         }
         true
@@ -223,4 +243,55 @@ impl PlayerState {
             UnitPosition::Building(building) => self.buildings.get(*building),
         }
     }
+
+    /// Moves a unit to the field. Buildings cannot be moved.
+    pub fn set_unit_field(&mut self, unit: &UnitPosition, field: &Rc<Field>) {
+        match unit {
+            UnitPosition::Character => self.character.location = field.clone(),
+            UnitPosition::Worker(worker) => self.production.set(*worker, field),
+            UnitPosition::Mech(mech) => self.mechs.set(*mech, field),
+            UnitPosition::Building(_) => {}
+        }
+    }
+
+    /// Is any unit or building of this player on the field?
+    pub fn references(&self, field: &Rc<Field>) -> bool {
+        Rc::ptr_eq(&self.character.location, field)
+            || self
+                .production
+                .workers
+                .iter()
+                .chain(self.mechs.mechs.iter())
+                .chain([
+                    &self.buildings.armory,
+                    &self.buildings.mill,
+                    &self.buildings.tunnel,
+                    &self.buildings.monument,
+                ])
+                .flatten()
+                .any(|f| Rc::ptr_eq(f, field))
+    }
+
+    /// Points every unit and building on the old field to its replacement
+    pub fn relocate(&mut self, old: &Rc<Field>, new: &Rc<Field>) {
+        if Rc::ptr_eq(&self.character.location, old) {
+            self.character.location = new.clone();
+        }
+        let units = self
+            .production
+            .workers
+            .iter_mut()
+            .chain(self.mechs.mechs.iter_mut())
+            .chain([
+                &mut self.buildings.armory,
+                &mut self.buildings.mill,
+                &mut self.buildings.tunnel,
+                &mut self.buildings.monument,
+            ]);
+        for f in units.flatten() {
+            if Rc::ptr_eq(f, old) {
+                *f = new.clone();
+            }
+        }
+    }
 }
@@ -1,4 +1,5 @@
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PopularityState {
     pub popularity: u8,
     pub star: bool,
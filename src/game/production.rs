@@ -1,4 +1,4 @@
-use std::{iter::zip, rc::Rc};
+use std::rc::Rc;
 
 use crate::{game::board::Field, turn::mask::WorkerMask};
 
@@ -14,10 +14,23 @@ pub enum Worker {
     Eighth = 7,
 }
 
+pub const WORKERS: [Worker; 8] = [
+    Worker::First,
+    Worker::Second,
+    Worker::Third,
+    Worker::Fourth,
+    Worker::Fifth,
+    Worker::Sixth,
+    Worker::Seventh,
+    Worker::Eighth,
+];
+
 pub type WorkerEntity = Rc<Field>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ProductionState {
+    #[cfg_attr(feature = "serde", serde(with = "crate::game::snapshot::field_refs"))]
     pub workers: [Option<WorkerEntity>; 8],
     pub deployed_workers: usize,
     pub star: bool,
@@ -25,18 +38,17 @@ pub struct ProductionState {
 
 impl ProductionState {
     pub fn new() -> ProductionState {
-        let state = ProductionState {
+        ProductionState {
             workers: [const { None }; 8],
             deployed_workers: 0,
             star: false,
-        };
-
-        state
+        }
     }
 
     pub fn deploy(&mut self, tile: &Rc<Field>) {
         if self.deployed_workers < self.workers.len() {
             self.workers[self.deployed_workers] = Some(tile.clone());
+            self.deployed_workers += 1;
         }
 
         if self.deployed_workers >= self.workers.len() {
@@ -44,10 +56,14 @@ impl ProductionState {
         }
     }
 
+    pub fn can_deploy(&self) -> bool {
+        self.deployed_workers < self.workers.len()
+    }
+
     pub fn amount(&self, field: &Rc<Field>) -> u8 {
         self.workers.iter().fold(0, |acc, w| {
             acc + match w {
-                Some(t) if Rc::ptr_eq(field, &t) => 1,
+                Some(t) if Rc::ptr_eq(field, t) => 1,
                 _ => 0,
             }
         })
@@ -57,23 +73,35 @@ impl ProductionState {
         self.workers[worker as usize].as_ref()
     }
 
+    pub fn set(&mut self, worker: Worker, tile: &Rc<Field>) {
+        if let Some(f) = self.workers[worker as usize].as_mut() {
+            *f = tile.clone();
+        }
+    }
+
     pub fn get_deployed(&self) -> WorkerMask {
-        zip(self.workers.iter(), [WorkerMask::all()]).fold(
-            WorkerMask::empty(),
-            |mask, (worker, m)| match worker {
-                Some(_) => mask | m, // if the worker is deployed
+        self.workers
+            .iter()
+            .zip(WORKERS)
+            .fold(WorkerMask::empty(), |mask, (worker, w)| match worker {
+                Some(_) => mask | WorkerMask::get_worker(w), // if the worker is deployed
                 _ => mask,
-            },
-        )
+            })
     }
 
     pub fn at(&self, field: &Rc<Field>) -> WorkerMask {
-        zip(self.workers.iter(), [WorkerMask::all()]).fold(
-            WorkerMask::empty(),
-            |mask, (worker, m)| match worker {
-                Some(f) if Rc::ptr_eq(field, &f) => mask | m, // if the worker is deployed and at this field
+        self.workers
+            .iter()
+            .zip(WORKERS)
+            .fold(WorkerMask::empty(), |mask, (worker, w)| match worker {
+                Some(f) if Rc::ptr_eq(field, f) => mask | WorkerMask::get_worker(w), // if the worker is deployed and at this field
                 _ => mask,
-            },
-        )
+            })
+    }
+}
+
+impl Default for ProductionState {
+    fn default() -> Self {
+        Self::new()
     }
 }
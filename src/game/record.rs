@@ -0,0 +1,537 @@
+use std::{error::Error, fmt, str::FromStr};
+
+use rand::SeedableRng;
+
+use crate::{
+    game::{
+        GameRng,
+        game::{Game, PlayerInfo, SetupError},
+        player::PlayerTemplate,
+    },
+    template::{
+        BoardTemplate, Player, board::NORMAL, faction::faction_by_name,
+        player_mat::player_mat_by_name,
+    },
+    turn::{
+        check::IllegalMove,
+        mask::TurnMask,
+        notation::{format_turn, parse_turn},
+    },
+};
+
+/// The first line of every record
+pub const RECORD_HEADER: &str = "# scythe record v1";
+
+/// The setup of one seat
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeatRecord {
+    pub name: String,
+    pub faction: String,
+    pub player_mat: String,
+    pub start_location_index: usize,
+    pub bonus_starting_coins: u32,
+    pub bonus_starting_power: u8,
+    pub bonus_starting_popularity: u8,
+}
+
+/// A choice made by a seat during a turn, outside of the turn itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Combat { seat: usize, power: u8, cards: u8 },
+    Encounter { seat: usize, option: u8 },
+    Factory { seat: usize, card: u8 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TurnRecord {
+    /// None if the seat had forfeited and its turn was skipped
+    pub mask: Option<TurnMask>,
+    /// Stored in order, but not yet consumed by the engine
+    pub decisions: Vec<Decision>,
+}
+
+/// Everything needed to play a game again
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameRecord {
+    pub board: String,
+    /// Seeds the random number generator of the game
+    pub seed: u64,
+    pub seats: Vec<SeatRecord>,
+    pub turns: Vec<TurnRecord>,
+}
+
+/// The record text could not be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordParseError {
+    /// The line number, starting at 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for RecordParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for RecordParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    UnknownBoard(String),
+    UnknownFaction {
+        seat: usize,
+        name: String,
+    },
+    UnknownPlayerMat {
+        seat: usize,
+        name: String,
+    },
+    /// The seats cannot start on the board
+    Setup(SetupError),
+    /// The first recorded turn that is not legal anymore
+    Diverged {
+        turn: usize,
+        reason: IllegalMove,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::UnknownBoard(name) => write!(f, "Unknown board {name}"),
+            ReplayError::UnknownFaction { seat, name } => {
+                write!(f, "Seat {seat} has the unknown faction {name}")
+            }
+            ReplayError::UnknownPlayerMat { seat, name } => {
+                write!(f, "Seat {seat} has the unknown player mat {name}")
+            }
+            ReplayError::Setup(error) => write!(f, "{error}"),
+            ReplayError::Diverged { turn, reason } => {
+                write!(f, "Turn {turn} diverged: {reason}")
+            }
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+impl GameRecord {
+    /// The game before the first turn
+    pub fn setup(&self) -> Result<Game, ReplayError> {
+        if NORMAL.name.eq_ignore_ascii_case(&self.board) {
+            self.setup_on(&NORMAL)
+        } else {
+            Err(ReplayError::UnknownBoard(self.board.clone()))
+        }
+    }
+
+    fn setup_on<const F: usize, const R: usize, const P: usize>(
+        &self,
+        template: &BoardTemplate<F, R, P>,
+    ) -> Result<Game, ReplayError> {
+        let mut infos = Vec::with_capacity(self.seats.len());
+        for (seat, record) in self.seats.iter().enumerate() {
+            let faction =
+                faction_by_name(&record.faction).ok_or_else(|| ReplayError::UnknownFaction {
+                    seat,
+                    name: record.faction.clone(),
+                })?;
+            let player_mat = player_mat_by_name(&record.player_mat).ok_or_else(|| {
+                ReplayError::UnknownPlayerMat {
+                    seat,
+                    name: record.player_mat.clone(),
+                }
+            })?;
+            infos.push(PlayerInfo {
+                template: PlayerTemplate {
+                    player: Player {
+                        name: &record.name,
+                        bonus_starting_coins: record.bonus_starting_coins,
+                        bonus_starting_power: record.bonus_starting_power,
+                        bonus_starting_popularity: record.bonus_starting_popularity,
+                    },
+                    faction,
+                    player_mat,
+                },
+                start_location_index: record.start_location_index,
+            });
+        }
+
+        let infos: Vec<&PlayerInfo> = infos.iter().collect();
+        let mut rng = GameRng::seed_from_u64(self.seed);
+        Game::new(template, &infos, &mut rng).map_err(ReplayError::Setup)
+    }
+
+    /// Plays all turns again and returns the final game
+    pub fn replay(&self) -> Result<Game, ReplayError> {
+        let mut game = self.setup()?;
+        for (turn, record) in self.turns.iter().enumerate() {
+            game = match &record.mask {
+                Some(mask) => game
+                    .apply(mask)
+                    .map_err(|reason| ReplayError::Diverged { turn, reason })?,
+                None => Game {
+                    turn: game.turn + 1,
+                    ..game
+                },
+            };
+        }
+        Ok(game)
+    }
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{RECORD_HEADER}")?;
+        writeln!(f, "board {}", self.board)?;
+        writeln!(f, "seed {}", self.seed)?;
+        for seat in &self.seats {
+            writeln!(
+                f,
+                "seat {} {} start={} coins={} power={} popularity={} {}",
+                seat.faction,
+                seat.player_mat,
+                seat.start_location_index,
+                seat.bonus_starting_coins,
+                seat.bonus_starting_power,
+                seat.bonus_starting_popularity,
+                quote(&seat.name)
+            )?;
+        }
+        for turn in &self.turns {
+            match &turn.mask {
+                Some(mask) => writeln!(f, "turn {}", format_turn(mask))?,
+                None => writeln!(f, "skip")?,
+            }
+            for decision in &turn.decisions {
+                match decision {
+                    Decision::Combat { seat, power, cards } => {
+                        writeln!(f, "combat {seat} {power} {cards}")?
+                    }
+                    Decision::Encounter { seat, option } => {
+                        writeln!(f, "encounter {seat} {option}")?
+                    }
+                    Decision::Factory { seat, card } => writeln!(f, "factory {seat} {card}")?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for GameRecord {
+    type Err = RecordParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()));
+        match lines.next() {
+            Some((_, RECORD_HEADER)) => {}
+            _ => return Err(parse_error(1, &format!("Expected `{RECORD_HEADER}`"))),
+        }
+
+        let mut board = None;
+        let mut seed = None;
+        let mut seats = Vec::new();
+        let mut turns: Vec<TurnRecord> = Vec::new();
+        for (line, text) in lines {
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+            let (keyword, rest) = text.split_once(' ').unwrap_or((text, ""));
+            let rest = rest.trim();
+            match keyword {
+                "board" => board = Some(rest.to_string()),
+                "seed" => seed = Some(number(line, rest)?),
+                "seat" => seats.push(parse_seat(line, rest)?),
+                "turn" => {
+                    let mask = parse_turn(rest).map_err(|e| parse_error(line, &e.to_string()))?;
+                    turns.push(TurnRecord {
+                        mask: Some(mask),
+                        decisions: Vec::new(),
+                    });
+                }
+                "skip" => turns.push(TurnRecord {
+                    mask: None,
+                    decisions: Vec::new(),
+                }),
+                "combat" | "encounter" | "factory" => {
+                    let values: Vec<&str> = rest.split_whitespace().collect();
+                    let decision = match (keyword, &values[..]) {
+                        ("combat", [seat, power, cards]) => Decision::Combat {
+                            seat: number(line, seat)?,
+                            power: number(line, power)?,
+                            cards: number(line, cards)?,
+                        },
+                        ("encounter", [seat, option]) => Decision::Encounter {
+                            seat: number(line, seat)?,
+                            option: number(line, option)?,
+                        },
+                        ("factory", [seat, card]) => Decision::Factory {
+                            seat: number(line, seat)?,
+                            card: number(line, card)?,
+                        },
+                        _ => return Err(parse_error(line, "Wrong number of values")),
+                    };
+                    match turns.last_mut() {
+                        Some(turn) => turn.decisions.push(decision),
+                        None => return Err(parse_error(line, "Decision before the first turn")),
+                    }
+                }
+                _ => return Err(parse_error(line, &format!("Unknown entry `{keyword}`"))),
+            }
+        }
+
+        Ok(GameRecord {
+            board: board.ok_or_else(|| parse_error(1, "Missing board"))?,
+            seed: seed.ok_or_else(|| parse_error(1, "Missing seed"))?,
+            seats,
+            turns,
+        })
+    }
+}
+
+fn parse_error(line: usize, message: &str) -> RecordParseError {
+    RecordParseError {
+        line,
+        message: message.to_string(),
+    }
+}
+
+fn number<T: FromStr>(line: usize, text: &str) -> Result<T, RecordParseError> {
+    text.parse()
+        .map_err(|_| parse_error(line, &format!("Invalid number `{text}`")))
+}
+
+/// `<faction> <mat> start=<n> coins=<n> power=<n> popularity=<n> "<name>"`
+fn parse_seat(line: usize, text: &str) -> Result<SeatRecord, RecordParseError> {
+    let mut parts = text.splitn(7, ' ');
+    let mut next = || {
+        parts
+            .next()
+            .filter(|part| !part.is_empty())
+            .ok_or_else(|| parse_error(line, "Incomplete seat"))
+    };
+    let faction = next()?.to_string();
+    let player_mat = next()?.to_string();
+    let mut value = |key: &str| -> Result<&str, RecordParseError> {
+        next()?
+            .strip_prefix(key)
+            .and_then(|part| part.strip_prefix('='))
+            .ok_or_else(|| parse_error(line, &format!("Expected `{key}=`")))
+    };
+    let start_location_index = number(line, value("start")?)?;
+    let bonus_starting_coins = number(line, value("coins")?)?;
+    let bonus_starting_power = number(line, value("power")?)?;
+    let bonus_starting_popularity = number(line, value("popularity")?)?;
+    let name = unquote(line, next()?)?;
+
+    Ok(SeatRecord {
+        name,
+        faction,
+        player_mat,
+        start_location_index,
+        bonus_starting_coins,
+        bonus_starting_power,
+        bonus_starting_popularity,
+    })
+}
+
+/// The name in quotes, so that empty names and surrounding spaces survive
+fn quote(name: &str) -> String {
+    let escaped = name
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+fn unquote(line: usize, text: &str) -> Result<String, RecordParseError> {
+    let inner = text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .ok_or_else(|| parse_error(line, "Expected a quoted name"))?;
+    let mut name = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('\\') => name.push('\\'),
+                Some('"') => name.push('"'),
+                Some('n') => name.push('\n'),
+                _ => return Err(parse_error(line, "Invalid escape in the name")),
+            },
+            '"' => return Err(parse_error(line, "Unescaped quote in the name")),
+            c => name.push(c),
+        }
+    }
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        game::production::Worker,
+        template::{Position, faction::FACTIONS, player_mat::PLAYER_MATS},
+        test_support::seat,
+        turn::{
+            check::MoveError,
+            mask::{Primary, UnitPosition},
+            predict::sample_action,
+        },
+    };
+
+    fn record() -> GameRecord {
+        GameRecord {
+            board: "NORMAL".to_string(),
+            seed: 5,
+            seats: vec![
+                seat("Rusviet", "Industrial", 0),
+                seat("Polania", "Agricultural", 1),
+            ],
+            turns: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let mut record = record();
+        record.seats[0].name = String::new();
+        record.seats[1].name = " \"Alex\" the\\Second\n".to_string();
+        record.seats[1].bonus_starting_coins = 2;
+        let mut game = record.setup().unwrap();
+        let mut rng = GameRng::seed_from_u64(1);
+        for turn in 0..6 {
+            let mask = sample_action(&game, &mut rng);
+            game = game.apply(&mask).unwrap();
+            record.turns.push(TurnRecord {
+                mask: (turn != 3).then_some(mask),
+                decisions: match turn {
+                    0 => vec![Decision::Encounter { seat: 0, option: 2 }],
+                    1 => vec![
+                        Decision::Combat {
+                            seat: 1,
+                            power: 3,
+                            cards: 1,
+                        },
+                        Decision::Factory { seat: 1, card: 0 },
+                    ],
+                    _ => Vec::new(),
+                },
+            });
+        }
+
+        let text = record.to_string();
+        assert!(text.starts_with(RECORD_HEADER));
+        assert_eq!(text.parse::<GameRecord>(), Ok(record));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let parse = |text: &str| text.parse::<GameRecord>().unwrap_err();
+        assert_eq!(parse("board NORMAL").line, 1);
+
+        let error = parse(&format!("{RECORD_HEADER}\nboard NORMAL\nseed x"));
+        assert_eq!(
+            (error.line, error.message.as_str()),
+            (3, "Invalid number `x`")
+        );
+        let error = parse(&format!(
+            "{RECORD_HEADER}\nboard NORMAL\nseed 1\ncombat 0 1 2"
+        ));
+        assert_eq!(
+            (error.line, error.message.as_str()),
+            (4, "Decision before the first turn")
+        );
+        let error = parse(&format!("{RECORD_HEADER}\nseed 1\nturn TAX\nturn JUMP"));
+        assert_eq!(error.line, 4);
+        let error = parse(&format!(
+            "{RECORD_HEADER}\nboard NORMAL\nseed 1\nseat Rusviet"
+        ));
+        assert_eq!((error.line, error.message.as_str()), (4, "Incomplete seat"));
+        let seat = "seat Rusviet Industrial start=0 coins=0 power=0 popularity=0";
+        for (name, message) in [
+            ("Joey", "Expected a quoted name"),
+            ("\"", "Expected a quoted name"),
+            ("\"Jo\"ey\"", "Unescaped quote in the name"),
+            ("\"Joey\\\"", "Invalid escape in the name"),
+        ] {
+            let error = parse(&format!(
+                "{RECORD_HEADER}\nboard NORMAL\nseed 1\n{seat} {name}"
+            ));
+            assert_eq!((error.line, error.message.as_str()), (4, message), "{name}");
+        }
+    }
+
+    #[test]
+    fn replay_reports_the_first_divergence() {
+        let mut record = record();
+        let tax = TurnMask::PrimaryOnly(Primary::Tax);
+        for mask in [tax, tax, parse_turn("MOVE w1(3,1)").unwrap()] {
+            record.turns.push(TurnRecord {
+                mask: Some(mask),
+                decisions: Vec::new(),
+            });
+        }
+        assert_eq!(
+            record.replay().unwrap_err(),
+            ReplayError::Diverged {
+                turn: 2,
+                reason: IllegalMove::Movement {
+                    index: 0,
+                    unit: UnitPosition::Worker(Worker::First),
+                    reason: MoveError::HomeBase(Position::new(3, 1)),
+                },
+            }
+        );
+
+        record.turns.truncate(2);
+        assert_eq!(record.replay().unwrap().turn, 2);
+        record.seats[1].faction = "Albion".to_string();
+        assert_eq!(
+            record.replay().unwrap_err(),
+            ReplayError::UnknownFaction {
+                seat: 1,
+                name: "Albion".to_string(),
+            }
+        );
+        record.seats[1].faction = "Polania".to_string();
+        record.seats[1].start_location_index = 9;
+        assert_eq!(
+            record.setup().unwrap_err(),
+            ReplayError::Setup(SetupError::InvalidStartLocation { seat: 1 })
+        );
+    }
+
+    #[test]
+    fn every_start_location_can_be_taken() {
+        let seats = (0..NORMAL.starting_locations.len())
+            .map(|index| {
+                let faction = FACTIONS[index % FACTIONS.len()].name;
+                let player_mat = PLAYER_MATS[index % PLAYER_MATS.len()].name;
+                seat(faction, player_mat, index)
+            })
+            .collect();
+        let record = GameRecord {
+            board: "NORMAL".to_string(),
+            seed: 0,
+            seats,
+            turns: Vec::new(),
+        };
+
+        let mut game = record.setup().unwrap();
+        assert_eq!(game.players.len(), NORMAL.starting_locations.len());
+        for (player, location) in game.players.iter().zip(&NORMAL.starting_locations) {
+            assert_eq!(player.character.location.position, location.position);
+        }
+
+        let mut rng = GameRng::seed_from_u64(1);
+        for _ in 0..2 * game.players.len() {
+            game = game.apply(&sample_action(&game, &mut rng)).unwrap();
+        }
+    }
+}
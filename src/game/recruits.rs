@@ -6,7 +6,15 @@ pub enum Recruit {
     Coin,
 }
 
+pub const RECRUITS: [Recruit; 4] = [
+    Recruit::Popularity,
+    Recruit::Power,
+    Recruit::Card,
+    Recruit::Coin,
+];
+
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RecruitsState {
     pub secondary_military_recruited: bool,
     pub secondary_coin_recruited: bool,
@@ -102,3 +110,9 @@ impl RecruitsState {
         }
     }
 }
+
+impl Default for RecruitsState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,291 @@
+//! Serde support for game snapshots.
+//!
+//! Units and buildings point at the fields of the board. They are written as the position of
+//! their field and linked to the fields of the board again when the game is read.
+
+use std::{collections::HashMap, error::Error, fmt, rc::Rc};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    game::{
+        GameRng, Tile,
+        board::{Board, Field, ResourceField},
+        game::Game,
+        player::PlayerState,
+    },
+    template::Position,
+};
+
+/// A field known only by its position, until it is linked to the board
+fn unlinked(position: Position) -> Rc<Field> {
+    Rc::new(Field {
+        encounter_token: false,
+        tunnelable: false,
+        tile: Tile::Home,
+        position,
+        resources: ResourceField::empty(),
+    })
+}
+
+/// Points the field reference at the field of the board with the same position
+fn link(field: &mut Rc<Field>, board: &Board) -> Result<(), Position> {
+    *field = board
+        .get_field(&field.position)
+        .cloned()
+        .ok_or(field.position)?;
+    Ok(())
+}
+
+/// Points all units and buildings of the player at the fields of the board
+fn link_player(player: &mut PlayerState, board: &Board) -> Result<(), Position> {
+    link(&mut player.character.location, board)?;
+    let buildings = &mut player.buildings;
+    let entities = player
+        .production
+        .workers
+        .iter_mut()
+        .chain(player.mechs.mechs.iter_mut())
+        .chain([
+            &mut buildings.tunnel,
+            &mut buildings.mill,
+            &mut buildings.armory,
+            &mut buildings.monument,
+        ])
+        .flatten();
+    for field in entities {
+        link(field, board)?;
+    }
+    Ok(())
+}
+
+/// Writes a field reference as its position
+pub mod field_ref {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(field: &Rc<Field>, serializer: S) -> Result<S::Ok, S::Error> {
+        field.position.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rc<Field>, D::Error> {
+        Position::deserialize(deserializer).map(unlinked)
+    }
+}
+
+pub mod optional_field_ref {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        field: &Option<Rc<Field>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        field.as_ref().map(|f| f.position).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Rc<Field>>, D::Error> {
+        Ok(Option::<Position>::deserialize(deserializer)?.map(unlinked))
+    }
+}
+
+pub mod field_refs {
+    use super::*;
+
+    pub fn serialize<S: Serializer, const N: usize>(
+        fields: &[Option<Rc<Field>>; N],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let positions: Vec<Option<Position>> = fields
+            .iter()
+            .map(|field| field.as_ref().map(|f| f.position))
+            .collect();
+        positions.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<[Option<Rc<Field>>; N], D::Error> {
+        let positions = Vec::<Option<Position>>::deserialize(deserializer)?;
+        let len = positions.len();
+        let fields: Vec<Option<Rc<Field>>> = positions
+            .into_iter()
+            .map(|position| position.map(unlinked))
+            .collect();
+        fields
+            .try_into()
+            .map_err(|_| serde::de::Error::invalid_length(len, &format!("{N} entries").as_str()))
+    }
+}
+
+/// The board as a list of fields and rivers between positions
+#[derive(Serialize, Deserialize)]
+pub struct BoardData {
+    fields: Vec<Field>,
+    rivers: Vec<(Position, Position)>,
+}
+
+impl From<Board> for BoardData {
+    fn from(board: Board) -> Self {
+        BoardData {
+            fields: board.get_fields().into_iter().map(|f| **f).collect(),
+            rivers: board
+                .rivers
+                .iter()
+                .map(|(f1, f2)| (f1.position, f2.position))
+                .collect(),
+        }
+    }
+}
+
+impl From<BoardData> for Board {
+    fn from(data: BoardData) -> Self {
+        let fields: HashMap<Position, Rc<Field>> = data
+            .fields
+            .into_iter()
+            .map(|field| (field.position, Rc::new(field)))
+            .collect();
+        let rivers = data
+            .rivers
+            .iter()
+            .filter_map(|(p1, p2)| Some((fields.get(p1)?.clone(), fields.get(p2)?.clone())))
+            .collect();
+        Board { fields, rivers }
+    }
+}
+
+/// The game as read, before its units are linked to the board
+#[derive(Deserialize)]
+pub struct GameData {
+    board: Board,
+    players: Vec<PlayerState>,
+    turn: u32,
+    rng: GameRng,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// A unit or building stands on a position that is not on the board
+    UnknownField(Position),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::UnknownField(position) => {
+                write!(f, "There is no field at {position:?}")
+            }
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl TryFrom<GameData> for Game {
+    type Error = SnapshotError;
+
+    fn try_from(data: GameData) -> Result<Self, Self::Error> {
+        let players = data
+            .players
+            .into_iter()
+            .map(|mut player| {
+                link_player(&mut player, &data.board).map_err(SnapshotError::UnknownField)?;
+                Ok(Rc::new(player))
+            })
+            .collect::<Result<_, SnapshotError>>()?;
+
+        Ok(Game {
+            board: data.board,
+            players,
+            turn: data.turn,
+            rng: data.rng,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{
+        game::game::PlayerInfo,
+        template::{Faction, faction::RUSVIET, player_mat::INDUSTRIAL},
+        test_support::{game, player},
+        turn::predict::sample_action,
+    };
+
+    fn played_game(turns: usize) -> Game {
+        let mut game = game(2);
+        let mut rng = GameRng::seed_from_u64(5);
+        for _ in 0..turns {
+            game = game.apply(&sample_action(&game, &mut rng)).unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn game_round_trip() {
+        let game = played_game(40);
+        let json = serde_json::to_string(&game).unwrap();
+        let copy: Game = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&copy).unwrap(), json);
+
+        // Units point at the fields of the board again
+        for player in &copy.players {
+            let location = &player.character.location;
+            assert!(Rc::ptr_eq(
+                location,
+                copy.board.get_field(&location.position).unwrap()
+            ));
+            for worker in player.production.workers.iter().flatten() {
+                assert!(Rc::ptr_eq(
+                    worker,
+                    copy.board.get_field(&worker.position).unwrap()
+                ));
+            }
+        }
+
+        // Both games continue the same way
+        let (mut game, mut copy) = (game, copy);
+        let mut rng = GameRng::seed_from_u64(6);
+        for _ in 0..40 {
+            let mask = sample_action(&game, &mut rng);
+            game = game.apply(&mask).unwrap();
+            copy = copy.apply(&mask).unwrap();
+        }
+        assert_eq!(
+            serde_json::to_string(&game).unwrap(),
+            serde_json::to_string(&copy).unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_field() {
+        let mut json: serde_json::Value = serde_json::to_value(played_game(0)).unwrap();
+        json["players"][0]["character"]["location"] = serde_json::json!([20, 20]);
+        let error = serde_json::from_value::<Game>(json).unwrap_err();
+        assert!(error.to_string().contains("no field"), "{error}");
+    }
+
+    #[test]
+    fn templates_by_name_or_embedded() {
+        let info = player(RUSVIET, INDUSTRIAL, 0);
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["template"]["faction"], "Rusviet");
+        assert_eq!(json["template"]["player_mat"], "Industrial");
+
+        let custom = Faction {
+            name: "Albion",
+            ..RUSVIET
+        };
+        let info = player(custom.clone(), INDUSTRIAL, 0);
+        let json = serde_json::to_string(&info).unwrap();
+        let copy: PlayerInfo = serde_json::from_str(&json).unwrap();
+        assert_eq!(copy.template.faction, custom);
+        assert_eq!(copy.template.player_mat, INDUSTRIAL);
+
+        let unknown = json.replace("Industrial", "Unknown");
+        assert!(serde_json::from_str::<PlayerInfo>(&unknown).is_err());
+    }
+}
@@ -18,7 +18,24 @@ pub enum SecondaryUpgrade {
     Enlist,
 }
 
+pub const PRIMARY_UPGRADES: [PrimaryUpgrade; 6] = [
+    PrimaryUpgrade::Move,
+    PrimaryUpgrade::Tax,
+    PrimaryUpgrade::Promote,
+    PrimaryUpgrade::Produce,
+    PrimaryUpgrade::Bolster,
+    PrimaryUpgrade::Enforce,
+];
+
+pub const SECONDARY_UPGRADES: [SecondaryUpgrade; 4] = [
+    SecondaryUpgrade::Upgrade,
+    SecondaryUpgrade::Deploy,
+    SecondaryUpgrade::Build,
+    SecondaryUpgrade::Enlist,
+];
+
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UpgradesState {
     pub popularity_evolved: bool,
     pub power_evolved: bool,
@@ -0,0 +1,270 @@
+use std::rc::Rc;
+
+use crate::{
+    game::{board::Field, game::Game, player::PlayerState},
+    template::Position,
+};
+
+/// Random keys are derived from the component they stand for, so no key table is stored
+const SEED: u64 = 0x5c79_7e5e_ed00_b0a7;
+
+// The components of a position
+const FIELD: u64 = 1;
+const CHARACTER: u64 = 2;
+const WORKER: u64 = 3;
+const MECH: u64 = 4;
+const BUILDING: u64 = 5;
+const TRACK: u64 = 6;
+const FLAGS: u64 = 7;
+const ACTIVE: u64 = 8;
+
+/// SplitMix64 finaliser
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// The key of a component with an owner and a value
+fn key(component: u64, owner: u64, value: u64) -> u64 {
+    mix(mix(mix(SEED ^ component) ^ owner) ^ value)
+}
+
+fn position_value(position: &Position) -> u64 {
+    u64::from(position.x() as u8) << 8 | u64::from(position.y() as u8)
+}
+
+/// The resources and encounter token on a field
+pub fn field_hash(field: &Field) -> u64 {
+    let resources = &field.resources;
+    let value = u64::from(resources.wood)
+        ^ u64::from(resources.metal).rotate_left(16)
+        ^ u64::from(resources.oil).rotate_left(32)
+        ^ u64::from(resources.food).rotate_left(48)
+        ^ u64::from(field.encounter_token) << 63;
+    key(FIELD, position_value(&field.position), value)
+}
+
+/// The units, buildings, tracks and flags of the player on this seat
+pub fn player_hash(seat: usize, player: &PlayerState) -> u64 {
+    let seat = seat as u64;
+    let mut hash = key(
+        CHARACTER,
+        seat,
+        position_value(&player.character.location.position),
+    );
+
+    // Workers and mechs are interchangeable, so only how many stand on each field is hashed.
+    // The sum of the keys keeps units on the same field from cancelling each other out.
+    let units = [
+        (WORKER, player.production.workers.as_slice()),
+        (MECH, player.mechs.mechs.as_slice()),
+    ];
+    for (component, entities) in units {
+        let fields = entities
+            .iter()
+            .flatten()
+            .map(|field| mix(component << 32 ^ position_value(&field.position)))
+            .fold(0, u64::wrapping_add);
+        hash ^= key(component, seat, fields);
+    }
+    let buildings = &player.buildings;
+    for (index, field) in [
+        &buildings.armory,
+        &buildings.monument,
+        &buildings.tunnel,
+        &buildings.mill,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        if let Some(field) = field {
+            hash ^= key(
+                BUILDING,
+                seat << 8 | index as u64,
+                position_value(&field.position),
+            );
+        }
+    }
+
+    let tracks = [
+        u64::from(player.military.power),
+        u64::from(player.popularity.popularity),
+        u64::from(player.coins),
+        u64::from(player.cards),
+        u64::from(player.combat_wins),
+    ];
+    for (index, value) in tracks.into_iter().enumerate() {
+        hash ^= key(TRACK, seat << 8 | index as u64, value);
+    }
+
+    let upgrades = &player.upgrades;
+    let recruits = &player.recruits;
+    let flags = [
+        upgrades.popularity_evolved,
+        upgrades.power_evolved,
+        upgrades.card_evolved,
+        upgrades.move_evolved,
+        upgrades.tax_evolved,
+        upgrades.produce_evolved,
+        recruits.secondary_military_recruited,
+        recruits.secondary_coin_recruited,
+        recruits.secondary_popularity_recruited,
+        recruits.secondary_card_recruited,
+        recruits.onetime_military_recruited,
+        recruits.onetime_coin_recruited,
+        recruits.onetime_popularity_recruited,
+        recruits.onetime_card_recruited,
+        upgrades.star,
+        player.mechs.star,
+        buildings.star,
+        recruits.star,
+        player.military.star,
+        player.popularity.star,
+        player.production.star,
+    ];
+    let flags = flags
+        .into_iter()
+        .enumerate()
+        .fold(0, |value, (index, flag)| value | u64::from(flag) << index);
+    let costs = [
+        upgrades.upgrade_base_cost,
+        upgrades.upgrade_evolution_cost,
+        upgrades.deploy_base_cost,
+        upgrades.deploy_evolution_cost,
+        upgrades.build_base_cost,
+        upgrades.build_evolution_cost,
+        upgrades.enlist_base_cost,
+        upgrades.enlist_evolution_cost,
+    ]
+    .into_iter()
+    .fold(0, |value, cost| value << 4 | u64::from(cost & 0xf));
+    // Which mechs are deployed decides the abilities of all of them
+    let mechs = u64::from(player.mechs.get_deployed().bits());
+    hash ^ key(FLAGS, seat, flags | mechs << 24 | costs << 32)
+}
+
+fn active_hash(game: &Game) -> u64 {
+    key(ACTIVE, 0, game.get_active_index() as u64)
+}
+
+/// The hash of the whole position, computed from scratch
+pub fn zobrist_hash(game: &Game) -> u64 {
+    let fields = game
+        .board
+        .fields
+        .values()
+        .fold(0, |hash, field| hash ^ field_hash(field));
+    let players = game
+        .players
+        .iter()
+        .enumerate()
+        .fold(0, |hash, (seat, player)| hash ^ player_hash(seat, player));
+    fields ^ players ^ active_hash(game)
+}
+
+/// The hash of the next position from the hash of the previous one.
+///
+/// Only fields and players that were replaced are hashed again.
+pub fn update_hash(previous: &Game, hash: u64, next: &Game) -> u64 {
+    let mut hash = hash ^ active_hash(previous) ^ active_hash(next);
+    for (position, field) in &next.board.fields {
+        match previous.board.get_field(position) {
+            Some(old) if Rc::ptr_eq(old, field) => {}
+            Some(old) => hash ^= field_hash(old) ^ field_hash(field),
+            None => hash ^= field_hash(field),
+        }
+    }
+    for (seat, player) in next.players.iter().enumerate() {
+        match previous.players.get(seat) {
+            Some(old) if Rc::ptr_eq(old, player) => {}
+            Some(old) => hash ^= player_hash(seat, old) ^ player_hash(seat, player),
+            None => hash ^= player_hash(seat, player),
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{
+        game::{GameRng, production::Worker},
+        test_support::game,
+        turn::predict::sample_action,
+    };
+
+    #[test]
+    fn incremental_matches_full() {
+        let mut game = game(3);
+        let mut hash = zobrist_hash(&game);
+        let mut seen = vec![hash];
+        let mut rng = GameRng::seed_from_u64(2);
+        for _ in 0..150 {
+            let next = game.apply(&sample_action(&game, &mut rng)).unwrap();
+            hash = update_hash(&game, hash, &next);
+            assert_eq!(hash, zobrist_hash(&next));
+            seen.push(hash);
+            game = next;
+        }
+
+        // Every turn changes the active player, so consecutive positions differ
+        assert!(seen.windows(2).all(|w| w[0] != w[1]));
+    }
+
+    #[test]
+    fn active_player_is_hashed() {
+        let game = game(3);
+        let tax: crate::turn::mask::TurnMask = "TAX".parse().unwrap();
+
+        // Skipping the turn of a seat keeps everything but the active player
+        let taxed = game.apply(&tax).unwrap();
+        let skipped = Game {
+            turn: game.turn + 1,
+            ..game.clone()
+        };
+        assert_ne!(zobrist_hash(&taxed), zobrist_hash(&skipped));
+
+        let cycled = Game {
+            turn: game.turn + 3,
+            ..game.clone()
+        };
+        assert_eq!(zobrist_hash(&cycled), zobrist_hash(&game));
+    }
+
+    #[test]
+    fn interchangeable_units_are_hashed_alike() {
+        let game = game(2);
+        let swap = |game: &Game| {
+            let mut swapped = game.clone();
+            let player = Rc::make_mut(&mut swapped.players[0]);
+            player.production.workers.swap(0, 1);
+            player.mechs.mechs.swap(2, 3);
+            swapped
+        };
+        assert_eq!(zobrist_hash(&swap(&game)), zobrist_hash(&game));
+
+        // Two mechs on the same field do not cancel out, and their abilities count
+        let mut mechs = game.clone();
+        let village = mechs.board.get_field(&Position::new(2, 1)).unwrap().clone();
+        let player = Rc::make_mut(&mut mechs.players[0]);
+        player.mechs.mechs[2] = Some(village.clone());
+        player.mechs.mechs[3] = Some(village.clone());
+        assert_ne!(zobrist_hash(&mechs), zobrist_hash(&game));
+        assert_eq!(zobrist_hash(&swap(&mechs)), zobrist_hash(&mechs));
+
+        let mut other = mechs.clone();
+        let player = Rc::make_mut(&mut other.players[0]);
+        player.mechs.mechs.swap(0, 3);
+        assert_ne!(zobrist_hash(&other), zobrist_hash(&mechs));
+
+        // Two workers on a field are not the same as one
+        let mut stacked = game.clone();
+        Rc::make_mut(&mut stacked.players[0])
+            .production
+            .set(Worker::Second, &village);
+        assert_ne!(zobrist_hash(&stacked), zobrist_hash(&game));
+    }
+}
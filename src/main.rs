@@ -1,31 +1,31 @@
-// pub mod agent;
+pub mod agent;
 pub mod game;
 pub mod network;
 pub mod template;
+#[cfg(test)]
+mod test_support;
 pub mod turn;
 
-// use agent::{
-//     human::{PriorityAgent, Step},
-//     random::RandomAgent,
-//     Agent,
-// };
-use template::{faction::RUSVIET, player_mat::INDUSTRIAL, Player};
-
-use std::io;
+use rand::SeedableRng;
 
 use crate::{
-    game::{
-        game::{Game, PlayerInfo},
-        player::PlayerTemplate,
+    agent::{
+        Agent,
+        human::{PriorityAgent, Step},
+        random::RandomAgent,
+        runner::{GameRunner, IllegalActionPolicy},
     },
+    game::{GameRng, Tile, game::PlayerInfo, player::PlayerTemplate},
     template::{
+        Player, PrimaryAction,
         board::NORMAL,
-        faction::{NORDIC, POLANIA},
-        player_mat::{AGRICULTURAL, PATRIOTIC},
+        faction::{NORDIC, POLANIA, RUSVIET},
+        player_mat::{AGRICULTURAL, INDUSTRIAL, PATRIOTIC},
     },
 };
 
-const DEBUG: u32 = 2;
+const MAX_TURNS: u32 = 300;
+const SEED: u64 = 0;
 
 fn main() {
     println!("Welcome to scythe statistics!");
@@ -70,77 +70,51 @@ fn main() {
         start_location_index: 2,
     };
 
-    let mut game = Game::new(&NORMAL, [&player1, &player2, &player3]);
-    let x = game.players.get(1).unwrap();
-    println!("{x :#?}");
-    // let mut agent = PriorityAgent {
-    //     priority: vec![
-    //         Step::Population,
-    //         Step::Upgrade,
-    //         Step::Build,
-    //         Step::Recruit,
-    //         Step::Power,
-    //         Step::Popularity,
-    //         Step::Deploy,
-    //     ],
-    //     final_step: PrimaryAction::Tax,
-    //     mill_tile: Tile::Mountain,
-    // };
-
-//     while game.turn <= 100 {
-//         let action = agent.get_action(&state);
-//         let newstate = turn(state, &action);
-
-//         if DEBUG >= 1 {
-//             match action {
-//                 TurnMask::PrimaryOnly(primary) => {
-//                     println!("Turn: {} == Action: {:?}", state.turns, primary)
-//                 }
-//                 TurnMask::PrimaryAndSecondary(primary, secondary) => {
-//                     println!(
-//                         "Turn: {} == Action: {:?} == Then: {:?}",
-//                         state.turns, primary, secondary
-//                     )
-//                 }
-//             }
-//         }
-
-//         if DEBUG >= 2 {
-//             println!("{action:?}");
-//             println!("");
-//         }
-
-//         if DEBUG >= 3 {
-//             println!("{newstate:#?}");
-//             println!("");
-//         }
-
-//         if DEBUG >= 4 {
-//             let mut input = String::new();
-//             io::stdin()
-//                 .read_line(&mut input)
-//                 .expect("Failed to read the line!");
-//         }
-//         state = newstate;
-//     }
+    let priority = PriorityAgent {
+        priority: vec![
+            Step::Population,
+            Step::Upgrade,
+            Step::Build,
+            Step::Recruit,
+            Step::Power,
+            Step::Popularity,
+            Step::Deploy,
+        ],
+        final_step: PrimaryAction::Tax,
+        mill_tile: Tile::Mountain,
+    };
+    let mut rng = GameRng::seed_from_u64(SEED);
+    let mut runner = GameRunner::new(
+        &NORMAL,
+        vec![
+            (player1, Box::new(priority) as Box<dyn Agent>),
+            (player2, Box::new(RandomAgent::new(&mut rng))),
+            (player3, Box::new(RandomAgent::new(&mut rng))),
+        ],
+        MAX_TURNS,
+        IllegalActionPolicy::Random,
+        &mut rng,
+    );
 
-//     println!(
-//         "Congratulations! You have won the game in {} turns! {} has scored {} coins!",
-//         state.turns,
-//         faction.name,
-//         state.total_coins()
-//     );
-//     println!("You have achieved the following stars:");
-//     println!("Upgrades: {}", state.upgrades.star);
-//     println!("Mechs: {}", state.mechs.star);
-//     println!("Buildings: {}", state.buildings.star);
-//     println!("Recruits: {}", state.recruits.star);
-//     println!("Military: {}", state.military.star);
-//     println!("Popularity: {}", state.popularity.star);
-//     println!("Total coins: {}", state.total_coins());
-//     println!("Total stars: {}", state.stars());
+    match runner.run() {
+        Ok(result) => {
+            println!("The game ended after {} turns", result.turns);
+            for (seat, (info, _)) in runner.seats.iter().enumerate() {
+                println!(
+                    "{} has scored {} coins with {} stars",
+                    info.template.player.name,
+                    result.scores[seat],
+                    result.stars.last().map_or(0, |stars| stars[seat])
+                );
+            }
 
-//     if DEBUG >= 1 {
-//         println!("{state:?}");
-//     }
+            // The record is written to the file given as first argument
+            if let Some(path) = std::env::args().nth(1)
+                && let Err(error) = std::fs::write(&path, result.record.to_string())
+            {
+                println!("The record could not be written to {path}: {error}");
+            }
+        }
+        Err(error) => println!("The game could not be started: {error}"),
+    }
 }
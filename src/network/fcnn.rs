@@ -1,7 +1,10 @@
-use ndarray::{Array1, Array2};
+use ndarray::{Array1, Array2, Axis, Zip, concatenate};
+use rand::Rng;
+use rayon::prelude::*;
 
-type MathFn = fn(f64) -> f64;
-type MathFnClosure<'a> = Box<dyn Fn(f64) -> f64 + 'a>;
+use crate::network::loss::Loss;
+
+type MathFnClosure<'a> = Box<dyn Fn(f64) -> f64 + Send + Sync + 'a>;
 
 pub enum MLFunction<'a> {
     Linear,
@@ -10,11 +13,57 @@ pub enum MLFunction<'a> {
     ReLU,
     LeakyReLU,
     ELU,
+    /// Normalises the whole layer to probabilities, so it is only used as output function
+    Softmax,
     Custom(MathFnClosure<'a>, MathFnClosure<'a>), // Custom function and derivative
 }
 
 impl<'a> MLFunction<'a> {
-    fn as_fn_pair(&self) -> (MathFnClosure, MathFnClosure) {
+    /// Whether the function is rectifying, so that its layer is initialised for it
+    fn is_rectifier(&self) -> bool {
+        matches!(
+            self,
+            MLFunction::ReLU | MLFunction::LeakyReLU | MLFunction::ELU
+        )
+    }
+
+    /// Applies the function to the weighted sums of a batch with one sample per row
+    fn activate(&self, sums: &Array2<f64>) -> Array2<f64> {
+        match self {
+            MLFunction::Softmax => {
+                let mut result = sums.clone();
+                for mut row in result.rows_mut() {
+                    let max = row.fold(f64::NEG_INFINITY, |a, b| a.max(*b));
+                    row.mapv_inplace(|x| (x - max).exp());
+                    let sum = row.sum();
+                    row /= sum;
+                }
+                result
+            }
+            _ => sums.mapv(self.as_fn_pair().0),
+        }
+    }
+
+    /// The gradient with respect to the weighted sums from the gradient with respect to the
+    /// activations
+    fn backpropagate(
+        &self,
+        sums: &Array2<f64>,
+        activations: &Array2<f64>,
+        gradient: Array2<f64>,
+    ) -> Array2<f64> {
+        match self {
+            MLFunction::Softmax => {
+                let weighted = (&gradient * activations)
+                    .sum_axis(Axis(1))
+                    .insert_axis(Axis(1));
+                (gradient - weighted) * activations
+            }
+            _ => gradient * sums.mapv(self.as_fn_pair().1),
+        }
+    }
+
+    fn as_fn_pair(&self) -> (MathFnClosure<'_>, MathFnClosure<'_>) {
         match self {
             MLFunction::Linear => (Box::new(|x| x), Box::new(|_| 1.0)),
             MLFunction::Sigmoid => (
@@ -25,7 +74,7 @@ impl<'a> MLFunction<'a> {
                 }),
             ),
             MLFunction::Tanh => (
-                Box::new(|x| tanh_norm(x)),
+                Box::new(tanh_norm),
                 Box::new(|x| 0.5 - x.tanh().powi(2) / 2.0),
             ),
             MLFunction::ReLU => (
@@ -40,6 +89,7 @@ impl<'a> MLFunction<'a> {
                 Box::new(elu),
                 Box::new(|x| if x > 0.0 { 1.0 } else { x.exp() }),
             ),
+            MLFunction::Softmax => unreachable!("softmax is applied to whole layers"),
             MLFunction::Custom(f, df) => (Box::new(f), Box::new(df)),
         }
     }
@@ -58,21 +108,14 @@ fn relu(x: f64) -> f64 {
 }
 
 fn leaky_relu(x: f64) -> f64 {
-    if x > 0.0 {
-        x
-    } else {
-        0.01 * x
-    }
+    if x > 0.0 { x } else { 0.01 * x }
 }
 
 fn elu(x: f64) -> f64 {
-    if x > 0.0 {
-        x
-    } else {
-        x.exp() - 1.0
-    }
+    if x > 0.0 { x } else { x.exp() - 1.0 }
 }
 
+#[derive(Clone)]
 pub struct FCNN<'a> {
     weights: Vec<Array2<f64>>,
     biases: Vec<Array1<f64>>,
@@ -80,19 +123,89 @@ pub struct FCNN<'a> {
     heights: Vec<usize>,
 }
 
+/// Gradients of, or steps for, the weights and biases of every layer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradients {
+    pub weights: Vec<Array2<f64>>,
+    pub biases: Vec<Array1<f64>>,
+}
+
+impl Gradients {
+    /// All zero, in the shape of these
+    pub fn zeros_like(&self) -> Gradients {
+        Gradients {
+            weights: self
+                .weights
+                .iter()
+                .map(|w| Array2::zeros(w.raw_dim()))
+                .collect(),
+            biases: self
+                .biases
+                .iter()
+                .map(|b| Array1::zeros(b.raw_dim()))
+                .collect(),
+        }
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &f64> {
+        self.weights
+            .iter()
+            .flat_map(|w| w.iter())
+            .chain(self.biases.iter().flat_map(|b| b.iter()))
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut f64> {
+        self.weights
+            .iter_mut()
+            .flat_map(|w| w.iter_mut())
+            .chain(self.biases.iter_mut().flat_map(|b| b.iter_mut()))
+    }
+
+    /// The euclidean norm over all values
+    pub fn norm(&self) -> f64 {
+        self.values().map(|v| v * v).sum::<f64>().sqrt()
+    }
+}
+
+/// The activations of every layer, starting with the input, and the weighted sums before the
+/// function of every layer, for a batch with one sample per row
+struct Forward {
+    activations: Vec<Array2<f64>>,
+    sums: Vec<Array2<f64>>,
+}
+
 impl<'a> FCNN<'a> {
-    pub fn new(heights: Vec<usize>, output_func: &'a MLFunction<'a>) -> Self {
+    /// Hidden layers use ReLU, the last layer the output function.
+    ///
+    /// Weights are drawn uniformly with He scaling before rectifying functions and Xavier scaling
+    /// before all others, biases start at zero.
+    pub fn new<R: Rng + ?Sized>(
+        heights: Vec<usize>,
+        output_func: &'a MLFunction<'a>,
+        rng: &mut R,
+    ) -> Self {
+        let layers = heights.len() - 1;
         let mut weights = Vec::new();
         let mut biases = Vec::new();
         let mut functions = Vec::new();
-        for i in 0..heights.len() - 1 {
-            weights.push(Array2::zeros((heights[i + 1], heights[i])));
-            biases.push(Array1::zeros(heights[i + 1]));
-            functions.push(if i < heights.len() - 1 {
+        for i in 0..layers {
+            let function = if i < layers - 1 {
                 &MLFunction::ReLU
             } else {
-                &output_func
-            });
+                output_func
+            };
+            let (fan_in, fan_out) = (heights[i] as f64, heights[i + 1] as f64);
+            let limit = if function.is_rectifier() {
+                (6.0 / fan_in).sqrt()
+            } else {
+                (6.0 / (fan_in + fan_out)).sqrt()
+            };
+            weights.push(Array2::from_shape_simple_fn(
+                (heights[i + 1], heights[i]),
+                || rng.random_range(-limit..=limit),
+            ));
+            biases.push(Array1::zeros(heights[i + 1]));
+            functions.push(function);
         }
         Self {
             weights,
@@ -102,65 +215,302 @@ impl<'a> FCNN<'a> {
         }
     }
 
-    pub fn new_softmax(heights: Vec<usize>) -> Self {
-        Self::new(heights, &MLFunction::Tanh)
+    pub fn new_softmax<R: Rng + ?Sized>(heights: Vec<usize>, rng: &mut R) -> Self {
+        Self::new(heights, &MLFunction::Softmax, rng)
+    }
+
+    fn forward(&self, inputs: &Array2<f64>) -> Forward {
+        if inputs.ncols() != self.heights[0] {
+            panic!("Input size does not match the input layer size!");
+        }
+        let mut activations = vec![inputs.clone()];
+        let mut sums = Vec::new();
+        for i in 0..self.heights.len() - 1 {
+            let sum = activations[i].dot(&self.weights[i].t()) + &self.biases[i];
+            activations.push(self.functions[i].activate(&sum));
+            sums.push(sum);
+        }
+        Forward { activations, sums }
+    }
+
+    /// A network from its parts, which are expected to fit together
+    pub(super) fn from_parts(
+        heights: Vec<usize>,
+        functions: Vec<&'a MLFunction<'a>>,
+        weights: Vec<Array2<f64>>,
+        biases: Vec<Array1<f64>>,
+    ) -> Self {
+        FCNN {
+            weights,
+            biases,
+            functions,
+            heights,
+        }
+    }
+
+    /// The number of neurons of every layer, starting with the input
+    pub fn heights(&self) -> &[usize] {
+        &self.heights
+    }
+
+    /// The function of every layer after the input
+    pub fn functions(&self) -> &[&'a MLFunction<'a>] {
+        &self.functions
+    }
+
+    pub fn weights(&self) -> &[Array2<f64>] {
+        &self.weights
+    }
+
+    pub fn biases(&self) -> &[Array1<f64>] {
+        &self.biases
+    }
+
+    /// Subtracts the step from the weights and biases
+    pub fn update(&mut self, step: &Gradients) {
+        for (weights, step) in self.weights.iter_mut().zip(&step.weights) {
+            Zip::from(weights).and(step).for_each(|w, s| *w -= s);
+        }
+        for (biases, step) in self.biases.iter_mut().zip(&step.biases) {
+            Zip::from(biases).and(step).for_each(|b, s| *b -= s);
+        }
+    }
+
+    /// The loss of the outputs, averaged over the rows of the batch
+    pub fn loss(&self, inputs: &Array2<f64>, targets: &Array2<f64>, loss: &Loss) -> f64 {
+        let outputs = self.forward(inputs).activations.pop();
+        loss.value(&outputs.expect("there is an output layer"), targets)
+    }
+
+    /// The gradients of the loss, averaged over the rows of the batch, for the weights and
+    /// biases of every layer
+    pub fn gradients(&self, inputs: &Array2<f64>, targets: &Array2<f64>, loss: &Loss) -> Gradients {
+        if targets.ncols() != self.heights[self.heights.len() - 1]
+            || targets.nrows() != inputs.nrows()
+        {
+            panic!("Input or target size does not match the network architecture!");
+        }
+        let Forward { activations, sums } = self.forward(inputs);
+        let layers = self.heights.len() - 1;
+        let outputs = &activations[layers];
+        let delta = match (loss, self.functions[layers - 1]) {
+            // The softmax Jacobian cancels against the cross-entropy gradient
+            (Loss::CrossEntropy, MLFunction::Softmax) => outputs - targets,
+            (loss, function) => {
+                function.backpropagate(&sums[layers - 1], outputs, loss.gradient(outputs, targets))
+            }
+        };
+        self.backward_from(&Forward { activations, sums }, delta).0
+    }
+
+    /// The gradients for a loss given by its gradient with respect to the outputs of every row.
+    /// Returns the gradients averaged over the rows and the gradient of every row with respect
+    /// to its inputs, to train a network that feeds into this one.
+    pub fn backward(
+        &self,
+        inputs: &Array2<f64>,
+        output_gradient: Array2<f64>,
+    ) -> (Gradients, Array2<f64>) {
+        let forward = self.forward(inputs);
+        let layers = self.heights.len() - 1;
+        let delta = self.functions[layers - 1].backpropagate(
+            &forward.sums[layers - 1],
+            &forward.activations[layers],
+            output_gradient,
+        );
+        self.backward_from(&forward, delta)
+    }
+
+    /// Backpropagates the gradient with respect to the weighted sums of the output layer
+    fn backward_from(&self, forward: &Forward, mut delta: Array2<f64>) -> (Gradients, Array2<f64>) {
+        let Forward { activations, sums } = forward;
+        let samples = activations[0].nrows() as f64;
+        let layers = self.heights.len() - 1;
+        let mut weights = Vec::with_capacity(layers);
+        let mut biases = Vec::with_capacity(layers);
+        for i in (0..layers).rev() {
+            weights.push(delta.t().dot(&activations[i]) / samples);
+            biases.push(delta.sum_axis(Axis(0)) / samples);
+            let gradient = delta.dot(&self.weights[i]);
+            delta = match i {
+                0 => gradient,
+                _ => self.functions[i - 1].backpropagate(&sums[i - 1], &activations[i], gradient),
+            };
+        }
+        weights.reverse();
+        biases.reverse();
+        (Gradients { weights, biases }, delta)
     }
 }
 
 pub trait Predictor {
-    fn predict(&self, input: &Array1<f64>) -> Array1<f64>;
-}
+    /// The outputs of a batch with one sample per row
+    fn predict_batch(&self, inputs: &Array2<f64>) -> Array2<f64>;
 
-impl Predictor for FCNN<'_> {
     fn predict(&self, input: &Array1<f64>) -> Array1<f64> {
-        if input.len() != self.heights[0] {
-            panic!("Input size does not match the input layer size!");
-        }
-        let mut result = input.clone();
-        for i in 0..self.heights.len() - 1 {
-            result = self.weights[i].dot(&result) + &self.biases[i];
-            let (f, _) = self.functions[i].as_fn_pair();
-            result.mapv_inplace(f);
+        self.predict_batch(&input.view().insert_axis(Axis(0)).to_owned())
+            .remove_axis(Axis(0))
+    }
+
+    /// Like `predict_batch`, but splits the batch into blocks of `rows` that are predicted in
+    /// parallel
+    fn predict_parallel(&self, inputs: &Array2<f64>, rows: usize) -> Array2<f64>
+    where
+        Self: Sync,
+    {
+        if inputs.nrows() <= rows {
+            return self.predict_batch(inputs);
         }
-        1.0 / result.sum() * result // probability
+        let blocks: Vec<Array2<f64>> = inputs
+            .axis_chunks_iter(Axis(0), rows.max(1))
+            .into_par_iter()
+            .map(|block| self.predict_batch(&block.to_owned()))
+            .collect();
+        let views: Vec<_> = blocks.iter().map(|b| b.view()).collect();
+        concatenate(Axis(0), &views).expect("all blocks have the same outputs")
+    }
+}
+
+impl Predictor for FCNN<'_> {
+    fn predict_batch(&self, inputs: &Array2<f64>) -> Array2<f64> {
+        self.forward(inputs)
+            .activations
+            .pop()
+            .expect("there is an output layer")
     }
 }
 
 pub trait Trainer {
-    fn train(&mut self, input: &Array1<f64>, target: &Array1<f64>, learning_rate: f64);
+    /// One step of plain gradient descent on half the squared error of a batch with one sample
+    /// per row. See `Optimiser` for other losses and optimisers.
+    fn train_batch(&mut self, inputs: &Array2<f64>, targets: &Array2<f64>, learning_rate: f64);
+
+    fn train(&mut self, input: &Array1<f64>, target: &Array1<f64>, learning_rate: f64) {
+        self.train_batch(
+            &input.view().insert_axis(Axis(0)).to_owned(),
+            &target.view().insert_axis(Axis(0)).to_owned(),
+            learning_rate,
+        );
+    }
 }
 
 impl Trainer for FCNN<'_> {
-    fn train(&mut self, input: &Array1<f64>, target: &Array1<f64>, learning_rate: f64) {
-        if input.len() != self.heights[0] || target.len() != self.heights[self.heights.len() - 1] {
-            panic!("Input or target size does not match the network architecture!");
+    fn train_batch(&mut self, inputs: &Array2<f64>, targets: &Array2<f64>, learning_rate: f64) {
+        let mut step = self.gradients(inputs, targets, &Loss::MeanSquared);
+        step.values_mut().for_each(|v| *v *= learning_rate);
+        self.update(&step);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::game::GameRng;
+
+    fn batch(rng: &mut GameRng, rows: usize, cols: usize) -> Array2<f64> {
+        Array2::from_shape_simple_fn((rows, cols), || rng.random_range(-1.0..1.0))
+    }
+
+    /// Rows of probabilities
+    fn distributions(rng: &mut GameRng, rows: usize, cols: usize) -> Array2<f64> {
+        let mut batch = batch(rng, rows, cols).mapv(f64::exp);
+        for mut row in batch.rows_mut() {
+            row /= row.sum();
         }
+        batch
+    }
 
-        let mut outputs = Vec::new();
-        let mut derivs = Vec::new();
-        outputs.push(input.clone());
-        for i in 0..self.heights.len() - 1 {
-            let w_sum = self.weights[i].dot(&outputs[i]) + &self.biases[i];
-            let (f, df) = self.functions[i].as_fn_pair();
-            outputs.push(w_sum.mapv(f));
-            derivs.push(w_sum.mapv(df));
-        }
-
-        let mut delta = target - &outputs[outputs.len() - 1];
-        for i in (0..self.heights.len() - 1).rev() {
-            let inner_delta = &delta / &derivs[i]; // deriv of the nth hidden layer
-            self.biases[i] = &self.biases[i] + &inner_delta * learning_rate;
-
-            delta.map_inplace(|x| *x = 0.0); // reset delta
-            let (J, K) = self.weights[i].dim();
-            for j in 0..J {
-                for k in 0..K {
-                    delta[j] += inner_delta[j] / self.weights[i][(j, k)];
-                    self.weights[i][(j, k)] += inner_delta[j] / outputs[i][k] * learning_rate;
+    #[test]
+    fn gradients_match_finite_differences() {
+        let mut rng = GameRng::seed_from_u64(0);
+        let cases = [
+            (MLFunction::Sigmoid, Loss::MeanSquared),
+            (MLFunction::Tanh, Loss::Huber { delta: 0.3 }),
+            (MLFunction::ELU, Loss::MeanSquared),
+            (MLFunction::Softmax, Loss::MeanSquared),
+            (MLFunction::Softmax, Loss::CrossEntropy),
+        ];
+        for (output, loss) in cases {
+            let mut network = FCNN::new(vec![3, 5, 4, 2], &output, &mut rng);
+            let (inputs, targets) = (batch(&mut rng, 6, 3), distributions(&mut rng, 6, 2));
+            let gradients = network.gradients(&inputs, &targets, &loss);
+
+            let epsilon = 1e-6;
+            for layer in 0..network.weights.len() {
+                for (index, analytic) in gradients.weights[layer].indexed_iter() {
+                    let original = network.weights[layer][index];
+                    network.weights[layer][index] = original + epsilon;
+                    let above = network.loss(&inputs, &targets, &loss);
+                    network.weights[layer][index] = original - epsilon;
+                    let below = network.loss(&inputs, &targets, &loss);
+                    network.weights[layer][index] = original;
+
+                    let numeric = (above - below) / (2.0 * epsilon);
+                    assert!((numeric - analytic).abs() < 1e-6, "{numeric} != {analytic}");
+                }
+                for (index, analytic) in gradients.biases[layer].iter().enumerate() {
+                    let original = network.biases[layer][index];
+                    network.biases[layer][index] = original + epsilon;
+                    let above = network.loss(&inputs, &targets, &loss);
+                    network.biases[layer][index] = original - epsilon;
+                    let below = network.loss(&inputs, &targets, &loss);
+                    network.biases[layer][index] = original;
+
+                    let numeric = (above - below) / (2.0 * epsilon);
+                    assert!((numeric - analytic).abs() < 1e-6, "{numeric} != {analytic}");
                 }
             }
+        }
+    }
 
-            delta = delta / self.heights[i] as f64; // renormalize delta with height of layer i-1. This approximates the inverse matrix of the weights.
+    #[test]
+    fn learns_a_function() {
+        let mut rng = GameRng::seed_from_u64(1);
+        let mut network = FCNN::new(vec![2, 16, 1], &MLFunction::Linear, &mut rng);
+        let inputs = batch(&mut rng, 64, 2);
+        let targets = inputs
+            .map_axis(Axis(1), |row| row[0] * row[1] + row[0])
+            .insert_axis(Axis(1));
+
+        let before = network.loss(&inputs, &targets, &Loss::MeanSquared);
+        for _ in 0..2000 {
+            network.train_batch(&inputs, &targets, 0.1);
+        }
+        assert!(network.loss(&inputs, &targets, &Loss::MeanSquared) < before / 10.0);
+
+        // Values are predicted as they are, only softmax outputs are probabilities
+        let input = inputs.row(0).to_owned();
+        assert!((network.predict(&input)[0] - targets[(0, 0)]).abs() < 0.5);
+        let softmax = FCNN::new_softmax(vec![2, 4, 3], &mut rng);
+        assert!((softmax.predict(&input).sum() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn batches_match_single_predictions() {
+        let mut rng = GameRng::seed_from_u64(3);
+        let network = FCNN::new(vec![5, 8, 3], &MLFunction::Sigmoid, &mut rng);
+        let inputs = batch(&mut rng, 37, 5);
+        let outputs = network.predict_batch(&inputs);
+        for (input, output) in inputs.rows().into_iter().zip(outputs.rows()) {
+            let single = network.predict(&input.to_owned());
+            assert!(
+                single
+                    .iter()
+                    .zip(output)
+                    .all(|(a, b)| (a - b).abs() < 1e-12)
+            );
+        }
+        for rows in [1, 10, 37, 100] {
+            let parallel = network.predict_parallel(&inputs, rows);
+            assert_eq!(parallel.dim(), outputs.dim());
+            assert!(
+                parallel
+                    .iter()
+                    .zip(&outputs)
+                    .all(|(a, b)| (a - b).abs() < 1e-12)
+            );
         }
     }
 }
@@ -0,0 +1,44 @@
+use ndarray::Array2;
+
+/// Outputs are never taken to be below this in the logarithm of the cross-entropy
+const MIN_PROBABILITY: f64 = 1e-12;
+
+/// The error of the outputs of a batch with one sample per row
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Loss {
+    /// Half the squared error
+    MeanSquared,
+    /// Half the squared error up to `delta`, growing linearly beyond it
+    Huber { delta: f64 },
+    /// The cross-entropy of target and output distributions, for softmax outputs
+    CrossEntropy,
+}
+
+impl Loss {
+    /// The loss summed over the outputs and averaged over the rows
+    pub fn value(&self, outputs: &Array2<f64>, targets: &Array2<f64>) -> f64 {
+        let total = match self {
+            Loss::MeanSquared => (outputs - targets).mapv(|e| e * e / 2.0).sum(),
+            Loss::Huber { delta } => (outputs - targets)
+                .mapv(|e| {
+                    if e.abs() <= *delta {
+                        e * e / 2.0
+                    } else {
+                        delta * (e.abs() - delta / 2.0)
+                    }
+                })
+                .sum(),
+            Loss::CrossEntropy => -(targets * &outputs.mapv(|o| o.max(MIN_PROBABILITY).ln())).sum(),
+        };
+        total / outputs.nrows() as f64
+    }
+
+    /// The gradient of the loss of every row with respect to its outputs
+    pub fn gradient(&self, outputs: &Array2<f64>, targets: &Array2<f64>) -> Array2<f64> {
+        match self {
+            Loss::MeanSquared => outputs - targets,
+            Loss::Huber { delta } => (outputs - targets).mapv(|e| e.clamp(-delta, *delta)),
+            Loss::CrossEntropy => -(targets / &outputs.mapv(|o| o.max(MIN_PROBABILITY))),
+        }
+    }
+}
@@ -1 +1,5 @@
 pub mod fcnn;
+pub mod loss;
+pub mod model;
+pub mod optimiser;
+pub mod policy_value;
@@ -0,0 +1,409 @@
+//! A binary file format for trained networks.
+//!
+//! All numbers are little endian:
+//!
+//! | Field     | Type                 | Content                                          |
+//! |-----------|----------------------|--------------------------------------------------|
+//! | magic     | 4 bytes              | `FCNN`                                           |
+//! | version   | `u32`                | `VERSION`                                        |
+//! | encoder   | `u32`                | the `encoder::VERSION` of the inputs             |
+//! | layers    | `u32`                | the number of layers `n`, including the input    |
+//! | heights   | `n` × `u64`          | the neurons of every layer                       |
+//! | functions | `n - 1` × `u8`       | the function of every layer after the input      |
+//! | values    | `f64`                | per layer the weights row by row, then biases    |
+//! | checksum  | `u64`                | FNV-1a of all bytes before it                    |
+//!
+//! Weights of a layer have one row per neuron and one column per neuron of the layer before.
+//! Functions are numbered `Linear` 0, `Sigmoid` 1, `Tanh` 2, `ReLU` 3, `LeakyReLU` 4, `ELU` 5
+//! and `Softmax` 6. Networks with `Custom` functions cannot be written.
+//!
+//! Networks are rejected when they were written for another version of the encoder, as their
+//! inputs would mean something else.
+//!
+//! A `PolicyValueNetwork` is written as the magic `PVNN` and `VERSION`, followed by its trunk,
+//! policy head and value head. Each of them is a `u64` with its length in bytes and a network in
+//! the format above.
+
+use std::{error::Error, fmt, io, path::Path};
+
+use ndarray::{Array1, Array2};
+
+use crate::{
+    agent::encoder::VERSION as ENCODER_VERSION,
+    network::{
+        fcnn::{FCNN, MLFunction},
+        policy_value::PolicyValueNetwork,
+    },
+};
+
+pub const MAGIC: [u8; 4] = *b"FCNN";
+
+pub const POLICY_VALUE_MAGIC: [u8; 4] = *b"PVNN";
+
+/// Changes whenever the layout of the format changes
+pub const VERSION: u32 = 2;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+fn function_code(function: &MLFunction) -> Option<u8> {
+    match function {
+        MLFunction::Linear => Some(0),
+        MLFunction::Sigmoid => Some(1),
+        MLFunction::Tanh => Some(2),
+        MLFunction::ReLU => Some(3),
+        MLFunction::LeakyReLU => Some(4),
+        MLFunction::ELU => Some(5),
+        MLFunction::Softmax => Some(6),
+        MLFunction::Custom(_, _) => None,
+    }
+}
+
+fn function_by_code(code: u8) -> Option<&'static MLFunction<'static>> {
+    match code {
+        0 => Some(&MLFunction::Linear),
+        1 => Some(&MLFunction::Sigmoid),
+        2 => Some(&MLFunction::Tanh),
+        3 => Some(&MLFunction::ReLU),
+        4 => Some(&MLFunction::LeakyReLU),
+        5 => Some(&MLFunction::ELU),
+        6 => Some(&MLFunction::Softmax),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+pub enum ModelError {
+    Io(io::Error),
+    /// The layer has a `Custom` function, which cannot be written
+    CustomFunction(usize),
+    /// The data does not start with `MAGIC`
+    NotAModel,
+    UnsupportedVersion(u32),
+    /// The network was trained on inputs of another encoder version
+    EncoderMismatch(u32),
+    ChecksumMismatch,
+    /// The network does not take the input it is used for
+    IncompatibleInput {
+        expected: usize,
+        found: usize,
+    },
+    /// The data is cut short, too long or describes an invalid network
+    Malformed(String),
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelError::Io(error) => write!(f, "The model could not be read or written: {error}"),
+            ModelError::CustomFunction(layer) => {
+                write!(
+                    f,
+                    "Layer {layer} has a custom function, which cannot be saved"
+                )
+            }
+            ModelError::NotAModel => write!(f, "The data is not a model"),
+            ModelError::UnsupportedVersion(version) => write!(
+                f,
+                "The model has version {version}, but only version {VERSION} is supported"
+            ),
+            ModelError::EncoderMismatch(version) => write!(
+                f,
+                "The model takes inputs of encoder version {version}, but the encoder has version {ENCODER_VERSION}"
+            ),
+            ModelError::ChecksumMismatch => write!(f, "The checksum of the model does not match"),
+            ModelError::IncompatibleInput { expected, found } => write!(
+                f,
+                "The model takes {found} inputs, but {expected} are given"
+            ),
+            ModelError::Malformed(message) => write!(f, "The model is malformed: {message}"),
+        }
+    }
+}
+
+impl Error for ModelError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ModelError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the values of the format from the front of the data
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], ModelError> {
+        if self.bytes.len() < N {
+            return Err(ModelError::Malformed("unexpected end of data".to_string()));
+        }
+        let (value, rest) = self.bytes.split_at(N);
+        self.bytes = rest;
+        Ok(value.try_into().expect("the length is checked"))
+    }
+
+    fn u32(&mut self) -> Result<u32, ModelError> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, ModelError> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn f64(&mut self) -> Result<f64, ModelError> {
+        self.take().map(f64::from_le_bytes)
+    }
+
+    fn values(&mut self, count: usize) -> Result<Vec<f64>, ModelError> {
+        if self.bytes.len() / 8 < count {
+            return Err(ModelError::Malformed("unexpected end of data".to_string()));
+        }
+        (0..count).map(|_| self.f64()).collect()
+    }
+}
+
+impl FCNN<'_> {
+    /// The network in the model format
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ModelError> {
+        let codes = self
+            .functions()
+            .iter()
+            .enumerate()
+            .map(|(layer, function)| {
+                function_code(function).ok_or(ModelError::CustomFunction(layer + 1))
+            })
+            .collect::<Result<Vec<u8>, _>>()?;
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend(ENCODER_VERSION.to_le_bytes());
+        bytes.extend((self.heights().len() as u32).to_le_bytes());
+        for height in self.heights() {
+            bytes.extend((*height as u64).to_le_bytes());
+        }
+        bytes.extend(codes);
+        for (weights, biases) in self.weights().iter().zip(self.biases()) {
+            for value in weights.iter().chain(biases) {
+                bytes.extend(value.to_le_bytes());
+            }
+        }
+        bytes.extend(checksum(&bytes).to_le_bytes());
+        Ok(bytes)
+    }
+
+    /// A network in the model format
+    pub fn from_bytes(bytes: &[u8]) -> Result<FCNN<'static>, ModelError> {
+        if !bytes.starts_with(&MAGIC) {
+            return Err(ModelError::NotAModel);
+        }
+        let mut reader = Reader { bytes: &bytes[4..] };
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(ModelError::UnsupportedVersion(version));
+        }
+        let encoder = reader.u32()?;
+        if encoder != ENCODER_VERSION {
+            return Err(ModelError::EncoderMismatch(encoder));
+        }
+        let Some((data, expected)) = bytes.split_last_chunk::<8>() else {
+            return Err(ModelError::Malformed("unexpected end of data".to_string()));
+        };
+        if checksum(data) != u64::from_le_bytes(*expected) {
+            return Err(ModelError::ChecksumMismatch);
+        }
+        reader.bytes = &data[12..];
+
+        let layers = reader.u32()? as usize;
+        if layers < 2 {
+            return Err(ModelError::Malformed(format!(
+                "{layers} layers, but at least 2 are needed"
+            )));
+        }
+        let heights = (0..layers)
+            .map(|_| {
+                let height = reader.u64()?;
+                usize::try_from(height)
+                    .ok()
+                    .filter(|h| *h > 0)
+                    .ok_or_else(|| ModelError::Malformed(format!("a layer of height {height}")))
+            })
+            .collect::<Result<Vec<usize>, _>>()?;
+        let functions = (0..layers - 1)
+            .map(|_| {
+                let [code] = reader.take()?;
+                function_by_code(code)
+                    .ok_or_else(|| ModelError::Malformed(format!("unknown function {code}")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut weights = Vec::with_capacity(layers - 1);
+        let mut biases = Vec::with_capacity(layers - 1);
+        for shape in heights.windows(2).map(|h| (h[1], h[0])) {
+            let count = shape.0.checked_mul(shape.1).ok_or_else(|| {
+                ModelError::Malformed(format!("a layer of {} × {} weights", shape.0, shape.1))
+            })?;
+            let values = reader.values(count)?;
+            weights.push(Array2::from_shape_vec(shape, values).expect("the size is read"));
+            biases.push(Array1::from(reader.values(shape.0)?));
+        }
+        if !reader.bytes.is_empty() {
+            return Err(ModelError::Malformed(format!(
+                "{} bytes after the values",
+                reader.bytes.len()
+            )));
+        }
+        Ok(FCNN::from_parts(heights, functions, weights, biases))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ModelError> {
+        std::fs::write(path, self.to_bytes()?).map_err(ModelError::Io)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<FCNN<'static>, ModelError> {
+        FCNN::from_bytes(&std::fs::read(path).map_err(ModelError::Io)?)
+    }
+}
+
+impl PolicyValueNetwork<'_> {
+    /// The network in the model format
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ModelError> {
+        let mut bytes = POLICY_VALUE_MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        for part in [self.trunk(), self.policy(), self.value()] {
+            let part = part.to_bytes()?;
+            bytes.extend((part.len() as u64).to_le_bytes());
+            bytes.extend(part);
+        }
+        Ok(bytes)
+    }
+
+    /// A network in the model format
+    pub fn from_bytes(bytes: &[u8]) -> Result<PolicyValueNetwork<'static>, ModelError> {
+        if !bytes.starts_with(&POLICY_VALUE_MAGIC) {
+            return Err(ModelError::NotAModel);
+        }
+        let mut reader = Reader { bytes: &bytes[4..] };
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(ModelError::UnsupportedVersion(version));
+        }
+        let mut part = || {
+            let length = usize::try_from(reader.u64()?).unwrap_or(usize::MAX);
+            if reader.bytes.len() < length {
+                return Err(ModelError::Malformed("unexpected end of data".to_string()));
+            }
+            let (part, rest) = reader.bytes.split_at(length);
+            reader.bytes = rest;
+            FCNN::from_bytes(part)
+        };
+        let (trunk, policy, value) = (part()?, part()?, part()?);
+        if !reader.bytes.is_empty() {
+            return Err(ModelError::Malformed(format!(
+                "{} bytes after the value head",
+                reader.bytes.len()
+            )));
+        }
+        let features = trunk.heights()[trunk.heights().len() - 1];
+        if policy.heights()[0] != features || value.heights()[0] != features {
+            return Err(ModelError::Malformed(
+                "the heads do not take the outputs of the trunk".to_string(),
+            ));
+        }
+        Ok(PolicyValueNetwork::from_parts(trunk, policy, value))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ModelError> {
+        std::fs::write(path, self.to_bytes()?).map_err(ModelError::Io)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<PolicyValueNetwork<'static>, ModelError> {
+        PolicyValueNetwork::from_bytes(&std::fs::read(path).map_err(ModelError::Io)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{game::GameRng, network::fcnn::Predictor};
+
+    #[test]
+    fn round_trip() {
+        let mut rng = GameRng::seed_from_u64(0);
+        let network = FCNN::new(vec![4, 6, 3], &MLFunction::Softmax, &mut rng);
+        let bytes = network.to_bytes().unwrap();
+        let loaded = FCNN::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.heights(), network.heights());
+        assert_eq!(loaded.weights(), network.weights());
+        assert_eq!(loaded.biases(), network.biases());
+        let input = Array1::from(vec![0.5, -1.0, 0.25, 2.0]);
+        assert_eq!(loaded.predict(&input), network.predict(&input));
+
+        let mut corrupted = bytes.clone();
+        corrupted[30] ^= 1;
+        assert!(matches!(
+            FCNN::from_bytes(&corrupted),
+            Err(ModelError::ChecksumMismatch)
+        ));
+        assert!(matches!(
+            FCNN::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(ModelError::ChecksumMismatch)
+        ));
+        let mut future = bytes.clone();
+        future[4] = 9;
+        assert!(matches!(
+            FCNN::from_bytes(&future),
+            Err(ModelError::UnsupportedVersion(9))
+        ));
+        let mut other_encoder = bytes;
+        other_encoder[8..12].copy_from_slice(&(ENCODER_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            FCNN::from_bytes(&other_encoder),
+            Err(ModelError::EncoderMismatch(v)) if v == ENCODER_VERSION + 1
+        ));
+
+        let network = PolicyValueNetwork::new(vec![4, 5], 3, 2, &mut rng);
+        let bytes = network.to_bytes().unwrap();
+        let loaded = PolicyValueNetwork::from_bytes(&bytes).unwrap();
+        let inputs = input.insert_axis(ndarray::Axis(0));
+        assert_eq!(
+            loaded.predict_batch(&inputs),
+            network.predict_batch(&inputs)
+        );
+        assert!(PolicyValueNetwork::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        let custom = MLFunction::Custom(Box::new(|x| x), Box::new(|_| 1.0));
+        let network = FCNN::new(vec![2, 2], &custom, &mut rng);
+        assert!(matches!(
+            network.to_bytes(),
+            Err(ModelError::CustomFunction(1))
+        ));
+    }
+
+    #[test]
+    fn oversized_layers_are_malformed() {
+        // Two layers whose weights cannot be counted in a usize
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend(ENCODER_VERSION.to_le_bytes());
+        bytes.extend(2u32.to_le_bytes());
+        bytes.extend(u64::MAX.to_le_bytes());
+        bytes.extend(3u64.to_le_bytes());
+        bytes.push(0);
+        bytes.extend(checksum(&bytes).to_le_bytes());
+        assert!(matches!(
+            FCNN::from_bytes(&bytes),
+            Err(ModelError::Malformed(_))
+        ));
+    }
+}
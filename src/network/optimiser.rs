@@ -0,0 +1,178 @@
+use ndarray::Array2;
+
+use crate::network::{
+    fcnn::{FCNN, Gradients},
+    loss::Loss,
+};
+
+/// Keeps the adaptive optimisers from dividing by zero
+const EPSILON: f64 = 1e-8;
+
+/// How gradients are turned into steps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    /// Gradient descent, where `momentum` keeps that part of the previous step
+    Sgd { momentum: f64 },
+    /// Divides by a moving average of the squared gradients
+    RmsProp { decay: f64 },
+    /// Moving averages of the gradients and their squares with bias correction
+    Adam { beta1: f64, beta2: f64 },
+}
+
+impl Method {
+    pub fn adam() -> Self {
+        Method::Adam {
+            beta1: 0.9,
+            beta2: 0.999,
+        }
+    }
+}
+
+/// Trains a network on batches with one sample per row
+#[derive(Debug, Clone)]
+pub struct Optimiser {
+    pub method: Method,
+    pub loss: Loss,
+    pub learning_rate: f64,
+    /// Weight decay added to the gradients of the weights, but not the biases
+    pub l2: f64,
+    /// Scales the gradients down to at most this norm
+    pub clip: Option<f64>,
+    first: Option<Gradients>,
+    second: Option<Gradients>,
+    steps: i32,
+}
+
+impl Optimiser {
+    pub fn new(method: Method, loss: Loss, learning_rate: f64) -> Self {
+        Optimiser {
+            method,
+            loss,
+            learning_rate,
+            l2: 0.0,
+            clip: None,
+            first: None,
+            second: None,
+            steps: 0,
+        }
+    }
+
+    /// Forgets the moving averages, as for a new network
+    pub fn reset(&mut self) {
+        self.first = None;
+        self.second = None;
+        self.steps = 0;
+    }
+
+    /// One step on the batch. Returns the loss before the step.
+    pub fn step(&mut self, network: &mut FCNN, inputs: &Array2<f64>, targets: &Array2<f64>) -> f64 {
+        let loss = network.loss(inputs, targets, &self.loss);
+        let gradients = network.gradients(inputs, targets, &self.loss);
+        self.apply(network, gradients);
+        loss
+    }
+
+    /// One step with gradients that were computed elsewhere, such as for a loss of its own
+    pub fn apply(&mut self, network: &mut FCNN, mut gradients: Gradients) {
+        if self.l2 > 0.0 {
+            for (gradient, weights) in gradients.weights.iter_mut().zip(network.weights()) {
+                gradient.scaled_add(self.l2, weights);
+            }
+        }
+        if let Some(clip) = self.clip {
+            let norm = gradients.norm();
+            if norm > clip {
+                gradients.values_mut().for_each(|g| *g *= clip / norm);
+            }
+        }
+
+        self.steps += 1;
+        let first = self.first.get_or_insert_with(|| gradients.zeros_like());
+        let second = self.second.get_or_insert_with(|| gradients.zeros_like());
+        let rate = self.learning_rate;
+        let mut step = gradients.zeros_like();
+        let values = step
+            .values_mut()
+            .zip(gradients.values())
+            .zip(first.values_mut().zip(second.values_mut()));
+        match self.method {
+            Method::Sgd { momentum } => {
+                for ((step, g), (velocity, _)) in values {
+                    *velocity = momentum * *velocity + g;
+                    *step = rate * *velocity;
+                }
+            }
+            Method::RmsProp { decay } => {
+                for ((step, g), (_, square)) in values {
+                    *square = decay * *square + (1.0 - decay) * g * g;
+                    *step = rate * g / (square.sqrt() + EPSILON);
+                }
+            }
+            Method::Adam { beta1, beta2 } => {
+                let correction1 = 1.0 - beta1.powi(self.steps);
+                let correction2 = 1.0 - beta2.powi(self.steps);
+                for ((step, g), (mean, square)) in values {
+                    *mean = beta1 * *mean + (1.0 - beta1) * g;
+                    *square = beta2 * *square + (1.0 - beta2) * g * g;
+                    *step =
+                        rate * (*mean / correction1) / ((*square / correction2).sqrt() + EPSILON);
+                }
+            }
+        }
+        network.update(&step);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Axis;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::{game::GameRng, network::fcnn::MLFunction};
+
+    #[test]
+    fn every_method_learns() {
+        let mut rng = GameRng::seed_from_u64(2);
+        let inputs = Array2::from_shape_simple_fn((32, 2), || rng.random_range(-1.0..1.0));
+
+        // The class is the quadrant of the input
+        let mut classes = Array2::zeros((32, 4));
+        for (row, input) in inputs.axis_iter(Axis(0)).enumerate() {
+            classes[(
+                row,
+                usize::from(input[0] > 0.0) * 2 + usize::from(input[1] > 0.0),
+            )] = 1.0;
+        }
+        let values = inputs
+            .map_axis(Axis(1), |row| row[0] - row[1])
+            .insert_axis(Axis(1));
+
+        let methods = [
+            Method::Sgd { momentum: 0.9 },
+            Method::RmsProp { decay: 0.9 },
+            Method::adam(),
+        ];
+        for method in methods {
+            let mut optimiser = Optimiser::new(method, Loss::CrossEntropy, 0.01);
+            optimiser.l2 = 1e-4;
+            optimiser.clip = Some(5.0);
+            let mut network = FCNN::new_softmax(vec![2, 16, 4], &mut rng);
+            let before = optimiser.step(&mut network, &inputs, &classes);
+            for _ in 0..300 {
+                optimiser.step(&mut network, &inputs, &classes);
+            }
+            let after = network.loss(&inputs, &classes, &Loss::CrossEntropy);
+            assert!(after < before / 2.0, "{method:?}: {before} -> {after}");
+
+            let mut optimiser = Optimiser::new(method, Loss::Huber { delta: 1.0 }, 0.01);
+            let mut network = FCNN::new(vec![2, 16, 1], &MLFunction::Linear, &mut rng);
+            let before = optimiser.step(&mut network, &inputs, &values);
+            for _ in 0..300 {
+                optimiser.step(&mut network, &inputs, &values);
+            }
+            let after = network.loss(&inputs, &values, &optimiser.loss);
+            assert!(after < before / 2.0, "{method:?}: {before} -> {after}");
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use ndarray::Array2;
+use rand::Rng;
+
+use crate::network::{
+    fcnn::{FCNN, Gradients, MLFunction, Predictor},
+    optimiser::Optimiser,
+};
+
+/// A policy head and a value head over a shared trunk.
+///
+/// The policy head outputs logits and the value head values between 0 and 1, as `MLFunction::Tanh`
+/// is scaled to that range.
+#[derive(Clone)]
+pub struct PolicyValueNetwork<'a> {
+    trunk: FCNN<'a>,
+    policy: FCNN<'a>,
+    value: FCNN<'a>,
+}
+
+/// The gradients of every part of a `PolicyValueNetwork`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyValueGradients {
+    pub trunk: Gradients,
+    pub policy: Gradients,
+    pub value: Gradients,
+}
+
+impl<'a> PolicyValueNetwork<'a> {
+    /// `trunk` holds the heights of the input and of the shared layers
+    pub fn new<R: Rng + ?Sized>(
+        trunk: Vec<usize>,
+        policy: usize,
+        values: usize,
+        rng: &mut R,
+    ) -> Self {
+        let features = *trunk.last().expect("the trunk has an input");
+        PolicyValueNetwork {
+            trunk: FCNN::new(trunk, &MLFunction::ReLU, rng),
+            policy: FCNN::new(vec![features, policy], &MLFunction::Linear, rng),
+            value: FCNN::new(vec![features, values], &MLFunction::Tanh, rng),
+        }
+    }
+
+    /// A network from its parts, which are expected to fit together
+    pub(super) fn from_parts(trunk: FCNN<'a>, policy: FCNN<'a>, value: FCNN<'a>) -> Self {
+        PolicyValueNetwork {
+            trunk,
+            policy,
+            value,
+        }
+    }
+
+    pub fn trunk(&self) -> &FCNN<'a> {
+        &self.trunk
+    }
+
+    pub fn policy(&self) -> &FCNN<'a> {
+        &self.policy
+    }
+
+    pub fn value(&self) -> &FCNN<'a> {
+        &self.value
+    }
+
+    /// The policy logits and the values of a batch with one sample per row
+    pub fn predict_batch(&self, inputs: &Array2<f64>) -> (Array2<f64>, Array2<f64>) {
+        let features = self.trunk.predict_batch(inputs);
+        (
+            self.policy.predict_batch(&features),
+            self.value.predict_batch(&features),
+        )
+    }
+
+    /// The gradients for a loss given by its gradients with respect to the policy logits and
+    /// the values of every row. The trunk is trained by both heads.
+    pub fn backward(
+        &self,
+        inputs: &Array2<f64>,
+        policy_gradient: Array2<f64>,
+        value_gradient: Array2<f64>,
+    ) -> PolicyValueGradients {
+        let features = self.trunk.predict_batch(inputs);
+        let (policy, policy_features) = self.policy.backward(&features, policy_gradient);
+        let (value, value_features) = self.value.backward(&features, value_gradient);
+        let (trunk, _) = self
+            .trunk
+            .backward(inputs, policy_features + value_features);
+        PolicyValueGradients {
+            trunk,
+            policy,
+            value,
+        }
+    }
+}
+
+/// An optimiser of its own for every part of a `PolicyValueNetwork`, as each keeps moving
+/// averages in the shape of its part
+#[derive(Debug, Clone)]
+pub struct PolicyValueOptimiser {
+    pub trunk: Optimiser,
+    pub policy: Optimiser,
+    pub value: Optimiser,
+}
+
+impl PolicyValueOptimiser {
+    pub fn new(optimiser: Optimiser) -> Self {
+        PolicyValueOptimiser {
+            trunk: optimiser.clone(),
+            policy: optimiser.clone(),
+            value: optimiser,
+        }
+    }
+
+    pub fn apply(&mut self, network: &mut PolicyValueNetwork, gradients: PolicyValueGradients) {
+        self.trunk.apply(&mut network.trunk, gradients.trunk);
+        self.policy.apply(&mut network.policy, gradients.policy);
+        self.value.apply(&mut network.value, gradients.value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{game::GameRng, network::loss::Loss};
+
+    #[test]
+    fn trunk_gradients_match_finite_differences() {
+        let mut rng = GameRng::seed_from_u64(0);
+        let inputs = Array2::from_shape_simple_fn((5, 3), || rng.random_range(-1.0..1.0));
+        let logits = Array2::from_shape_simple_fn((5, 4), || rng.random_range(-1.0..1.0));
+        let values = Array2::from_shape_simple_fn((5, 2), || rng.random_range(0.0..1.0));
+        let mut network = PolicyValueNetwork::new(vec![3, 6, 5], 4, 2, &mut rng);
+
+        // Half the squared error of both heads, averaged over the rows
+        let loss = |network: &PolicyValueNetwork| {
+            let (p, v) = network.predict_batch(&inputs);
+            Loss::MeanSquared.value(&p, &logits) + Loss::MeanSquared.value(&v, &values)
+        };
+        let (p, v) = network.predict_batch(&inputs);
+        // The values are on the scale of the targets
+        assert!(v.iter().all(|v| (0.0..1.0).contains(v)));
+        let gradients = network.backward(&inputs, p - &logits, v - &values);
+
+        let epsilon = 1e-6;
+        for layer in 0..2 {
+            for (index, analytic) in gradients.trunk.weights[layer].indexed_iter() {
+                let original = network.trunk.weights()[layer][index];
+                let mut step = gradients.trunk.zeros_like();
+                step.weights[layer][index] = -epsilon;
+                network.trunk.update(&step);
+                let above = loss(&network);
+                step.weights[layer][index] = 2.0 * epsilon;
+                network.trunk.update(&step);
+                let below = loss(&network);
+                step.weights[layer][index] = -epsilon;
+                network.trunk.update(&step);
+                assert!((network.trunk.weights()[layer][index] - original).abs() < 1e-12);
+
+                let numeric = (above - below) / (2.0 * epsilon);
+                assert!((numeric - analytic).abs() < 1e-6, "{numeric} != {analytic}");
+            }
+        }
+    }
+}
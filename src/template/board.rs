@@ -2,7 +2,8 @@ use crate::{game::Tile, template::{BoardTemplate, FieldTemplate, HomeTemplate}};
 
 use super::Position;
 
-pub const NORMAL: BoardTemplate<46, 30, 7> = BoardTemplate {
+pub const NORMAL: BoardTemplate<47, 30, 7> = BoardTemplate {
+    name: "NORMAL",
     fields: [
         FieldTemplate { position: Position(-4, 3), tile: Tile::Mountain, tunnelable: false, explorer_token: false },
         FieldTemplate { position: Position(-3, 3), tile: Tile::Farm, tunnelable: false, explorer_token: false },
@@ -50,6 +51,7 @@ pub const NORMAL: BoardTemplate<46, 30, 7> = BoardTemplate {
         FieldTemplate { position: Position(3, -2), tile: Tile::Mountain, tunnelable: false, explorer_token: true },
         FieldTemplate { position: Position(4, -2), tile: Tile::Tundra, tunnelable: false, explorer_token: false },
         
+        FieldTemplate { position: Position(-1, -3), tile: Tile::Village, tunnelable: false, explorer_token: false },
         FieldTemplate { position: Position(0, -3), tile: Tile::Lake, tunnelable: false, explorer_token: false },
         FieldTemplate { position: Position(1, -3), tile: Tile::Farm, tunnelable: false, explorer_token: false },
         FieldTemplate { position: Position(2, -3), tile: Tile::Mountain, tunnelable: false, explorer_token: true },
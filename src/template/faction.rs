@@ -56,3 +56,12 @@ pub const POLANIA: Faction = Faction {
     combat_power: CombatPower::Camaraderie,
     faction_ability: FactionAbility::Meander,
 };
+
+pub const FACTIONS: [Faction; 5] = [SAXONY, RUSVIET, NORDIC, CRIMEA, POLANIA];
+
+/// The faction with this name, ignoring case
+pub fn faction_by_name(name: &str) -> Option<Faction<'static>> {
+    FACTIONS
+        .into_iter()
+        .find(|faction| faction.name.eq_ignore_ascii_case(name))
+}
@@ -3,8 +3,11 @@ use crate::game::Tile;
 pub mod faction;
 pub mod player_mat;
 pub mod board;
+#[cfg(feature = "serde")]
+mod named;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Player<'a> {
     pub name: &'a str,
     pub bonus_starting_coins: u32,
@@ -12,7 +15,12 @@ pub struct Player<'a> {
     pub bonus_starting_popularity: u8,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(remote = "Self")
+)]
 pub struct Faction<'a> {
     pub name: &'a str,
     pub starting_power: u8,
@@ -26,6 +34,7 @@ pub struct Faction<'a> {
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MobilityPower {
     Underpass,
     Township,
@@ -35,6 +44,7 @@ pub enum MobilityPower {
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CombatPower {
     Disarm,
     PeoplesArmy,
@@ -44,6 +54,7 @@ pub enum CombatPower {
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FactionAbility {
     Relentless,
     Coercion,
@@ -53,7 +64,12 @@ pub enum FactionAbility {
 }
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(remote = "Self")
+)]
 pub struct PlayerMat<'a> {
     pub name: &'a str,
     pub starting_index: u8,
@@ -81,6 +97,7 @@ pub struct PlayerMat<'a> {
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PrimaryAction {
     Move,
     Tax,
@@ -92,6 +109,7 @@ pub enum PrimaryAction {
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SecondaryAction {
     Upgrade,
     Deploy,
@@ -101,6 +119,7 @@ pub enum SecondaryAction {
 
 #[derive(Debug)]
 pub struct BoardTemplate<const F: usize, const R: usize, const P: usize> {
+    pub name: &'static str,
     pub fields: [FieldTemplate; F],
     pub rivers: [(Position,Position); R],
     pub starting_locations: [HomeTemplate; P],
@@ -121,5 +140,31 @@ pub struct HomeTemplate {
     pub start2: Position,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
-pub struct Position(i8, i8);
\ No newline at end of file
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Position(i8, i8);
+
+/// Offsets of the six hex neighbours in axial coordinates
+const NEIGHBOURS: [(i8, i8); 6] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, -1), (-1, 1)];
+
+impl Position {
+    pub const fn new(x: i8, y: i8) -> Self {
+        Position(x, y)
+    }
+
+    pub fn x(&self) -> i8 {
+        self.0
+    }
+
+    pub fn y(&self) -> i8 {
+        self.1
+    }
+
+    pub fn neighbours(&self) -> [Position; 6] {
+        NEIGHBOURS.map(|(dx, dy)| Position(self.0.saturating_add(dx), self.1.saturating_add(dy)))
+    }
+
+    pub fn is_adjacent(&self, other: &Position) -> bool {
+        self.neighbours().contains(other)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,52 @@
+//! Templates are written by name if they are one of the known templates, and embedded otherwise.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+
+use crate::template::{
+    Faction, PlayerMat, faction::faction_by_name, player_mat::player_mat_by_name,
+};
+
+macro_rules! named_template {
+    ($template:ident, $by_name:ident, $kind:literal) => {
+        impl Serialize for $template<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                if $by_name(self.name).as_ref() == Some(self) {
+                    serializer.serialize_str(self.name)
+                } else {
+                    $template::serialize(self, serializer)
+                }
+            }
+        }
+
+        impl<'de: 'a, 'a> Deserialize<'de> for $template<'a> {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                struct Embedded<'a>($template<'a>);
+
+                impl<'de: 'a, 'a> Deserialize<'de> for Embedded<'a> {
+                    fn deserialize<D: Deserializer<'de>>(
+                        deserializer: D,
+                    ) -> Result<Self, D::Error> {
+                        $template::deserialize(deserializer).map(Embedded)
+                    }
+                }
+
+                #[derive(Deserialize)]
+                #[serde(untagged)]
+                enum Named<'a> {
+                    Name(String),
+                    #[serde(borrow)]
+                    Embedded(Embedded<'a>),
+                }
+
+                match Named::deserialize(deserializer)? {
+                    Named::Name(name) => $by_name(&name)
+                        .ok_or_else(|| D::Error::custom(format!("unknown {} {name}", $kind))),
+                    Named::Embedded(Embedded(template)) => Ok(template),
+                }
+            }
+        }
+    };
+}
+
+named_template!(Faction, faction_by_name, "faction");
+named_template!(PlayerMat, player_mat_by_name, "player mat");
@@ -115,3 +115,13 @@ pub const AGRICULTURAL: PlayerMat = PlayerMat {
     enlist_evolutions: 2,
     enlist_coins: 3,
 };
+
+pub const PLAYER_MATS: [PlayerMat; 5] =
+    [INDUSTRIAL, ENGINEERING, PATRIOTIC, MECHANICAL, AGRICULTURAL];
+
+/// The player mat with this name, ignoring case
+pub fn player_mat_by_name(name: &str) -> Option<PlayerMat<'static>> {
+    PLAYER_MATS
+        .into_iter()
+        .find(|mat| mat.name.eq_ignore_ascii_case(name))
+}
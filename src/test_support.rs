@@ -0,0 +1,76 @@
+//! Fixtures for the tests of every module that needs a game
+
+use rand::SeedableRng;
+
+use crate::{
+    game::{
+        GameRng,
+        game::{Game, PlayerInfo},
+        player::PlayerTemplate,
+        record::SeatRecord,
+    },
+    template::{
+        Faction, Player, PlayerMat,
+        board::NORMAL,
+        faction::{CRIMEA, NORDIC, POLANIA, RUSVIET, SAXONY},
+        player_mat::{AGRICULTURAL, ENGINEERING, INDUSTRIAL, MECHANICAL, PATRIOTIC},
+    },
+};
+
+/// The faction, player mat and start location of the seats of `game`, in order
+const SETUPS: [(Faction, PlayerMat, usize); 5] = [
+    (RUSVIET, INDUSTRIAL, 0),
+    (POLANIA, AGRICULTURAL, 1),
+    (NORDIC, PATRIOTIC, 2),
+    (SAXONY, ENGINEERING, 3),
+    (CRIMEA, MECHANICAL, 4),
+];
+
+/// A seat of a record named after its faction, without bonuses
+pub fn seat(faction: &str, player_mat: &str, start_location_index: usize) -> SeatRecord {
+    SeatRecord {
+        name: faction.to_string(),
+        faction: faction.to_string(),
+        player_mat: player_mat.to_string(),
+        start_location_index,
+        bonus_starting_coins: 0,
+        bonus_starting_power: 0,
+        bonus_starting_popularity: 0,
+    }
+}
+
+/// A seat named after its faction, without bonuses
+pub fn player(
+    faction: Faction<'static>,
+    player_mat: PlayerMat<'static>,
+    start_location_index: usize,
+) -> PlayerInfo<'static> {
+    PlayerInfo {
+        template: PlayerTemplate {
+            player: Player {
+                name: faction.name,
+                bonus_starting_coins: 0,
+                bonus_starting_power: 0,
+                bonus_starting_popularity: 0,
+            },
+            faction,
+            player_mat,
+        },
+        start_location_index,
+    }
+}
+
+/// A new game on NORMAL with seed 0 with the first `seats` setups. Rusviet is active with workers on
+/// the village (2,1) and the mountain (3,0), Polania follows with workers on the woods (-1,3)
+/// and the tundra (0,3).
+pub fn game(seats: usize) -> Game {
+    let players: Vec<PlayerInfo> = SETUPS
+        .into_iter()
+        .take(seats)
+        .map(|(faction, player_mat, start_location_index)| {
+            player(faction, player_mat, start_location_index)
+        })
+        .collect();
+    let players: Vec<&PlayerInfo> = players.iter().collect();
+    Game::new(&NORMAL, &players, &mut GameRng::seed_from_u64(0)).unwrap()
+}
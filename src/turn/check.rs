@@ -1,21 +1,258 @@
-use std::rc::Rc;
+use std::{error::Error, fmt, rc::Rc};
 
 use crate::{
     game::{
         Resource, Tile,
         board::{Field, ResourceField},
+        buildings::Building,
         game::Game,
         mechs::Mech,
         player::PlayerState,
         production::Worker,
+        recruits::Recruit,
+        upgrades::{PrimaryUpgrade, SecondaryUpgrade},
     },
-    template::Position,
-    turn::mask::{
-        MechMask, Move, Movement, Primary, Produce, Trade, TradeUnit, UnitMovement, WorkerMask,
+    template::{FactionAbility, MobilityPower, Position, PrimaryAction, SecondaryAction},
+    turn::{
+        execute::{map_primary, map_secondary, map_secondary_resource},
+        mask::{
+            MechMask, MechMove, Move, Movement, NormalMove, Primary, Produce, ResourceCost,
+            Secondary, Trade, TradeUnit, UNITS, UnitMovement, UnitPosition, WorkerMask,
+        },
     },
 };
 
-pub type Reason = Option<&'static str>;
+pub type Reason = Option<IllegalMove>;
+pub type MoveReason = Option<MoveError>;
+
+/// Why a turn was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IllegalMove {
+    /// Move3 and Produce3 require the evolved primary action
+    NotEvolved(PrimaryAction),
+    NotEnoughCoins {
+        action: PrimaryAction,
+        coins: u32,
+    },
+    /// The sub-move at the index of a Move1, Move2 or Move3 failed
+    Movement {
+        index: usize,
+        unit: UnitPosition,
+        reason: MoveError,
+    },
+    UnitNotDeployed(UnitPosition),
+    FieldNotControlled {
+        unit: UnitPosition,
+        position: Position,
+    },
+    FieldControlledByEnemy {
+        unit: UnitPosition,
+        position: Position,
+    },
+    NotEnoughResources {
+        unit: UnitPosition,
+        position: Position,
+        missing: ResourceField,
+    },
+    CannotPayProduction,
+    UnproducibleTile {
+        worker: Worker,
+        position: Position,
+        tile: Tile,
+    },
+    DuplicateProduction {
+        worker: Worker,
+        position: Position,
+    },
+    SecondaryNotLinked {
+        primary: PrimaryAction,
+        secondary: SecondaryAction,
+    },
+    PrimaryAlreadyUpgraded(PrimaryUpgrade),
+    SecondaryAlreadyUpgraded(SecondaryUpgrade),
+    MechAlreadyDeployed(Mech),
+    AlreadyBuilt(Building),
+    UnbuildableTile {
+        worker: Worker,
+        position: Position,
+        tile: Tile,
+    },
+    FieldHasBuilding {
+        worker: Worker,
+        position: Position,
+    },
+    SecondaryAlreadyRecruited(Recruit),
+    OnetimeAlreadyRecruited(Recruit),
+    ResourceCostMismatch {
+        expected: u8,
+        given: usize,
+    },
+    /// Paying units are listed in the order of `UNITS`, the unit comes before one listed earlier
+    UnorderedPayment(UnitPosition),
+}
+
+/// Why a single unit movement was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MoveError {
+    UnitNotDeployed,
+    InvalidPosition(Position),
+    AlreadyMoved,
+    SameField(Position),
+    HomeBase(Position),
+    NotAdjacent {
+        from: Position,
+        to: Position,
+    },
+    Lake(Position),
+    River {
+        from: Position,
+        to: Position,
+    },
+    EnemyUnits(Position),
+    Combat(Position),
+    Encounter(Position),
+    SpeedRequired,
+    WorkerDouble,
+    NotEnoughResources {
+        position: Position,
+        missing: ResourceField,
+    },
+    WorkersNotStationed {
+        position: Position,
+        workers: WorkerMask,
+    },
+    WorkersAlreadyMoved(WorkerMask),
+    InconsistentHistory,
+}
+
+impl fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IllegalMove::NotEvolved(action) => write!(f, "{action:?} is not evolved"),
+            IllegalMove::NotEnoughCoins { action, coins } => {
+                write!(f, "Not enough coins to {action:?}, only {coins} available")
+            }
+            IllegalMove::Movement {
+                index,
+                unit,
+                reason,
+            } => write!(f, "Move {} of {unit:?} failed: {reason}", index + 1),
+            IllegalMove::UnitNotDeployed(unit) => write!(f, "{unit:?} is not deployed"),
+            IllegalMove::FieldNotControlled { unit, position } => {
+                write!(f, "Field {position:?} of {unit:?} is not controlled")
+            }
+            IllegalMove::FieldControlledByEnemy { unit, position } => {
+                write!(
+                    f,
+                    "Field {position:?} of {unit:?} is controlled by an enemy"
+                )
+            }
+            IllegalMove::NotEnoughResources {
+                unit,
+                position,
+                missing,
+            } => write!(
+                f,
+                "Field {position:?} of {unit:?} is missing resources {missing:?}"
+            ),
+            IllegalMove::CannotPayProduction => write!(f, "Cannot pay the production cost"),
+            IllegalMove::UnproducibleTile {
+                worker,
+                position,
+                tile,
+            } => write!(
+                f,
+                "{worker:?} worker cannot produce on {tile:?} at {position:?}"
+            ),
+            IllegalMove::DuplicateProduction { worker, position } => write!(
+                f,
+                "{worker:?} worker produces on {position:?} multiple times"
+            ),
+            IllegalMove::SecondaryNotLinked { primary, secondary } => {
+                write!(f, "{secondary:?} is not linked to {primary:?}")
+            }
+            IllegalMove::PrimaryAlreadyUpgraded(primary) => {
+                write!(f, "{primary:?} is already upgraded")
+            }
+            IllegalMove::SecondaryAlreadyUpgraded(secondary) => {
+                write!(f, "{secondary:?} cannot be upgraded any further")
+            }
+            IllegalMove::MechAlreadyDeployed(mech) => {
+                write!(f, "{mech:?} mech is already deployed")
+            }
+            IllegalMove::AlreadyBuilt(building) => write!(f, "{building:?} is already built"),
+            IllegalMove::UnbuildableTile {
+                worker,
+                position,
+                tile,
+            } => write!(
+                f,
+                "{worker:?} worker cannot build on {tile:?} at {position:?}"
+            ),
+            IllegalMove::FieldHasBuilding { worker, position } => write!(
+                f,
+                "Field {position:?} of {worker:?} worker already has a building"
+            ),
+            IllegalMove::SecondaryAlreadyRecruited(recruit) => {
+                write!(f, "{recruit:?} recruit is already enlisted")
+            }
+            IllegalMove::OnetimeAlreadyRecruited(recruit) => {
+                write!(f, "{recruit:?} onetime bonus is already taken")
+            }
+            IllegalMove::ResourceCostMismatch { expected, given } => write!(
+                f,
+                "Secondary action costs {expected} resources, but {given} were given"
+            ),
+            IllegalMove::UnorderedPayment(unit) => {
+                write!(
+                    f,
+                    "{unit:?} pays out of order, units pay in the order of UNITS"
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveError::UnitNotDeployed => write!(f, "Unit is not deployed"),
+            MoveError::InvalidPosition(p) => write!(f, "Position {p:?} is not a valid field"),
+            MoveError::AlreadyMoved => write!(f, "Unit has already moved this turn"),
+            MoveError::SameField(p) => write!(f, "Unit has to leave {p:?}"),
+            MoveError::HomeBase(p) => write!(f, "Cannot move onto the home base {p:?}"),
+            MoveError::NotAdjacent { from, to } => {
+                write!(f, "{to:?} is not adjacent to {from:?}")
+            }
+            MoveError::Lake(p) => write!(f, "Cannot move onto the lake {p:?}"),
+            MoveError::River { from, to } => {
+                write!(f, "Cannot cross the river from {from:?} to {to:?}")
+            }
+            MoveError::EnemyUnits(p) => {
+                write!(f, "Workers cannot move onto enemy units at {p:?}")
+            }
+            MoveError::Combat(p) => write!(f, "Combat at {p:?} is not supported yet"),
+            MoveError::Encounter(p) => {
+                write!(f, "The character has to stop at the encounter {p:?}")
+            }
+            MoveError::SpeedRequired => write!(f, "Moving two fields requires the speed mech"),
+            MoveError::WorkerDouble => write!(f, "Workers can only move a single field"),
+            MoveError::NotEnoughResources { position, missing } => {
+                write!(f, "Field {position:?} is missing resources {missing:?}")
+            }
+            MoveError::WorkersNotStationed { position, workers } => {
+                write!(f, "Workers {workers:?} are not stationed at {position:?}")
+            }
+            MoveError::WorkersAlreadyMoved(workers) => {
+                write!(f, "Workers {workers:?} have already moved this turn")
+            }
+            MoveError::InconsistentHistory => write!(f, "Movement history is inconsistent"),
+        }
+    }
+}
+
+impl Error for IllegalMove {}
+impl Error for MoveError {}
 
 pub fn check_primary(game: &Game, primary: &Primary) -> Reason {
     let player = game.get_active_player();
@@ -24,52 +261,74 @@ pub fn check_primary(game: &Game, primary: &Primary) -> Reason {
         Primary::Move(Move::Move1(m)) => check_move(game, player, &[m], &mut history),
         Primary::Move(Move::Move2(m1, m2)) => check_move(game, player, &[m1, m2], &mut history),
         Primary::Move(Move::Move3(m1, m2, m3)) => (!player.upgrades.move_evolved)
-            .then_some("Move3 is not evolved")
+            .then_some(IllegalMove::NotEvolved(PrimaryAction::Move))
             .or_else(|| check_move(game, player, &[m1, m2, m3], &mut history)),
         Primary::Tax => None,
-        Primary::Trade(trade) => (player.coins < 1)
-            .then_some("Not enough coins to trade")
-            .or_else(|| match trade {
+        Primary::Trade(trade) => {
+            check_coins(player, PrimaryAction::Trade).or_else(|| match trade {
                 Trade::Trade1(u) => check_trade1(game, u),
                 Trade::Trade2(u1, u2) => check_trade2(game, u1, u2),
-            }),
-        Primary::Promote => (player.coins < 1).then_some("Not enough coins to promote"),
-        Primary::Bolster => (player.coins < 1).then_some("Not enough coins to bolster"),
-        Primary::Enforce => (player.coins < 1).then_some("Not enough coins to enforce"),
+            })
+        }
+        Primary::Promote => check_coins(player, PrimaryAction::Promote),
+        Primary::Bolster => check_coins(player, PrimaryAction::Bolster),
+        Primary::Enforce => check_coins(player, PrimaryAction::Enforce),
         Primary::Produce(Produce::Produce1(tile1)) => check_produce(player, &[tile1]),
         Primary::Produce(Produce::Produce2(tile1, tile2)) => check_produce(player, &[tile1, tile2]),
         Primary::Produce(Produce::Produce3(tile1, tile2, tile3)) => {
             (!player.upgrades.produce_evolved)
-                .then_some("Produce3 is not evolved")
+                .then_some(IllegalMove::NotEvolved(PrimaryAction::Produce))
                 .or_else(|| check_produce(player, &[tile1, tile2, tile3]))
         }
     }
 }
 
+pub fn check_coins(player: &Rc<PlayerState>, action: PrimaryAction) -> Reason {
+    (player.coins < 1).then_some(IllegalMove::NotEnoughCoins {
+        action,
+        coins: player.coins,
+    })
+}
+
 pub fn check_move(
     game: &Game,
     player: &Rc<PlayerState>,
     movement: &[&UnitMovement],
     history: &mut History,
 ) -> Reason {
-    movement.iter().fold(None, |acc: Reason, &mov| {
-        acc.or_else(|| match mov {
-            UnitMovement::Character(m) => {
-                check_character_move(game, &player.character.location, m, history)
-            }
-            UnitMovement::Worker(worker, m) => {
-                check_worker_move(game, &player, *worker, m, history)
-            }
-            UnitMovement::Mech(mech, m) => check_mech_move(game, &player, *mech, m, history),
+    movement
+        .iter()
+        .enumerate()
+        .fold(None, |acc: Reason, (index, &mov)| {
+            acc.or_else(|| {
+                let (unit, reason) = match mov {
+                    UnitMovement::Character(m) => (
+                        UnitPosition::Character,
+                        check_character_move(game, player, m, history),
+                    ),
+                    UnitMovement::Worker(worker, m) => (
+                        UnitPosition::Worker(*worker),
+                        check_worker_move(game, player, *worker, m, history),
+                    ),
+                    UnitMovement::Mech(mech, m) => (
+                        UnitPosition::Mech(*mech),
+                        check_mech_move(game, player, *mech, m, history),
+                    ),
+                };
+                reason.map(|reason| IllegalMove::Movement {
+                    index,
+                    unit,
+                    reason,
+                })
+            })
         })
-    })
 }
 
 type WorkerHistory = (Rc<Field>, Rc<Field>, WorkerMask);
 type ResourceHistory = (Rc<Field>, Rc<Field>, ResourceField);
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-struct History {
+pub struct History {
     worker: Vec<WorkerHistory>,
     resource: Vec<ResourceHistory>,
 
@@ -88,6 +347,64 @@ impl History {
             mech_moved: MechMask::empty(),
         }
     }
+
+    pub fn character_moved(&self) -> bool {
+        self.character_moved
+    }
+
+    pub fn worker_moved(&self) -> WorkerMask {
+        self.worker_moved
+    }
+
+    pub fn mech_moved(&self) -> MechMask {
+        self.mech_moved
+    }
+
+    /// The resources on the field after the recorded moves
+    pub fn resources_at(&self, field: &Rc<Field>) -> Option<ResourceField> {
+        let mut available = field.resources;
+        for (a, b, amt) in self.resource.iter() {
+            if Rc::ptr_eq(a, b) {
+                continue;
+            }
+            if Rc::ptr_eq(a, field) {
+                available = available.checked_sub(*amt)?;
+            }
+            if Rc::ptr_eq(b, field) {
+                available = available + *amt;
+            }
+        }
+        Some(available)
+    }
+
+    /// The workers of the player stationed on the field after the recorded moves
+    pub fn workers_at(&self, player: &Rc<PlayerState>, field: &Rc<Field>) -> Option<WorkerMask> {
+        let mut stationed = player.production.at(field);
+        for (a, b, w) in self.worker.iter() {
+            if Rc::ptr_eq(a, b) {
+                continue;
+            }
+            if Rc::ptr_eq(a, field) {
+                if !stationed.contains(*w) {
+                    return None;
+                }
+                stationed = stationed.difference(*w)
+            }
+            if Rc::ptr_eq(b, field) {
+                if stationed.intersects(*w) {
+                    return None;
+                }
+                stationed = stationed.union(*w)
+            }
+        }
+        Some(stationed)
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// Are all the required resources at the source field after moves?
@@ -96,91 +413,94 @@ pub fn check_resources(
     to: &Rc<Field>,
     amount: ResourceField,
     history: &mut History,
-) -> Reason {
-    let mut available = from.resources;
-    for (a, b, amt) in history.resource.iter() {
-        if Rc::ptr_eq(a, b) {
-            continue;
-        }
-        if Rc::ptr_eq(a, from) {
-            match available.checked_sub(*amt) {
-                Some(sum) => available = sum,
-                None => return Some("Resource history is negative"),
-            };
-        }
-        if Rc::ptr_eq(b, from) {
-            available = available + *amt;
-        }
-    }
+) -> MoveReason {
+    let available = match history.resources_at(from) {
+        Some(available) => available,
+        None => return Some(MoveError::InconsistentHistory),
+    };
 
-    if available >= amount {
+    if available.checked_sub(amount).is_some() {
         history.resource.push((from.clone(), to.clone(), amount));
         None
     } else {
-        Some("Not enough resources to take on this move")
+        Some(MoveError::NotEnoughResources {
+            position: from.position,
+            missing: amount - available,
+        })
     }
 }
 
-/// Are all the required workers of the mask at the source field?
+/// Are all the required workers of the mask at the source field and free to be carried?
 pub fn check_carry_workers(
     player: &Rc<PlayerState>,
     from: &Rc<Field>,
     to: &Rc<Field>,
     workers: WorkerMask,
+    carried: WorkerMask,
     history: &mut History,
-) -> Reason {
-    let mut stationed = player.production.at(from);
-    for (a, b, w) in history.worker.iter() {
-        if Rc::ptr_eq(a, b) {
-            continue;
-        }
-        if Rc::ptr_eq(a, from) {
-            if !stationed.contains(*w) {
-                return Some("Required workers are not stationed at the field");
-            }
-            stationed = stationed.difference(*w)
-        }
-        if Rc::ptr_eq(b, from) {
-            if stationed.intersects(*w) {
-                return Some("Required workers are already stationed at the field");
-            }
-            stationed = stationed.union(*w)
-        }
+) -> MoveReason {
+    if workers.is_empty() {
+        return None;
     }
 
+    let stationed = match history.workers_at(player, from) {
+        Some(stationed) => stationed,
+        None => return Some(MoveError::InconsistentHistory),
+    };
+
+    let moved = history
+        .worker_moved
+        .intersection(workers)
+        .difference(carried);
     if !stationed.contains(workers) {
-        Some("Source field does not have all the required workers stationed")
-    } else if stationed.intersects(workers) {
-        Some("Target field already has some required workers stationed")
-    } else {
+        Some(MoveError::WorkersNotStationed {
+            position: from.position,
+            workers: workers.difference(stationed),
+        })
+    } else if moved.is_empty() {
         history.worker.push((from.clone(), to.clone(), workers));
+        history.worker_moved |= workers;
         None
+    } else {
+        Some(MoveError::WorkersAlreadyMoved(moved))
+    }
+}
+
+/// Looks up both target fields of a double move
+fn get_fields<'a>(
+    game: &'a Game,
+    p1: &Position,
+    p2: &Position,
+) -> Result<(&'a Rc<Field>, &'a Rc<Field>), MoveError> {
+    match (game.board.get_field(p1), game.board.get_field(p2)) {
+        (Some(t1), Some(t2)) => Ok((t1, t2)),
+        (None, _) => Err(MoveError::InvalidPosition(*p1)),
+        (_, None) => Err(MoveError::InvalidPosition(*p2)),
     }
 }
 
 pub fn check_character_move(
     game: &Game,
-    from: &Rc<Field>,
-    mov: &Movement<(Position, ResourceField)>,
+    player: &Rc<PlayerState>,
+    mov: &Movement<NormalMove>,
     history: &mut History,
-) -> Reason {
+) -> MoveReason {
+    let from = &player.character.location;
     match mov {
-        super::mask::Movement::Single((pos, res)) => match game.board.get_field(pos) {
-            Some(to) => check_character_movement(from, to, true, history)
+        Movement::Single((pos, res)) => match game.board.get_field(pos) {
+            Some(to) => check_character_movement(game, player, from, to, true, history)
                 .or_else(|| check_resources(from, to, *res, history)),
-            None => Some("Target position is not a valid field"),
+            None => Some(MoveError::InvalidPosition(*pos)),
+        },
+        Movement::Double((p1, r1), (p2, r2)) => match get_fields(game, p1, p2) {
+            Ok((t1, t2)) => check_speed(player)
+                .or_else(|| check_character_movement(game, player, from, t1, true, history))
+                .or_else(|| check_resources(from, t1, *r1, history))
+                .or_else(|| check_passing(t1))
+                .or_else(|| check_character_movement(game, player, t1, t2, false, history))
+                .or_else(|| check_resources(t1, t2, *r2, history)),
+            Err(reason) => Some(reason),
         },
-        super::mask::Movement::Double((p1, r1), (p2, r2)) => {
-            match (game.board.get_field(p1), game.board.get_field(p2)) {
-                (None, None) => Some("Target positions are not valid fields"),
-                (None, Some(_)) => Some("First target positions is not a valid field"),
-                (Some(_), None) => Some("Second target positions is not a valid field"),
-                (Some(t1), Some(t2)) => check_character_movement(from, t1, true, history)
-                    .or_else(|| check_resources(from, t2, *r1, history))
-                    .or_else(|| check_character_movement(t1, t2, false, history))
-                    .or_else(|| check_resources(t1, t2, *r2, history)),
-            }
-        }
     }
 }
 
@@ -188,82 +508,93 @@ pub fn check_worker_move(
     game: &Game,
     player: &Rc<PlayerState>,
     worker: Worker,
-    mov: &Movement<(Position, ResourceField)>,
+    mov: &Movement<NormalMove>,
     history: &mut History,
-) -> Reason {
+) -> MoveReason {
     match player.production.get(worker) {
         Some(from) => match mov {
-            super::mask::Movement::Single((pos, res)) => match game.board.get_field(pos) {
-                Some(to) => check_worker_movement(from, to, worker, true, history)
+            Movement::Single((pos, res)) => match game.board.get_field(pos) {
+                Some(to) => check_worker_movement(game, player, from, to, worker, true, history)
                     .or_else(|| check_resources(from, to, *res, history)),
-                None => Some("Target position is not a valid field"),
+                None => Some(MoveError::InvalidPosition(*pos)),
             },
-            super::mask::Movement::Double((p1, r1), (p2, r2)) => {
-                match (game.board.get_field(p1), game.board.get_field(p2)) {
-                    (None, None) => Some("Target positions are not valid fields"),
-                    (None, Some(_)) => Some("First target positions is not a valid field"),
-                    (Some(_), None) => Some("Second target positions is not a valid field"),
-                    (Some(t1), Some(t2)) => check_worker_movement(from, t1, worker, true, history)
-                        .or_else(|| check_resources(from, t1, *r1, history))
-                        .or_else(|| check_worker_movement(t1, t2, worker, false, history))
-                        .or_else(|| check_resources(t1, t2, *r2, history)),
-                }
-            }
+            Movement::Double(_, _) => Some(MoveError::WorkerDouble),
         },
-        None => Some("Worker is not deployed"),
+        None => Some(MoveError::UnitNotDeployed),
     }
 }
 
-fn check_mech_move(
+pub fn check_mech_move(
     game: &Game,
     player: &Rc<PlayerState>,
     mech: Mech,
-    mov: &Movement<(Position, WorkerMask, ResourceField)>,
+    mov: &Movement<MechMove>,
     history: &mut History,
-) -> Reason {
+) -> MoveReason {
     match player.mechs.get(mech) {
         Some(from) => match mov {
-            super::mask::Movement::Single((pos, workers, res)) => match game.board.get_field(pos) {
-                Some(to) => check_mech_movement(from, to, mech, true, history)
+            Movement::Single((pos, workers, res)) => match game.board.get_field(pos) {
+                Some(to) => check_mech_movement(game, player, from, to, mech, true, history)
                     .or_else(|| check_resources(from, to, *res, history))
-                    .or_else(|| check_carry_workers(&player, from, to, *workers, history)),
-                None => Some("Target position is not a valid field"),
+                    .or_else(|| {
+                        check_carry_workers(
+                            player,
+                            from,
+                            to,
+                            *workers,
+                            WorkerMask::empty(),
+                            history,
+                        )
+                    }),
+                None => Some(MoveError::InvalidPosition(*pos)),
+            },
+            Movement::Double((p1, w1, r1), (p2, w2, r2)) => match get_fields(game, p1, p2) {
+                Ok((t1, t2)) => check_speed(player)
+                    .or_else(|| check_mech_movement(game, player, from, t1, mech, true, history))
+                    .or_else(|| check_resources(from, t1, *r1, history))
+                    .or_else(|| {
+                        check_carry_workers(player, from, t1, *w1, WorkerMask::empty(), history)
+                    })
+                    .or_else(|| check_mech_movement(game, player, t1, t2, mech, false, history))
+                    .or_else(|| check_resources(t1, t2, *r2, history))
+                    .or_else(|| check_carry_workers(player, t1, t2, *w2, *w1, history)),
+                Err(reason) => Some(reason),
             },
-            super::mask::Movement::Double((p1, w1, r1), (p2, w2, r2)) => {
-                match (game.board.get_field(p1), game.board.get_field(p2)) {
-                    (None, None) => Some("Target positions are not valid fields"),
-                    (None, Some(_)) => Some("First target positions is not a valid field"),
-                    (Some(_), None) => Some("Second target positions is not a valid field"),
-                    (Some(t1), Some(t2)) => check_mech_movement(from, t1, mech, true, history)
-                        .or_else(|| check_resources(from, t1, *r1, history))
-                        .or_else(|| check_carry_workers(player, from, t1, *w1, history))
-                        .or_else(|| check_mech_movement(t1, t2, mech, false, history))
-                        .or_else(|| check_resources(t1, t2, *r2, history))
-                        .or_else(|| check_carry_workers(player, t1, t2, *w2, history)),
-                }
-            }
         },
-        None => Some("Mech is not deployed"),
+        None => Some(MoveError::UnitNotDeployed),
     }
 }
 
+/// Can the character and mechs move two fields in one move?
+pub fn check_speed(player: &Rc<PlayerState>) -> MoveReason {
+    (!player.mechs.is_deployed(Mech::Third)).then_some(MoveError::SpeedRequired)
+}
+
+/// Can the character continue its movement after entering this field?
+pub fn check_passing(field: &Rc<Field>) -> MoveReason {
+    field
+        .encounter_token
+        .then_some(MoveError::Encounter(field.position))
+}
+
 /// Can the character move from this field to that field in a single move?
 pub fn check_character_movement(
+    game: &Game,
+    player: &Rc<PlayerState>,
     from: &Rc<Field>,
     to: &Rc<Field>,
     check_already_moved: bool,
     history: &mut History,
-) -> Reason {
+) -> MoveReason {
     if check_already_moved && history.character_moved {
-        return Some("Cannot move the character multiple times in one turn");
+        return Some(MoveError::AlreadyMoved);
+    }
+    if let Some(reason) = check_reachable(game, player, from, to, false) {
+        return Some(reason);
+    }
+    if let Some(reason) = check_enemies(game, player, to, false) {
+        return Some(reason);
     }
-    // cannot move from a field of conflict
-    // might move from lake to lake
-    // might move from/to lake
-    // might move from/to home
-    // might move through tunnels
-    // might move over rivers
-    // can move to the field very next to the source
 
     history.character_moved = true;
     None
@@ -271,71 +602,174 @@ pub fn check_character_movement(
 
 /// Can the worker move from this field to that field in a single move?
 pub fn check_worker_movement(
+    game: &Game,
+    player: &Rc<PlayerState>,
     from: &Rc<Field>,
     to: &Rc<Field>,
     worker: Worker,
     check_already_moved: bool,
     history: &mut History,
-) -> Reason {
+) -> MoveReason {
     if check_already_moved && history.worker_moved.contains_worker(worker) {
-        return Some("Cannot move the same worker multiple times in one turn");
+        return Some(MoveError::AlreadyMoved);
+    }
+    if let Some(reason) = check_reachable(game, player, from, to, true) {
+        return Some(reason);
+    }
+    if let Some(reason) = check_enemies(game, player, to, true) {
+        return Some(reason);
     }
-
-    // cannot move from/to a field of conflict
-    // might move from lake to lake
-    // might move from/to lake
-    // might move from/to home
-    // might move through tunnels
-    // might move over rivers
-    // can move to the field very next to the source
 
     let mask = WorkerMask::get_worker(worker);
     history.worker.push((from.clone(), to.clone(), mask));
-    history.worker_moved = history.worker_moved | mask;
+    history.worker_moved |= mask;
     None
 }
 
 /// Can the mech move from this field to that field in a single move?
 pub fn check_mech_movement(
+    game: &Game,
+    player: &Rc<PlayerState>,
     from: &Rc<Field>,
     to: &Rc<Field>,
     mech: Mech,
     check_already_moved: bool,
     history: &mut History,
-) -> Reason {
+) -> MoveReason {
     if check_already_moved && history.mech_moved.contains_mech(mech) {
-        return Some("Cannot move the same mech multiple times in one turn");
+        return Some(MoveError::AlreadyMoved);
+    }
+    if let Some(reason) = check_reachable(game, player, from, to, false) {
+        return Some(reason);
+    }
+    if let Some(reason) = check_enemies(game, player, to, false) {
+        return Some(reason);
     }
-
-    // cannot move from/to a field of conflict
-    // might move from lake to lake
-    // might move from/to lake
-    // might move from/to home
-    // might move through tunnels
-    // might move over rivers
-    // can move to the field very next to the source
 
     let mask = MechMask::get_mech(mech);
-    history.mech_moved = history.mech_moved | mask;
+    history.mech_moved |= mask;
     None
 }
 
-fn check_movement(game: &Game, movement: &UnitMovement) -> Reason {
-    // no unit can walk multiple times
-    // if a mech carries workers, they need to be added to the target field
-    // if a unit carries resources, they need to be added to the target field
-    // movement appears in the appearance in the vector
-    // cannot move from a field of conflict
+/// Is the field connected to the source for a unit of this player? Characters and mechs share the abilities of deployed mechs.
+pub fn check_reachable(
+    game: &Game,
+    player: &Rc<PlayerState>,
+    from: &Rc<Field>,
+    to: &Rc<Field>,
+    worker: bool,
+) -> MoveReason {
+    if Rc::ptr_eq(from, to) {
+        return Some(MoveError::SameField(to.position));
+    }
+    if to.tile == Tile::Home {
+        return Some(MoveError::HomeBase(to.position));
+    }
 
-    None
+    let riverwalk = !worker && player.mechs.is_deployed(Mech::First);
+    let mobility = !worker && player.mechs.is_deployed(Mech::Second);
+
+    if is_tunnel(game, player, from, mobility) && is_tunnel(game, player, to, mobility) {
+        return None;
+    }
+    if mobility && is_mobility_connected(game, player, from, to) {
+        return None;
+    }
+
+    if !from.position.is_adjacent(&to.position) {
+        Some(MoveError::NotAdjacent {
+            from: from.position,
+            to: to.position,
+        })
+    } else if to.tile == Tile::Lake
+        && !(mobility
+            && matches!(
+                player.mobility_power,
+                MobilityPower::Seaworthy | MobilityPower::Submerge
+            ))
+    {
+        Some(MoveError::Lake(to.position))
+    } else if game.board.is_river(from, to)
+        && !(riverwalk && player.riverwalk.contains(&to.tile))
+        && !(worker && player.faction_ability == FactionAbility::Swim)
+    {
+        Some(MoveError::River {
+            from: from.position,
+            to: to.position,
+        })
+    } else {
+        None
+    }
+}
+
+/// Tunnels and mines are connected with each other
+fn is_tunnel(game: &Game, player: &Rc<PlayerState>, field: &Rc<Field>, mobility: bool) -> bool {
+    field.tunnelable
+        || player
+            .buildings
+            .get(Building::Tunnel)
+            .is_some_and(|f| Rc::ptr_eq(f, field))
+        || (mobility
+            && player.mobility_power == MobilityPower::Underpass
+            && field.tile == Tile::Mountain
+            && is_controlled_by(game, player, field))
+}
+
+/// Faction specific connections that ignore adjacency
+fn is_mobility_connected(
+    game: &Game,
+    player: &Rc<PlayerState>,
+    from: &Rc<Field>,
+    to: &Rc<Field>,
+) -> bool {
+    match player.mobility_power {
+        MobilityPower::Submerge => from.tile == Tile::Lake && to.tile == Tile::Lake,
+        MobilityPower::Township => {
+            (from.tile == Tile::Village
+                && to.tile == Tile::Factory
+                && is_controlled_by(game, player, from))
+                || (from.tile == Tile::Factory
+                    && to.tile == Tile::Village
+                    && is_controlled_by(game, player, to))
+        }
+        _ => false,
+    }
+}
+
+fn is_controlled_by(game: &Game, player: &Rc<PlayerState>, field: &Rc<Field>) -> bool {
+    game.get_player_control(field)
+        .is_some_and(|p| Rc::ptr_eq(p, player))
+}
+
+/// Are there enemy units on the target field?
+pub fn check_enemies(
+    game: &Game,
+    player: &Rc<PlayerState>,
+    to: &Rc<Field>,
+    worker: bool,
+) -> MoveReason {
+    let occupied = game
+        .players
+        .iter()
+        .filter(|p| !Rc::ptr_eq(p, player))
+        .any(|p| {
+            Rc::ptr_eq(&p.character.location, to)
+                || p.mechs.amount(to) > 0
+                || p.production.amount(to) > 0
+        });
+    match (occupied, worker) {
+        (false, _) => None,
+        (true, true) => Some(MoveError::EnemyUnits(to.position)),
+        (true, false) => Some(MoveError::Combat(to.position)),
+    }
 }
 
 pub fn check_trade1(game: &Game, (unit, from, _): &TradeUnit) -> Reason {
     let player = game.get_active_player();
     let field = player.get_unit_field(unit);
     match field {
-        Some(f) => check_field_for_trade(game, &player, f, from, 1),
-        None => Some("Unit is not deployed"),
+        Some(f) => check_field_for_trade(game, player, unit, f, from, 1),
+        None => Some(IllegalMove::UnitNotDeployed(*unit)),
     }
 }
 
@@ -344,39 +778,49 @@ pub fn check_trade2(game: &Game, (u1, r1, _): &TradeUnit, (u2, r2, _): &TradeUni
     let field1 = player.get_unit_field(u1);
     let field2 = player.get_unit_field(u2);
     match (field1, field2) {
-        (None, None) => Some("Units are not deployed"),
-        (None, Some(_)) => Some("Unit 1 is not deployed"),
-        (Some(_), None) => Some("Unit 2 is not deployed"),
+        (None, _) => Some(IllegalMove::UnitNotDeployed(*u1)),
+        (_, None) => Some(IllegalMove::UnitNotDeployed(*u2)),
         (Some(f1), Some(f2)) if Rc::ptr_eq(f1, f2) && r1 == r2 => {
-            check_field_for_trade(game, &player, f1, r1, 2)
+            check_field_for_trade(game, player, u1, f1, r1, 2)
         }
-        (Some(f1), Some(f2)) => check_field_for_trade(game, &player, f1, r1, 1)
-            .or(check_field_for_trade(game, &player, f2, r2, 1)),
+        (Some(f1), Some(f2)) => check_field_for_trade(game, player, u1, f1, r1, 1)
+            .or_else(|| check_field_for_trade(game, player, u2, f2, r2, 1)),
     }
 }
 
 pub fn check_field_for_trade(
     game: &Game,
     player: &Rc<PlayerState>,
+    unit: &UnitPosition,
     field: &Rc<Field>,
     resource: &Resource,
     amount: u32,
 ) -> Reason {
     let control_player = game.get_player_control(field);
     if control_player.is_none() {
-        Some("Field is not controlled")
+        Some(IllegalMove::FieldNotControlled {
+            unit: *unit,
+            position: field.position,
+        })
     } else if let Some(p) = control_player
-        && Rc::ptr_eq(p, player)
+        && !Rc::ptr_eq(p, player)
     {
-        Some("Field is controlled by enemy")
+        Some(IllegalMove::FieldControlledByEnemy {
+            unit: *unit,
+            position: field.position,
+        })
     } else if !field.resources.has(resource, amount) {
-        Some("Not enough resources to trade")
+        Some(IllegalMove::NotEnoughResources {
+            unit: *unit,
+            position: field.position,
+            missing: ResourceField::single(resource, amount - field.resources.get(resource)),
+        })
     } else {
         None
     }
 }
 
-const VALID_PRODUCTION_TILES: &[Tile] = &[
+pub const VALID_PRODUCTION_TILES: &[Tile] = &[
     Tile::Farm,
     Tile::Mountain,
     Tile::Tundra,
@@ -384,29 +828,753 @@ const VALID_PRODUCTION_TILES: &[Tile] = &[
     Tile::Woods,
 ];
 pub fn check_produce(player: &Rc<PlayerState>, prod: &[&Worker]) -> Reason {
+    if !player.can_produce() {
+        return Some(IllegalMove::CannotPayProduction);
+    }
     let mut production: Vec<Rc<Field>> = Vec::new();
     for worker in prod {
         match player.production.get(**worker) {
             Some(field) => {
-                if VALID_PRODUCTION_TILES.contains(&field.tile) {
-                    return Some("Cannot produce on unproducible tiles");
+                if !VALID_PRODUCTION_TILES.contains(&field.tile) {
+                    return Some(IllegalMove::UnproducibleTile {
+                        worker: **worker,
+                        position: field.position,
+                        tile: field.tile,
+                    });
                 }
                 if production.iter().any(|f| Rc::ptr_eq(f, field)) {
-                    return Some("Cannot produce on the same tile multiple times");
+                    return Some(IllegalMove::DuplicateProduction {
+                        worker: **worker,
+                        position: field.position,
+                    });
                 }
                 production.push(field.clone());
             }
-            None => return Some("Worker is not deployed and cant produce"),
+            None => return Some(IllegalMove::UnitNotDeployed(UnitPosition::Worker(**worker))),
         }
     }
     None
 }
 
-pub fn check_secondary_cost(game: &Playergame, secondary: &SecondaryAction) -> bool {
-    (match secondary {
-        SecondaryAction::Upgrade => game.resources.oil,
-        SecondaryAction::Deploy => game.resources.metal,
-        SecondaryAction::Build => game.resources.wood,
-        SecondaryAction::Enlist => game.resources.food,
-    }) >= game.upgrades.get_upgrade_cost(secondary)
+/// Is the secondary action available after the primary action was executed?
+pub fn check_secondary(game: &Game, primary: &Primary, secondary: &Secondary) -> Reason {
+    let player = game.get_active_player();
+    let action = map_secondary(secondary);
+    if player.get_secondary(map_primary(primary)) != action {
+        return Some(IllegalMove::SecondaryNotLinked {
+            primary: map_primary(primary),
+            secondary: action,
+        });
+    }
+
+    match secondary {
+        Secondary::Upgrade(p, s, cost) => {
+            check_upgrade(player, p, s).or_else(|| check_payment(game, player, &action, cost))
+        }
+        Secondary::Deploy(mech, worker, cost) => check_deploy(player, *mech, *worker)
+            .or_else(|| check_payment(game, player, &action, cost)),
+        Secondary::Build(building, worker, cost) => check_build(game, player, *building, *worker)
+            .or_else(|| check_payment(game, player, &action, cost)),
+        Secondary::Enlist(s, o, cost) => {
+            check_enlist(player, *s, *o).or_else(|| check_payment(game, player, &action, cost))
+        }
+    }
+}
+
+pub fn check_upgrade(
+    player: &Rc<PlayerState>,
+    primary: &PrimaryUpgrade,
+    secondary: &SecondaryUpgrade,
+) -> Reason {
+    if !player.upgrades.can_upgrade_primary(primary) {
+        Some(IllegalMove::PrimaryAlreadyUpgraded(*primary))
+    } else if !player.upgrades.can_upgrade_secondary(secondary) {
+        Some(IllegalMove::SecondaryAlreadyUpgraded(*secondary))
+    } else {
+        None
+    }
+}
+
+pub fn check_deploy(player: &Rc<PlayerState>, mech: Mech, worker: Worker) -> Reason {
+    if player.mechs.is_deployed(mech) {
+        Some(IllegalMove::MechAlreadyDeployed(mech))
+    } else if player.production.get(worker).is_none() {
+        Some(IllegalMove::UnitNotDeployed(UnitPosition::Worker(worker)))
+    } else {
+        None
+    }
+}
+
+pub fn check_build(
+    game: &Game,
+    player: &Rc<PlayerState>,
+    building: Building,
+    worker: Worker,
+) -> Reason {
+    if player.buildings.is_build(building) {
+        return Some(IllegalMove::AlreadyBuilt(building));
+    }
+    match player.production.get(worker) {
+        Some(field) if matches!(field.tile, Tile::Lake | Tile::Home) => {
+            Some(IllegalMove::UnbuildableTile {
+                worker,
+                position: field.position,
+                tile: field.tile,
+            })
+        }
+        Some(field) if game.has_building(field) => Some(IllegalMove::FieldHasBuilding {
+            worker,
+            position: field.position,
+        }),
+        Some(_) => None,
+        None => Some(IllegalMove::UnitNotDeployed(UnitPosition::Worker(worker))),
+    }
+}
+
+pub fn check_enlist(player: &Rc<PlayerState>, secondary: Recruit, onetime: Recruit) -> Reason {
+    if player.recruits.is_secondary_recruited(secondary) {
+        Some(IllegalMove::SecondaryAlreadyRecruited(secondary))
+    } else if player.recruits.is_onetime_recruited(onetime) {
+        Some(IllegalMove::OnetimeAlreadyRecruited(onetime))
+    } else {
+        None
+    }
+}
+
+/// Do the paying units stand on controlled fields holding enough of the required resource?
+///
+/// The order of the units does not change a payment, so each payment has the single form that
+/// lists its units in the order of `UNITS`.
+pub fn check_payment(
+    game: &Game,
+    player: &Rc<PlayerState>,
+    action: &SecondaryAction,
+    cost: &ResourceCost,
+) -> Reason {
+    let expected = player.upgrades.get_upgrade_cost(action);
+    let units = cost.units();
+    if units.len() != usize::from(expected) {
+        return Some(IllegalMove::ResourceCostMismatch {
+            expected,
+            given: units.len(),
+        });
+    }
+    let order = |unit: &UnitPosition| UNITS.iter().position(|u| u == unit);
+    if let Some(pair) = units
+        .windows(2)
+        .find(|pair| order(&pair[1]) < order(&pair[0]))
+    {
+        return Some(IllegalMove::UnorderedPayment(pair[1]));
+    }
+
+    let resource = map_secondary_resource(action);
+    let mut payment: Vec<(&Rc<Field>, u32)> = Vec::new();
+    for unit in units.iter() {
+        let field = match player.get_unit_field(unit) {
+            Some(f) => f,
+            None => return Some(IllegalMove::UnitNotDeployed(*unit)),
+        };
+        let paid = match payment.iter_mut().find(|(f, _)| Rc::ptr_eq(f, field)) {
+            Some((_, amount)) => {
+                *amount += 1;
+                *amount
+            }
+            None => {
+                payment.push((field, 1));
+                1
+            }
+        };
+        if paid == 1
+            && let Some(reason) = check_field_for_payment(game, player, unit, field)
+        {
+            return Some(reason);
+        }
+        if !field.resources.has(&resource, paid) {
+            // Everything this field has to pay, not only the units checked so far
+            let required = units
+                .iter()
+                .filter_map(|unit| player.get_unit_field(unit))
+                .filter(|f| Rc::ptr_eq(f, field))
+                .count() as u32;
+            return Some(IllegalMove::NotEnoughResources {
+                unit: *unit,
+                position: field.position,
+                missing: ResourceField::single(
+                    &resource,
+                    required - field.resources.get(&resource),
+                ),
+            });
+        }
+    }
+    None
+}
+
+fn check_field_for_payment(
+    game: &Game,
+    player: &Rc<PlayerState>,
+    unit: &UnitPosition,
+    field: &Rc<Field>,
+) -> Reason {
+    match game.get_player_control(field) {
+        None => Some(IllegalMove::FieldNotControlled {
+            unit: *unit,
+            position: field.position,
+        }),
+        Some(p) if !Rc::ptr_eq(p, player) => Some(IllegalMove::FieldControlledByEnemy {
+            unit: *unit,
+            position: field.position,
+        }),
+        Some(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::game;
+
+    fn field(game: &Game, x: i8, y: i8) -> Rc<Field> {
+        game.board.get_field(&Position::new(x, y)).unwrap().clone()
+    }
+
+    fn add_resource(game: &mut Game, (x, y): (i8, i8), resource: Resource, amount: u32) {
+        game.update_field(&Position::new(x, y), |field| {
+            field.resources.add_resource(&resource, amount)
+        });
+    }
+
+    fn missing(resource: Resource, amount: u32) -> ResourceField {
+        ResourceField::single(&resource, amount)
+    }
+
+    /// A step to the field without cargo
+    fn to(x: i8, y: i8) -> NormalMove {
+        (Position::new(x, y), ResourceField::empty())
+    }
+
+    fn worker(worker: Worker, movement: Movement<NormalMove>) -> UnitMovement {
+        UnitMovement::Worker(worker, movement)
+    }
+
+    fn moves(movements: &[UnitMovement]) -> Primary {
+        Primary::Move(match movements {
+            [m1] => Move::Move1(*m1),
+            [m1, m2] => Move::Move2(*m1, *m2),
+            [m1, m2, m3] => Move::Move3(*m1, *m2, *m3),
+            _ => unreachable!(),
+        })
+    }
+
+    fn trade(unit: Worker, from: Resource, to: Resource) -> TradeUnit {
+        (UnitPosition::Worker(unit), from, to)
+    }
+
+    #[test]
+    fn unevolved_actions() {
+        let game = game(2);
+        let movement = moves(&[
+            worker(Worker::First, Movement::Single(to(2, 0))),
+            worker(Worker::Second, Movement::Single(to(2, 0))),
+            UnitMovement::Character(Movement::Single(to(2, 2))),
+        ]);
+        assert_eq!(
+            check_primary(&game, &movement),
+            Some(IllegalMove::NotEvolved(PrimaryAction::Move))
+        );
+        let produce = Produce::Produce3(Worker::First, Worker::Second, Worker::Third);
+        assert_eq!(
+            check_primary(&game, &Primary::Produce(produce)),
+            Some(IllegalMove::NotEvolved(PrimaryAction::Produce))
+        );
+    }
+
+    #[test]
+    fn actions_that_cost_coins() {
+        let mut game = game(2);
+        game.get_active_player_mut().coins = 0;
+        let trade = Trade::Trade1(trade(Worker::First, Resource::Wood, Resource::Oil));
+        for (primary, action) in [
+            (Primary::Promote, PrimaryAction::Promote),
+            (Primary::Bolster, PrimaryAction::Bolster),
+            (Primary::Enforce, PrimaryAction::Enforce),
+            (Primary::Trade(trade), PrimaryAction::Trade),
+        ] {
+            assert_eq!(
+                check_primary(&game, &primary),
+                Some(IllegalMove::NotEnoughCoins { action, coins: 0 }),
+                "{primary:?}"
+            );
+        }
+        assert_eq!(check_primary(&game, &Primary::Tax), None);
+    }
+
+    #[test]
+    fn failed_sub_moves() {
+        let mut game = game(2);
+        let rejected = |game: &Game, movements: &[UnitMovement]| match check_primary(
+            game,
+            &moves(movements),
+        ) {
+            Some(IllegalMove::Movement {
+                index,
+                unit,
+                reason,
+            }) => Some((index, unit, reason)),
+            _ => None,
+        };
+        let w = UnitPosition::Worker;
+        let p = Position::new;
+        let (w1, w2, w3) = (Worker::First, Worker::Second, Worker::Third);
+
+        assert_eq!(
+            rejected(
+                &game,
+                &[
+                    worker(w1, Movement::Single(to(2, 0))),
+                    worker(w2, Movement::Single(to(9, 9)))
+                ]
+            ),
+            Some((1, w(w2), MoveError::InvalidPosition(p(9, 9))))
+        );
+        assert_eq!(
+            rejected(
+                &game,
+                &[
+                    worker(w1, Movement::Single(to(2, 0))),
+                    worker(w1, Movement::Single(to(2, 2)))
+                ]
+            ),
+            Some((1, w(w1), MoveError::AlreadyMoved))
+        );
+        for (movement, reason) in [
+            (
+                worker(w3, Movement::Single(to(2, 0))),
+                MoveError::UnitNotDeployed,
+            ),
+            (
+                worker(w1, Movement::Single(to(2, 1))),
+                MoveError::SameField(p(2, 1)),
+            ),
+            (
+                worker(w1, Movement::Single(to(-3, 0))),
+                MoveError::NotAdjacent {
+                    from: p(2, 1),
+                    to: p(-3, 0),
+                },
+            ),
+            (
+                worker(w2, Movement::Single(to(3, -1))),
+                MoveError::Lake(p(3, -1)),
+            ),
+            (
+                worker(w1, Movement::Single(to(1, 1))),
+                MoveError::River {
+                    from: p(2, 1),
+                    to: p(1, 1),
+                },
+            ),
+            (
+                worker(w1, Movement::Double(to(2, 0), to(1, 0))),
+                MoveError::WorkerDouble,
+            ),
+            (
+                worker(w1, Movement::Single((p(2, 0), missing(Resource::Wood, 1)))),
+                MoveError::NotEnoughResources {
+                    position: p(2, 1),
+                    missing: missing(Resource::Wood, 1),
+                },
+            ),
+        ] {
+            let UnitMovement::Worker(unit, _) = movement else {
+                unreachable!()
+            };
+            assert_eq!(
+                rejected(&game, &[movement]),
+                Some((0, w(unit), reason)),
+                "{movement:?}"
+            );
+        }
+
+        let character = UnitMovement::Character(Movement::Double(to(2, 2), to(1, 2)));
+        assert_eq!(
+            rejected(&game, &[character]),
+            Some((0, UnitPosition::Character, MoveError::SpeedRequired))
+        );
+        let home = field(&game, 3, 1);
+        game.get_active_player_mut()
+            .mechs
+            .deploy(Mech::Third, &home);
+        assert_eq!(
+            rejected(&game, &[character]),
+            Some((0, UnitPosition::Character, MoveError::Encounter(p(2, 2))))
+        );
+
+        // Workers do not fight, the character and mechs would
+        let mountain = field(&game, 3, 0);
+        Rc::make_mut(&mut game.players[1])
+            .production
+            .set(w1, &mountain);
+        assert_eq!(
+            rejected(&game, &[worker(w1, Movement::Single(to(3, 0)))]),
+            Some((0, w(w1), MoveError::EnemyUnits(p(3, 0))))
+        );
+        let mech = UnitMovement::Mech(
+            Mech::Third,
+            Movement::Single((p(3, 0), WorkerMask::empty(), ResourceField::empty())),
+        );
+        assert_eq!(
+            rejected(&game, &[mech]),
+            Some((
+                0,
+                UnitPosition::Mech(Mech::Third),
+                MoveError::Combat(p(3, 0))
+            ))
+        );
+    }
+
+    #[test]
+    fn trades() {
+        let mut game = game(2);
+        let w1 = UnitPosition::Worker(Worker::First);
+        let wood_for_oil = trade(Worker::First, Resource::Wood, Resource::Oil);
+        let trade1 = Primary::Trade(Trade::Trade1(wood_for_oil));
+        assert_eq!(
+            check_primary(
+                &game,
+                &Primary::Trade(Trade::Trade1(trade(
+                    Worker::Third,
+                    Resource::Wood,
+                    Resource::Oil
+                )))
+            ),
+            Some(IllegalMove::UnitNotDeployed(UnitPosition::Worker(
+                Worker::Third
+            )))
+        );
+        assert_eq!(
+            check_primary(&game, &trade1),
+            Some(IllegalMove::NotEnoughResources {
+                unit: w1,
+                position: Position::new(2, 1),
+                missing: missing(Resource::Wood, 1),
+            })
+        );
+
+        // Two trades from the same field and resource need both units of it
+        add_resource(&mut game, (2, 1), Resource::Wood, 1);
+        assert_eq!(check_primary(&game, &trade1), None);
+        let trade2 = Trade::Trade2(
+            wood_for_oil,
+            trade(Worker::First, Resource::Wood, Resource::Metal),
+        );
+        assert_eq!(
+            check_primary(&game, &Primary::Trade(trade2)),
+            Some(IllegalMove::NotEnoughResources {
+                unit: w1,
+                position: Position::new(2, 1),
+                missing: missing(Resource::Wood, 1),
+            })
+        );
+
+        // A field shared with a unit of an earlier seat belongs to that seat
+        game.turn = 1;
+        add_resource(&mut game, (-1, 3), Resource::Wood, 1);
+        let woods = field(&game, -1, 3);
+        assert_eq!(check_primary(&game, &trade1), None);
+        Rc::make_mut(&mut game.players[0])
+            .production
+            .set(Worker::First, &woods);
+        assert_eq!(
+            check_primary(&game, &trade1),
+            Some(IllegalMove::FieldControlledByEnemy {
+                unit: w1,
+                position: Position::new(-1, 3),
+            })
+        );
+
+        // Only a unit that does not stand on the field trades from an empty field
+        let player = game.get_active_player().clone();
+        assert_eq!(
+            check_field_for_trade(
+                &game,
+                &player,
+                &UnitPosition::Character,
+                &field(&game, 0, 0),
+                &Resource::Wood,
+                1
+            ),
+            Some(IllegalMove::FieldNotControlled {
+                unit: UnitPosition::Character,
+                position: Position::new(0, 0),
+            })
+        );
+    }
+
+    #[test]
+    fn production() {
+        let mut game = game(2);
+        let village = field(&game, 2, 1);
+        let lake = field(&game, 0, 1);
+        let produce = |produce| Primary::Produce(produce);
+
+        game.get_active_player_mut()
+            .production
+            .set(Worker::Second, &village);
+        assert_eq!(
+            check_primary(
+                &game,
+                &produce(Produce::Produce2(Worker::First, Worker::Second))
+            ),
+            Some(IllegalMove::DuplicateProduction {
+                worker: Worker::Second,
+                position: Position::new(2, 1),
+            })
+        );
+        assert_eq!(
+            check_primary(
+                &game,
+                &produce(Produce::Produce2(Worker::First, Worker::Third))
+            ),
+            Some(IllegalMove::UnitNotDeployed(UnitPosition::Worker(
+                Worker::Third
+            )))
+        );
+
+        game.get_active_player_mut()
+            .production
+            .set(Worker::First, &lake);
+        assert_eq!(
+            check_primary(&game, &produce(Produce::Produce1(Worker::First))),
+            Some(IllegalMove::UnproducibleTile {
+                worker: Worker::First,
+                position: Position::new(0, 1),
+                tile: Tile::Lake,
+            })
+        );
+
+        // With four workers production costs power
+        let player = game.get_active_player_mut();
+        player.production.deploy(&village);
+        player.production.deploy(&village);
+        player.military.power = 0;
+        assert_eq!(
+            check_primary(&game, &produce(Produce::Produce1(Worker::Second))),
+            Some(IllegalMove::CannotPayProduction)
+        );
+    }
+
+    /// The unit pays all of the cost
+    fn pay(unit: UnitPosition, amount: usize) -> ResourceCost {
+        match amount {
+            1 => ResourceCost::One(unit),
+            2 => ResourceCost::Two(unit, unit),
+            3 => ResourceCost::Three(unit, unit, unit),
+            4 => ResourceCost::Four(unit, unit, unit, unit),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn secondary_actions() {
+        let mut game = game(2);
+        let w1 = UnitPosition::Worker(Worker::First);
+        assert_eq!(
+            check_secondary(
+                &game,
+                &Primary::Tax,
+                &Secondary::Upgrade(PrimaryUpgrade::Move, SecondaryUpgrade::Deploy, pay(w1, 3))
+            ),
+            Some(IllegalMove::SecondaryNotLinked {
+                primary: PrimaryAction::Tax,
+                secondary: SecondaryAction::Upgrade,
+            })
+        );
+
+        let village = field(&game, 2, 1);
+        let player = game.get_active_player_mut();
+        player.upgrades.move_evolved = true;
+        player.upgrades.deploy_evolution_cost = 0;
+        player.mechs.deploy(Mech::First, &village);
+        player.recruits.recruit(Recruit::Power, Recruit::Coin);
+        let produce = Primary::Produce(Produce::Produce1(Worker::First));
+        for (primary, secondary, reason) in [
+            (
+                Primary::Bolster,
+                Secondary::Upgrade(PrimaryUpgrade::Move, SecondaryUpgrade::Build, pay(w1, 3)),
+                IllegalMove::PrimaryAlreadyUpgraded(PrimaryUpgrade::Move),
+            ),
+            (
+                Primary::Bolster,
+                Secondary::Upgrade(PrimaryUpgrade::Tax, SecondaryUpgrade::Deploy, pay(w1, 3)),
+                IllegalMove::SecondaryAlreadyUpgraded(SecondaryUpgrade::Deploy),
+            ),
+            (
+                produce,
+                Secondary::Deploy(Mech::First, Worker::First, pay(w1, 3)),
+                IllegalMove::MechAlreadyDeployed(Mech::First),
+            ),
+            (
+                produce,
+                Secondary::Deploy(Mech::Second, Worker::Third, pay(w1, 3)),
+                IllegalMove::UnitNotDeployed(UnitPosition::Worker(Worker::Third)),
+            ),
+            (
+                Primary::Promote,
+                Secondary::Enlist(Recruit::Power, Recruit::Card, pay(w1, 4)),
+                IllegalMove::SecondaryAlreadyRecruited(Recruit::Power),
+            ),
+            (
+                Primary::Promote,
+                Secondary::Enlist(Recruit::Card, Recruit::Coin, pay(w1, 4)),
+                IllegalMove::OnetimeAlreadyRecruited(Recruit::Coin),
+            ),
+        ] {
+            assert_eq!(
+                check_secondary(&game, &primary, &secondary),
+                Some(reason),
+                "{secondary:?}"
+            );
+        }
+
+        add_resource(&mut game, (2, 1), Resource::Oil, 3);
+        let upgrade = Secondary::Upgrade(PrimaryUpgrade::Tax, SecondaryUpgrade::Build, pay(w1, 3));
+        assert_eq!(check_secondary(&game, &Primary::Bolster, &upgrade), None);
+    }
+
+    #[test]
+    fn buildings() {
+        let mut game = game(2);
+        add_resource(&mut game, (2, 1), Resource::Wood, 3);
+        let w1 = UnitPosition::Worker(Worker::First);
+        let build = |game: &Game, building, worker| {
+            let secondary = Secondary::Build(building, worker, pay(w1, 3));
+            check_secondary(game, &Primary::Tax, &secondary)
+        };
+        assert_eq!(build(&game, Building::Mill, Worker::First), None);
+        assert_eq!(
+            build(&game, Building::Mill, Worker::Third),
+            Some(IllegalMove::UnitNotDeployed(UnitPosition::Worker(
+                Worker::Third
+            )))
+        );
+
+        let (village, lake, home) = (field(&game, 2, 1), field(&game, 0, 1), field(&game, 3, 1));
+        let player = game.get_active_player_mut();
+        player.buildings.built(Building::Armory, &village);
+        player.production.set(Worker::Second, &lake);
+        player.production.deploy(&home);
+        assert_eq!(
+            build(&game, Building::Armory, Worker::First),
+            Some(IllegalMove::AlreadyBuilt(Building::Armory))
+        );
+        assert_eq!(
+            build(&game, Building::Mill, Worker::First),
+            Some(IllegalMove::FieldHasBuilding {
+                worker: Worker::First,
+                position: Position::new(2, 1),
+            })
+        );
+        for (worker, position, tile) in [
+            (Worker::Second, Position::new(0, 1), Tile::Lake),
+            (Worker::Third, Position::new(3, 1), Tile::Home),
+        ] {
+            assert_eq!(
+                build(&game, Building::Mill, worker),
+                Some(IllegalMove::UnbuildableTile {
+                    worker,
+                    position,
+                    tile,
+                }),
+                "{worker:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn payments() {
+        let mut game = game(2);
+        let w1 = UnitPosition::Worker(Worker::First);
+        let w2 = UnitPosition::Worker(Worker::Second);
+        let pay_mill = |game: &Game, cost| {
+            let secondary = Secondary::Build(Building::Mill, Worker::First, cost);
+            check_secondary(game, &Primary::Tax, &secondary)
+        };
+        add_resource(&mut game, (2, 1), Resource::Wood, 2);
+        add_resource(&mut game, (3, 0), Resource::Wood, 1);
+
+        assert_eq!(
+            pay_mill(&game, pay(w1, 2)),
+            Some(IllegalMove::ResourceCostMismatch {
+                expected: 3,
+                given: 2,
+            })
+        );
+        assert_eq!(
+            pay_mill(
+                &game,
+                ResourceCost::Three(w1, w1, UnitPosition::Worker(Worker::Third))
+            ),
+            Some(IllegalMove::UnitNotDeployed(UnitPosition::Worker(
+                Worker::Third
+            )))
+        );
+        assert_eq!(
+            pay_mill(&game, pay(w1, 3)),
+            Some(IllegalMove::NotEnoughResources {
+                unit: w1,
+                position: Position::new(2, 1),
+                missing: missing(Resource::Wood, 1),
+            })
+        );
+        // The whole share of the field is missing, not only the first unit it cannot pay
+        assert_eq!(
+            pay_mill(&game, pay(w2, 3)),
+            Some(IllegalMove::NotEnoughResources {
+                unit: w2,
+                position: Position::new(3, 0),
+                missing: missing(Resource::Wood, 2),
+            })
+        );
+        assert_eq!(pay_mill(&game, ResourceCost::Three(w1, w1, w2)), None);
+        for cost in [
+            ResourceCost::Three(w2, w1, w1),
+            ResourceCost::Three(w1, w2, w1),
+        ] {
+            assert_eq!(
+                pay_mill(&game, cost),
+                Some(IllegalMove::UnorderedPayment(w1)),
+                "{cost:?}"
+            );
+        }
+
+        // A field shared with a unit of an earlier seat belongs to that seat
+        game.turn = 1;
+        add_resource(&mut game, (-1, 3), Resource::Wood, 4);
+        let woods = field(&game, -1, 3);
+        let polania = game.get_active_player().clone();
+        let build = pay(w1, 4);
+        let action = SecondaryAction::Build;
+        assert_eq!(check_payment(&game, &polania, &action, &build), None);
+        Rc::make_mut(&mut game.players[0])
+            .production
+            .set(Worker::First, &woods);
+        assert_eq!(
+            check_payment(&game, &polania, &action, &build),
+            Some(IllegalMove::FieldControlledByEnemy {
+                unit: w1,
+                position: Position::new(-1, 3),
+            })
+        );
+
+        // A payer that is not on the board of the game controls nothing
+        let mut stranger = (*polania).clone();
+        stranger.production.set(Worker::First, &field(&game, 0, 0));
+        assert_eq!(
+            check_payment(&game, &Rc::new(stranger), &action, &build),
+            Some(IllegalMove::FieldNotControlled {
+                unit: w1,
+                position: Position::new(0, 0),
+            })
+        );
+    }
 }
@@ -1,47 +1,58 @@
-use crate::{game::game::Game, turn::mask::TurnMask};
+use crate::{
+    game::{
+        Resource, Tile, board::ResourceField, game::Game, production::WORKERS, recruits::Recruit,
+        upgrades::SecondaryUpgrade,
+    },
+    template::{Position, PrimaryAction, SecondaryAction},
+    turn::mask::{
+        Movement, Primary, ResourceCost, Secondary, Trade, TradeUnit, TurnMask, UnitMovement,
+        UnitPosition, WorkerMask,
+    },
+};
 
-pub fn turn(mut game: Game, mask: &TurnMask) {
+/// Executes the turn of the active player without any checks and passes the turn on
+pub fn turn(game: &mut Game, mask: &TurnMask) {
     match mask {
         TurnMask::PrimaryOnly(primary) => {
-            execute_primary(&mut game, primary)
+            execute_primary(game, primary);
         }
-        TurnMask::PrimaryAndSecondary(primary, secondary) {
-            execute_primary(&mut state, primary);
-            execute_secondary(&mut state, secondary);
+        TurnMask::PrimaryAndSecondary(primary, secondary) => {
+            execute_primary(game, primary);
+            execute_secondary(game, secondary);
         }
-        _ => {}
     }
+    game.turn += 1;
 }
 
-
-fn execute_primary(mut state: &mut PlayerState, primary: &Primary) {
-    state.turns += 1;
+pub fn execute_primary(game: &mut Game, primary: &Primary) {
     match primary {
-        Primary::Move(Move1(tiles1)) => {
-            move_people(&mut state, tiles1);
-        }
-        Primary::Move(Move2(tiles1, tiles2)) => {
-            move_people(&mut state, tiles1);
-            move_people(&mut state, tiles2);
-        }
-        Primary::Move(Move3(tiles1, tiles2, tiles3)) => {
-            move_people(&mut state, tiles1);
-            move_people(&mut state, tiles2);
-            move_people(&mut state, tiles3);
+        Primary::Move(movement) => {
+            for mov in movement.movements() {
+                execute_movement(game, mov);
+            }
         }
         Primary::Tax => {
+            let state = game.get_active_player_mut();
             state.coins += if state.upgrades.tax_evolved { 2 } else { 1 };
         }
-        Primary::Trade(res1, res2) => {
-            state.coins -= 1;
-            state.resources.add(*res1, 1);
-            state.resources.add(*res2, 1);
-            if state.buildings.armory_built {
+        Primary::Trade(trade) => {
+            match trade {
+                Trade::Trade1(u) => trade_resource(game, u),
+                Trade::Trade2(u1, u2) => {
+                    trade_resource(game, u1);
+                    trade_resource(game, u2);
+                }
+            }
+
+            let state = game.get_active_player_mut();
+            state.coins = state.coins.saturating_sub(1);
+            if state.buildings.armory.is_some() {
                 state.military.add(1);
             }
         }
         Primary::Promote => {
-            state.coins -= 1;
+            let state = game.get_active_player_mut();
+            state.coins = state.coins.saturating_sub(1);
             let popularity_increase = if state.upgrades.popularity_evolved {
                 2
             } else {
@@ -49,156 +60,220 @@ fn execute_primary(mut state: &mut PlayerState, primary: &Primary) {
             };
             state.popularity.add(popularity_increase);
 
-            if state.buildings.armory_built {
+            if state.buildings.armory.is_some() {
                 state.military.add(1);
             }
         }
         Primary::Bolster => {
-            state.coins -= 1;
+            let state = game.get_active_player_mut();
+            state.coins = state.coins.saturating_sub(1);
             let power_increase = if state.upgrades.power_evolved { 3 } else { 2 };
             state.military.add(power_increase);
 
-            if state.buildings.monument_built {
+            if state.buildings.monument.is_some() {
                 state.popularity.add(1);
             }
         }
         Primary::Enforce => {
-            state.coins -= 1;
+            let state = game.get_active_player_mut();
+            state.coins = state.coins.saturating_sub(1);
             let card_increase = if state.upgrades.card_evolved { 2 } else { 1 };
-            state.cards += card_increase;
+            state.cards = state.cards.saturating_add(card_increase);
 
-            if state.buildings.monument_built {
+            if state.buildings.monument.is_some() {
                 state.popularity.add(1);
             }
         }
-        Primary::Produce(Produce1(tile1)) => {
-            let total = state.production.total();
+        Primary::Produce(produce) => {
+            let state = game.get_active_player_mut();
+            let total = state.production.deployed_workers;
             if total >= 4 {
-                state.military.add(-1)
+                state.military.sub(1)
             }
             if total >= 6 {
-                state.popularity.add(-1)
+                state.popularity.sub(1)
             }
             if total >= 8 {
-                state.coins -= 1
+                state.coins = state.coins.saturating_sub(1)
             }
 
-            produce_resource(&mut state, tile1);
-
-            match state.buildings.mill_location {
-                Some(location) => {
-                    produce_resource(&mut state, &location);
+            // Count the producing workers before new workers are deployed
+            let mut production: Vec<(Position, u32)> = Vec::new();
+            for worker in produce.workers() {
+                if let Some(field) = state.production.get(worker) {
+                    production.push((field.position, state.production.amount(field).into()));
                 }
-                None => {}
             }
-        }
-        Primary::Produce(Produce2(tile1, tile2)) => {
-            let total = state.production.total();
-            if total >= 4 {
-                state.military.add(-1)
-            }
-            if total >= 6 {
-                state.popularity.add(-1)
+            if let Some(mill) = state.buildings.mill.as_ref() {
+                production.push((mill.position, 1));
             }
-            if total >= 8 {
-                state.coins -= 1
-            }
-
-            produce_resource(&mut state, tile1);
-            produce_resource(&mut state, tile2);
 
-            match state.buildings.mill_location {
-                Some(location) => {
-                    produce_resource(&mut state, &location);
-                }
-                None => {}
+            for (position, amount) in production {
+                produce_resource(game, &position, amount);
             }
         }
-        Primary::Produce(Produce3(tile1, tile2, tile3)) => {
-            let total = state.production.total();
-            if total >= 4 {
-                state.military.add(-1)
-            }
-            if total >= 6 {
-                state.popularity.add(-1)
-            }
-            if total >= 8 {
-                state.coins -= 1
-            }
-
-            produce_resource(&mut state, tile1);
-            produce_resource(&mut state, tile2);
-            produce_resource(&mut state, tile3);
+    }
+}
 
-            match state.buildings.mill_location {
-                Some(location) => {
-                    produce_resource(&mut state, &location);
-                }
-                None => {}
-            }
+pub fn execute_secondary(game: &mut Game, secondary: &Secondary) {
+    let action = map_secondary(secondary);
+    match secondary {
+        Secondary::Upgrade(_, _, cost)
+        | Secondary::Deploy(_, _, cost)
+        | Secondary::Build(_, _, cost)
+        | Secondary::Enlist(_, _, cost) => {
+            pay_resources(game, cost, &map_secondary_resource(&action))
         }
     }
-}
 
-fn execute_secondary(state: &mut PlayerState, secondary: &Secondary) {
-    let cost = state.upgrades.get_upgrade_cost(&map_secondary(secondary));
+    let state = game.get_active_player_mut();
     match secondary {
-        Secondary::Upgrade(primary, secondary) if state.resources.oil >= cost => {
+        Secondary::Upgrade(primary, secondary, _) => {
             if state.recruits.is_secondary_recruited(Recruit::Power) {
                 state.military.add(1);
             }
-            state.resources.oil -= cost;
             state.upgrades.upgrade(*primary, *secondary);
             state.coins += state.upgrades.get_upgrade_coins(&SecondaryUpgrade::Upgrade);
         }
-        Secondary::Deploy(Deploy(mech)) if state.resources.metal >= cost => {
+        Secondary::Deploy(mech, worker, _) => {
             if state.recruits.is_secondary_recruited(Recruit::Coin) {
                 state.coins += 1;
             }
-            state.resources.metal -= cost;
-            state.mechs.deploy(*mech);
+            if let Some(field) = state.production.get(*worker).cloned() {
+                state.mechs.deploy(*mech, &field);
+            }
             state.coins += state.upgrades.get_upgrade_coins(&SecondaryUpgrade::Deploy);
         }
-        Secondary::Build(building) if state.resources.wood >= cost => {
+        Secondary::Build(building, worker, _) => {
             if state.recruits.is_secondary_recruited(Recruit::Popularity) {
                 state.popularity.add(1);
             }
-            state.resources.wood -= cost;
-            state.buildings.built(*building);
+            if let Some(field) = state.production.get(*worker).cloned() {
+                state.buildings.built(*building, &field);
+            }
             state.coins += state.upgrades.get_upgrade_coins(&SecondaryUpgrade::Build);
         }
-        Secondary::Enlist(secondary, onetime) if state.resources.food >= cost => {
+        Secondary::Enlist(secondary, onetime, _) => {
             if state.recruits.is_secondary_recruited(Recruit::Card) {
-                state.cards += 1;
+                state.cards = state.cards.saturating_add(1);
             }
-            state.resources.food -= cost;
             state.recruits.recruit(*secondary, *onetime);
+            match onetime {
+                Recruit::Power => state.military.add(2),
+                Recruit::Coin => state.coins += 2,
+                Recruit::Popularity => state.popularity.add(2),
+                Recruit::Card => state.cards = state.cards.saturating_add(2),
+            }
             state.coins += state.upgrades.get_upgrade_coins(&SecondaryUpgrade::Enlist);
         }
-        _ => {}
     }
 }
 
-fn move_people(state: &mut PlayerState, (from, to): &(Tile, Tile)) {
-    if check_move_from(state, from) {
-        state.production.add(from, -1);
-        state.production.add(to, 1);
+fn execute_movement(game: &mut Game, movement: &UnitMovement) {
+    match movement {
+        UnitMovement::Character(mov) => match mov {
+            Movement::Single((p, r)) => {
+                move_unit(game, &UnitPosition::Character, p, *r, WorkerMask::empty())
+            }
+            Movement::Double((p1, r1), (p2, r2)) => {
+                move_unit(game, &UnitPosition::Character, p1, *r1, WorkerMask::empty());
+                move_unit(game, &UnitPosition::Character, p2, *r2, WorkerMask::empty());
+            }
+        },
+        UnitMovement::Worker(worker, mov) => {
+            let unit = UnitPosition::Worker(*worker);
+            match mov {
+                Movement::Single((p, r)) => move_unit(game, &unit, p, *r, WorkerMask::empty()),
+                Movement::Double((p1, r1), (p2, r2)) => {
+                    move_unit(game, &unit, p1, *r1, WorkerMask::empty());
+                    move_unit(game, &unit, p2, *r2, WorkerMask::empty());
+                }
+            }
+        }
+        UnitMovement::Mech(mech, mov) => {
+            let unit = UnitPosition::Mech(*mech);
+            match mov {
+                Movement::Single((p, w, r)) => move_unit(game, &unit, p, *r, *w),
+                Movement::Double((p1, w1, r1), (p2, w2, r2)) => {
+                    move_unit(game, &unit, p1, *r1, *w1);
+                    move_unit(game, &unit, p2, *r2, *w2);
+                }
+            }
+        }
+    }
+}
+
+/// Moves the unit together with the carried resources and workers to the target field
+fn move_unit(
+    game: &mut Game,
+    unit: &UnitPosition,
+    to: &Position,
+    resources: ResourceField,
+    workers: WorkerMask,
+) {
+    let from = match game.get_active_player().get_unit_field(unit) {
+        Some(field) => field.position,
+        None => return,
+    };
+    if resources.total() > 0 {
+        game.update_field(&from, |f| f.resources = f.resources - resources);
+        game.update_field(to, |f| f.resources = f.resources + resources);
+    }
+    let target = match game.board.get_field(to) {
+        Some(field) => field.clone(),
+        None => return,
+    };
+
+    let state = game.get_active_player_mut();
+    state.set_unit_field(unit, &target);
+    for worker in WORKERS {
+        if workers.contains_worker(worker) {
+            state.production.set(worker, &target);
+        }
     }
 }
 
-fn produce_resource(state: &mut PlayerState, tile: &Tile) {
+fn trade_resource(game: &mut Game, (unit, from, to): &TradeUnit) {
+    let position = match game.get_active_player().get_unit_field(unit) {
+        Some(field) => field.position,
+        None => return,
+    };
+    game.update_field(&position, |f| {
+        f.resources = f.resources - ResourceField::single(from, 1) + ResourceField::single(to, 1)
+    });
+}
+
+fn produce_resource(game: &mut Game, position: &Position, amount: u32) {
+    let tile = match game.board.get_field(position) {
+        Some(field) => field.tile,
+        None => return,
+    };
     match tile {
         Tile::Village => {
-            state
-                .production
-                .add(&Tile::Village, state.production.get(&Tile::Village));
+            if let Some(field) = game.board.get_field(position).cloned() {
+                let state = game.get_active_player_mut();
+                for _ in 0..amount {
+                    state.production.deploy(&field);
+                }
+            }
         }
-        _ => match map_tile_resource(&tile) {
-            Some(resource) => {
-                state.resources.add(resource, state.production.get(tile));
+        _ => {
+            if let Some(resource) = map_tile_resource(&tile) {
+                game.update_field(position, |f| f.resources.add_resource(&resource, amount));
             }
-            None => {}
-        },
+        }
+    }
+}
+
+fn pay_resources(game: &mut Game, cost: &ResourceCost, resource: &Resource) {
+    for unit in cost.units() {
+        let position = match game.get_active_player().get_unit_field(&unit) {
+            Some(field) => field.position,
+            None => continue,
+        };
+        game.update_field(&position, |f| {
+            f.resources = f.resources - ResourceField::single(resource, 1)
+        });
     }
 }
 
@@ -206,7 +281,7 @@ pub fn map_primary(primary: &Primary) -> PrimaryAction {
     match primary {
         Primary::Move(_) => PrimaryAction::Move,
         Primary::Tax => PrimaryAction::Tax,
-        Primary::Trade(_, _) => PrimaryAction::Trade,
+        Primary::Trade(_) => PrimaryAction::Trade,
         Primary::Promote => PrimaryAction::Promote,
         Primary::Bolster => PrimaryAction::Bolster,
         Primary::Enforce => PrimaryAction::Enforce,
@@ -216,10 +291,19 @@ pub fn map_primary(primary: &Primary) -> PrimaryAction {
 
 pub fn map_secondary(secondary: &Secondary) -> SecondaryAction {
     match secondary {
-        Secondary::Upgrade(_, _) => SecondaryAction::Upgrade,
-        Secondary::Deploy(Deploy(_)) => SecondaryAction::Deploy,
-        Secondary::Build(_) => SecondaryAction::Build,
-        Secondary::Enlist(_, _) => SecondaryAction::Enlist,
+        Secondary::Upgrade(_, _, _) => SecondaryAction::Upgrade,
+        Secondary::Deploy(_, _, _) => SecondaryAction::Deploy,
+        Secondary::Build(_, _, _) => SecondaryAction::Build,
+        Secondary::Enlist(_, _, _) => SecondaryAction::Enlist,
+    }
+}
+
+pub fn map_secondary_resource(secondary: &SecondaryAction) -> Resource {
+    match secondary {
+        SecondaryAction::Upgrade => Resource::Oil,
+        SecondaryAction::Deploy => Resource::Metal,
+        SecondaryAction::Build => Resource::Wood,
+        SecondaryAction::Enlist => Resource::Food,
     }
 }
 
@@ -229,6 +313,6 @@ pub fn map_tile_resource(tile: &Tile) -> Option<Resource> {
         Tile::Mountain => Some(Resource::Metal),
         Tile::Tundra => Some(Resource::Oil),
         Tile::Farm => Some(Resource::Food),
-        Tile::Village => None,
+        Tile::Village | Tile::Lake | Tile::Factory | Tile::Home => None,
     }
 }
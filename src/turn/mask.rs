@@ -43,6 +43,17 @@ pub enum Trade {
     Trade2(TradeUnit, TradeUnit),
 }
 
+impl Produce {
+    pub fn workers(&self) -> Vec<Worker> {
+        match self {
+            Produce::Produce1(w1) => vec![*w1],
+            Produce::Produce2(w1, w2) => vec![*w1, *w2],
+            Produce::Produce3(w1, w2, w3) => vec![*w1, *w2, *w3],
+        }
+    }
+}
+
+/// The unit at whose field the first resource is traded for the second resource
 pub type TradeUnit = (UnitPosition, Resource, Resource);
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
@@ -53,6 +64,25 @@ pub enum Move {
     Move3(UnitMovement, UnitMovement, UnitMovement),
 }
 
+impl Move {
+    pub fn from_movements(movements: &[UnitMovement]) -> Option<Move> {
+        match movements {
+            [m1] => Some(Move::Move1(*m1)),
+            [m1, m2] => Some(Move::Move2(*m1, *m2)),
+            [m1, m2, m3] => Some(Move::Move3(*m1, *m2, *m3)),
+            _ => None,
+        }
+    }
+
+    pub fn movements(&self) -> Vec<&UnitMovement> {
+        match self {
+            Move::Move1(m1) => vec![m1],
+            Move::Move2(m1, m2) => vec![m1, m2],
+            Move::Move3(m1, m2, m3) => vec![m1, m2, m3],
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum UnitMovement {
     Character(Movement<NormalMove>), // Might trigger an encounter
@@ -151,6 +181,38 @@ pub enum ResourceCost {
     Four(UnitPosition, UnitPosition, UnitPosition, UnitPosition),
 }
 
+impl ResourceCost {
+    /// The units paying one resource each
+    pub fn units(&self) -> Vec<UnitPosition> {
+        match self {
+            ResourceCost::One(u1) => vec![*u1],
+            ResourceCost::Two(u1, u2) => vec![*u1, *u2],
+            ResourceCost::Three(u1, u2, u3) => vec![*u1, *u2, *u3],
+            ResourceCost::Four(u1, u2, u3, u4) => vec![*u1, *u2, *u3, *u4],
+        }
+    }
+}
+
+pub const UNITS: [UnitPosition; 17] = [
+    UnitPosition::Character,
+    UnitPosition::Worker(Worker::First),
+    UnitPosition::Worker(Worker::Second),
+    UnitPosition::Worker(Worker::Third),
+    UnitPosition::Worker(Worker::Fourth),
+    UnitPosition::Worker(Worker::Fifth),
+    UnitPosition::Worker(Worker::Sixth),
+    UnitPosition::Worker(Worker::Seventh),
+    UnitPosition::Worker(Worker::Eighth),
+    UnitPosition::Mech(Mech::First),
+    UnitPosition::Mech(Mech::Second),
+    UnitPosition::Mech(Mech::Third),
+    UnitPosition::Mech(Mech::Fourth),
+    UnitPosition::Building(Building::Armory),
+    UnitPosition::Building(Building::Monument),
+    UnitPosition::Building(Building::Tunnel),
+    UnitPosition::Building(Building::Mill),
+];
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum UnitPosition {
     Character,
@@ -1,4 +1,6 @@
 pub mod check;
 pub mod execute;
 pub mod mask;
+pub mod notation;
+pub mod perft;
 pub mod predict;
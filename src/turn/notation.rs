@@ -0,0 +1,833 @@
+use std::{error::Error, fmt, str::FromStr};
+
+use crate::{
+    game::{
+        RESOURCES, Resource,
+        board::ResourceField,
+        buildings::{BUILDINGS, Building},
+        mechs::{MECHS, Mech},
+        production::{WORKERS, Worker},
+        recruits::{RECRUITS, Recruit},
+        upgrades::{PRIMARY_UPGRADES, PrimaryUpgrade, SECONDARY_UPGRADES, SecondaryUpgrade},
+    },
+    template::Position,
+    turn::{
+        execute::{map_secondary, map_secondary_resource},
+        mask::{
+            MechMove, Move, Movement, NormalMove, Primary, Produce, ResourceCost, Secondary, Trade,
+            TradeUnit, TurnMask, UNITS, UnitMovement, UnitPosition, WorkerMask,
+        },
+    },
+};
+
+/// The text could not be read as a turn
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotationError {
+    /// The byte offset in the text
+    pub offset: usize,
+    pub message: String,
+}
+
+impl NotationError {
+    /// The parsed text with a marker below the error
+    pub fn pointer(&self, text: &str) -> String {
+        let column = text[..self.offset.min(text.len())].chars().count();
+        format!("{text}\n{}^ {}", " ".repeat(column), self.message)
+    }
+}
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.offset + 1)
+    }
+}
+
+impl Error for NotationError {}
+
+impl fmt::Display for TurnMask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_turn(self))
+    }
+}
+
+impl fmt::Display for Primary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_primary(self))
+    }
+}
+
+impl fmt::Display for Secondary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_secondary(self))
+    }
+}
+
+impl FromStr for TurnMask {
+    type Err = NotationError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        parse_turn(text)
+    }
+}
+
+impl FromStr for Primary {
+    type Err = NotationError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        parse_primary(text)
+    }
+}
+
+impl FromStr for Secondary {
+    type Err = NotationError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        parse_secondary(text)
+    }
+}
+
+/// Writes the turn, e.g. `MOVE char(-1,3)+2oil; mech1(0,2)[w1,w3]; BUILD mill@w2 pay w1:2wood`
+pub fn format_turn(mask: &TurnMask) -> String {
+    match mask {
+        TurnMask::PrimaryOnly(primary) => format_primary(primary),
+        TurnMask::PrimaryAndSecondary(primary, secondary) => {
+            format!(
+                "{}; {}",
+                format_primary(primary),
+                format_secondary(secondary)
+            )
+        }
+    }
+}
+
+pub fn format_primary(primary: &Primary) -> String {
+    match primary {
+        Primary::Tax => "TAX".to_string(),
+        Primary::Promote => "PROMOTE".to_string(),
+        Primary::Bolster => "BOLSTER".to_string(),
+        Primary::Enforce => "ENFORCE".to_string(),
+        Primary::Trade(Trade::Trade1(t)) => format!("TRADE {}", format_trade(t)),
+        Primary::Trade(Trade::Trade2(t1, t2)) => {
+            format!("TRADE {}, {}", format_trade(t1), format_trade(t2))
+        }
+        Primary::Produce(produce) => {
+            let workers: Vec<String> = produce.workers().iter().map(format_worker).collect();
+            format!("PRODUCE {}", workers.join(", "))
+        }
+        Primary::Move(movement) => {
+            let units: Vec<String> = movement
+                .movements()
+                .into_iter()
+                .map(format_unit_movement)
+                .collect();
+            format!("MOVE {}", units.join("; "))
+        }
+    }
+}
+
+pub fn format_secondary(secondary: &Secondary) -> String {
+    let (action, cost) = match secondary {
+        Secondary::Upgrade(p, s, cost) => (
+            format!(
+                "UPGRADE {}/{}",
+                primary_upgrade_name(p),
+                secondary_upgrade_name(s)
+            ),
+            cost,
+        ),
+        Secondary::Deploy(mech, worker, cost) => (
+            format!("DEPLOY {}@{}", format_mech(mech), format_worker(worker)),
+            cost,
+        ),
+        Secondary::Build(building, worker, cost) => (
+            format!(
+                "BUILD {}@{}",
+                building_name(building),
+                format_worker(worker)
+            ),
+            cost,
+        ),
+        Secondary::Enlist(s, o, cost) => (
+            format!("ENLIST {}/{}", recruit_name(s), recruit_name(o)),
+            cost,
+        ),
+    };
+
+    // Consecutive payments of the same unit are grouped
+    let resource = resource_name(&map_secondary_resource(&map_secondary(secondary)));
+    let mut payers: Vec<(UnitPosition, u32)> = Vec::new();
+    for unit in cost.units() {
+        match payers.last_mut() {
+            Some((last, amount)) if *last == unit => *amount += 1,
+            _ => payers.push((unit, 1)),
+        }
+    }
+    let payers: Vec<String> = payers
+        .iter()
+        .map(|(unit, amount)| format!("{}:{amount}{resource}", format_unit(unit)))
+        .collect();
+    format!("{action} pay {}", payers.join(", "))
+}
+
+fn format_trade((unit, from, to): &TradeUnit) -> String {
+    format!(
+        "{}:{}>{}",
+        format_unit(unit),
+        resource_name(from),
+        resource_name(to)
+    )
+}
+
+fn format_unit_movement(movement: &UnitMovement) -> String {
+    match movement {
+        UnitMovement::Character(m) => format!("char{}", format_steps(m, format_normal_step)),
+        UnitMovement::Worker(w, m) => {
+            format!(
+                "{}{}",
+                format_worker(w),
+                format_steps(m, format_normal_step)
+            )
+        }
+        UnitMovement::Mech(mech, m) => {
+            format!("{}{}", format_mech(mech), format_steps(m, format_mech_step))
+        }
+    }
+}
+
+fn format_steps<P>(movement: &Movement<P>, step: fn(&P) -> String) -> String {
+    match movement {
+        Movement::Single(p) => step(p),
+        Movement::Double(p1, p2) => format!("{}{}", step(p1), step(p2)),
+    }
+}
+
+fn format_normal_step((position, cargo): &NormalMove) -> String {
+    format!("{}{}", format_position(position), format_cargo(cargo))
+}
+
+fn format_mech_step((position, workers, cargo): &MechMove) -> String {
+    let mut text = format_position(position);
+    if !workers.is_empty() {
+        let carried: Vec<String> = WORKERS
+            .iter()
+            .filter(|w| workers.contains_worker(**w))
+            .map(format_worker)
+            .collect();
+        text += &format!("[{}]", carried.join(","));
+    }
+    text + &format_cargo(cargo)
+}
+
+fn format_position(position: &Position) -> String {
+    format!("({},{})", position.x(), position.y())
+}
+
+fn format_cargo(cargo: &ResourceField) -> String {
+    RESOURCES
+        .iter()
+        .filter(|r| cargo.get(r) > 0)
+        .map(|r| format!("+{}{}", cargo.get(r), resource_name(r)))
+        .collect()
+}
+
+fn format_unit(unit: &UnitPosition) -> String {
+    match unit {
+        UnitPosition::Character => "char".to_string(),
+        UnitPosition::Worker(w) => format_worker(w),
+        UnitPosition::Mech(m) => format_mech(m),
+        UnitPosition::Building(b) => building_name(b).to_string(),
+    }
+}
+
+fn format_worker(worker: &Worker) -> String {
+    format!("w{}", *worker as usize + 1)
+}
+
+fn format_mech(mech: &Mech) -> String {
+    let index = MECHS.iter().position(|m| m == mech).unwrap_or(0);
+    format!("mech{}", index + 1)
+}
+
+fn resource_name(resource: &Resource) -> &'static str {
+    match resource {
+        Resource::Wood => "wood",
+        Resource::Metal => "metal",
+        Resource::Oil => "oil",
+        Resource::Food => "food",
+    }
+}
+
+fn building_name(building: &Building) -> &'static str {
+    match building {
+        Building::Armory => "armory",
+        Building::Monument => "monument",
+        Building::Tunnel => "tunnel",
+        Building::Mill => "mill",
+    }
+}
+
+fn recruit_name(recruit: &Recruit) -> &'static str {
+    match recruit {
+        Recruit::Popularity => "popularity",
+        Recruit::Power => "power",
+        Recruit::Card => "card",
+        Recruit::Coin => "coin",
+    }
+}
+
+fn primary_upgrade_name(upgrade: &PrimaryUpgrade) -> &'static str {
+    match upgrade {
+        PrimaryUpgrade::Move => "move",
+        PrimaryUpgrade::Tax => "tax",
+        PrimaryUpgrade::Promote => "promote",
+        PrimaryUpgrade::Produce => "produce",
+        PrimaryUpgrade::Bolster => "bolster",
+        PrimaryUpgrade::Enforce => "enforce",
+    }
+}
+
+fn secondary_upgrade_name(upgrade: &SecondaryUpgrade) -> &'static str {
+    match upgrade {
+        SecondaryUpgrade::Upgrade => "upgrade",
+        SecondaryUpgrade::Deploy => "deploy",
+        SecondaryUpgrade::Build => "build",
+        SecondaryUpgrade::Enlist => "enlist",
+    }
+}
+
+/// Reads a turn written by `format_turn`. Keywords and names are case insensitive.
+pub fn parse_turn(text: &str) -> Result<TurnMask, NotationError> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for part in text.split(';') {
+        segments.push(Cursor {
+            text,
+            offset: start,
+            end: start + part.len(),
+        });
+        start += part.len() + 1;
+    }
+
+    let mut segments = segments.into_iter();
+    let mut first = match segments.next() {
+        Some(segment) => segment,
+        None => return Err(error(0, "Empty turn")),
+    };
+    first.skip_whitespace();
+    let offset = first.offset;
+    let keyword = first.word()?;
+    let mut secondary = None;
+    let primary = match keyword.to_ascii_uppercase().as_str() {
+        "TAX" => Primary::Tax,
+        "PROMOTE" => Primary::Promote,
+        "BOLSTER" => Primary::Bolster,
+        "ENFORCE" => Primary::Enforce,
+        "TRADE" => {
+            let t1 = first.trade()?;
+            let trade = if first.eat(',') {
+                Trade::Trade2(t1, first.trade()?)
+            } else {
+                Trade::Trade1(t1)
+            };
+            Primary::Trade(trade)
+        }
+        "PRODUCE" => {
+            let mut workers = vec![first.worker()?];
+            while first.eat(',') {
+                workers.push(first.worker()?);
+            }
+            let produce = match workers[..] {
+                [w1] => Produce::Produce1(w1),
+                [w1, w2] => Produce::Produce2(w1, w2),
+                [w1, w2, w3] => Produce::Produce3(w1, w2, w3),
+                _ => return Err(first.error("At most three workers produce")),
+            };
+            Primary::Produce(produce)
+        }
+        "MOVE" => {
+            let mut movements = vec![first.unit_movement()?];
+            first.finish()?;
+            for mut segment in segments.by_ref() {
+                if segment.is_secondary() {
+                    secondary = Some(segment.secondary()?);
+                    break;
+                }
+                movements.push(segment.unit_movement()?);
+                segment.finish()?;
+            }
+            match Move::from_movements(&movements) {
+                Some(movement) => Primary::Move(movement),
+                None => return Err(first.error("At most three units move")),
+            }
+        }
+        _ => return Err(error(offset, "Unknown primary action")),
+    };
+    first.finish()?;
+
+    if secondary.is_none()
+        && let Some(mut segment) = segments.next()
+    {
+        secondary = Some(segment.secondary()?);
+    }
+    if let Some(segment) = segments.next() {
+        return Err(error(segment.offset, "Unexpected text after the turn"));
+    }
+
+    Ok(match secondary {
+        Some(secondary) => TurnMask::PrimaryAndSecondary(primary, secondary),
+        None => TurnMask::PrimaryOnly(primary),
+    })
+}
+
+/// Reads a primary action without a secondary action
+pub fn parse_primary(text: &str) -> Result<Primary, NotationError> {
+    match parse_turn(text)? {
+        TurnMask::PrimaryOnly(primary) => Ok(primary),
+        TurnMask::PrimaryAndSecondary(_, _) => {
+            let offset = text.rfind(';').unwrap_or(0);
+            Err(error(offset, "Expected only a primary action"))
+        }
+    }
+}
+
+/// Reads a secondary action, e.g. `BUILD mill@w2 pay w1:2wood`
+pub fn parse_secondary(text: &str) -> Result<Secondary, NotationError> {
+    Cursor {
+        text,
+        offset: 0,
+        end: text.len(),
+    }
+    .secondary()
+}
+
+fn error(offset: usize, message: &str) -> NotationError {
+    NotationError {
+        offset,
+        message: message.to_string(),
+    }
+}
+
+/// Reads one segment of the text
+struct Cursor<'a> {
+    text: &'a str,
+    offset: usize,
+    end: usize,
+}
+
+impl Cursor<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.offset..self.end]
+    }
+
+    fn error(&self, message: &str) -> NotationError {
+        error(self.offset, message)
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.offset += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(c) {
+            self.offset += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), NotationError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{c}'")))
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), NotationError> {
+        self.skip_whitespace();
+        if self.rest().is_empty() {
+            Ok(())
+        } else {
+            Err(self.error("Unexpected text"))
+        }
+    }
+
+    /// Letters followed by digits, e.g. `mech1`
+    fn word(&mut self) -> Result<String, NotationError> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let letters = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let digits = rest[letters..]
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len() - letters);
+        if letters == 0 {
+            return Err(self.error("Expected a name"));
+        }
+        let word = rest[..letters + digits].to_ascii_lowercase();
+        self.offset += letters + digits;
+        Ok(word)
+    }
+
+    fn number(&mut self) -> Result<i64, NotationError> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let sign = usize::from(rest.starts_with('-'));
+        let digits = rest[sign..]
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len() - sign);
+        match rest[..sign + digits].parse() {
+            Ok(number) => {
+                self.offset += sign + digits;
+                Ok(number)
+            }
+            Err(_) => Err(self.error("Expected a number")),
+        }
+    }
+
+    fn is_secondary(&self) -> bool {
+        let keyword = self.rest().split_whitespace().next().unwrap_or("");
+        ["UPGRADE", "DEPLOY", "BUILD", "ENLIST"]
+            .iter()
+            .any(|k| k.eq_ignore_ascii_case(keyword))
+    }
+
+    fn named<T: Copy>(&mut self, items: &[T], name: fn(&T) -> String) -> Result<T, NotationError> {
+        self.skip_whitespace();
+        let offset = self.offset;
+        let word = self.word()?;
+        items
+            .iter()
+            .find(|item| name(item) == word)
+            .copied()
+            .ok_or_else(|| error(offset, &format!("Unknown name '{word}'")))
+    }
+
+    fn worker(&mut self) -> Result<Worker, NotationError> {
+        self.named(&WORKERS, format_worker)
+    }
+
+    fn mech(&mut self) -> Result<Mech, NotationError> {
+        self.named(&MECHS, format_mech)
+    }
+
+    fn unit(&mut self) -> Result<UnitPosition, NotationError> {
+        self.named(&UNITS, format_unit)
+    }
+
+    fn resource(&mut self) -> Result<Resource, NotationError> {
+        self.named(&RESOURCES, |r| resource_name(r).to_string())
+    }
+
+    fn amount(&mut self) -> Result<u32, NotationError> {
+        self.skip_whitespace();
+        let offset = self.offset;
+        u32::try_from(self.number()?).map_err(|_| error(offset, "Expected a positive amount"))
+    }
+
+    fn trade(&mut self) -> Result<TradeUnit, NotationError> {
+        let unit = self.unit()?;
+        self.expect(':')?;
+        let from = self.resource()?;
+        self.expect('>')?;
+        let to = self.resource()?;
+        Ok((unit, from, to))
+    }
+
+    fn position(&mut self) -> Result<Position, NotationError> {
+        self.expect('(')?;
+        self.skip_whitespace();
+        let offset = self.offset;
+        let x = i8::try_from(self.number()?).map_err(|_| error(offset, "Invalid coordinate"))?;
+        self.expect(',')?;
+        self.skip_whitespace();
+        let offset = self.offset;
+        let y = i8::try_from(self.number()?).map_err(|_| error(offset, "Invalid coordinate"))?;
+        self.expect(')')?;
+        Ok(Position::new(x, y))
+    }
+
+    fn cargo(&mut self) -> Result<ResourceField, NotationError> {
+        let mut cargo = ResourceField::empty();
+        while self.eat('+') {
+            let amount = self.amount()?;
+            let resource = self.resource()?;
+            cargo.add_resource(&resource, amount);
+        }
+        Ok(cargo)
+    }
+
+    fn carried_workers(&mut self) -> Result<WorkerMask, NotationError> {
+        let mut workers = WorkerMask::empty();
+        if self.eat('[') {
+            loop {
+                workers |= WorkerMask::get_worker(self.worker()?);
+                if !self.eat(',') {
+                    break;
+                }
+            }
+            self.expect(']')?;
+        }
+        Ok(workers)
+    }
+
+    fn normal_step(&mut self) -> Result<NormalMove, NotationError> {
+        let position = self.position()?;
+        Ok((position, self.cargo()?))
+    }
+
+    fn mech_step(&mut self) -> Result<MechMove, NotationError> {
+        let position = self.position()?;
+        let workers = self.carried_workers()?;
+        Ok((position, workers, self.cargo()?))
+    }
+
+    fn steps<P>(
+        &mut self,
+        step: fn(&mut Self) -> Result<P, NotationError>,
+    ) -> Result<Movement<P>, NotationError> {
+        let first = step(self)?;
+        self.skip_whitespace();
+        if self.rest().starts_with('(') {
+            Ok(Movement::Double(first, step(self)?))
+        } else {
+            Ok(Movement::Single(first))
+        }
+    }
+
+    fn unit_movement(&mut self) -> Result<UnitMovement, NotationError> {
+        self.skip_whitespace();
+        let offset = self.offset;
+        match self.unit()? {
+            UnitPosition::Character => Ok(UnitMovement::Character(self.steps(Self::normal_step)?)),
+            UnitPosition::Worker(w) => Ok(UnitMovement::Worker(w, self.steps(Self::normal_step)?)),
+            UnitPosition::Mech(m) => Ok(UnitMovement::Mech(m, self.steps(Self::mech_step)?)),
+            UnitPosition::Building(_) => Err(error(offset, "Buildings cannot move")),
+        }
+    }
+
+    fn secondary(&mut self) -> Result<Secondary, NotationError> {
+        self.skip_whitespace();
+        let offset = self.offset;
+        let keyword = self.word()?;
+        let unpaid = ResourceCost::One(UnitPosition::Character);
+        let action = match keyword.as_str() {
+            "upgrade" => {
+                let p = self.named(&PRIMARY_UPGRADES, |u| primary_upgrade_name(u).to_string())?;
+                self.expect('/')?;
+                let s = self.named(&SECONDARY_UPGRADES, |u| {
+                    secondary_upgrade_name(u).to_string()
+                })?;
+                Secondary::Upgrade(p, s, unpaid)
+            }
+            "deploy" => {
+                let mech = self.mech()?;
+                self.expect('@')?;
+                Secondary::Deploy(mech, self.worker()?, unpaid)
+            }
+            "build" => {
+                let building = self.named(&BUILDINGS, |b| building_name(b).to_string())?;
+                self.expect('@')?;
+                Secondary::Build(building, self.worker()?, unpaid)
+            }
+            "enlist" => {
+                let s = self.named(&RECRUITS, |r| recruit_name(r).to_string())?;
+                self.expect('/')?;
+                let o = self.named(&RECRUITS, |r| recruit_name(r).to_string())?;
+                Secondary::Enlist(s, o, unpaid)
+            }
+            _ => return Err(error(offset, "Unknown secondary action")),
+        };
+
+        let cost = self.payment(&map_secondary_resource(&map_secondary(&action)))?;
+        self.finish()?;
+        Ok(match action {
+            Secondary::Upgrade(p, s, _) => Secondary::Upgrade(p, s, cost),
+            Secondary::Deploy(m, w, _) => Secondary::Deploy(m, w, cost),
+            Secondary::Build(b, w, _) => Secondary::Build(b, w, cost),
+            Secondary::Enlist(s, o, _) => Secondary::Enlist(s, o, cost),
+        })
+    }
+
+    /// The units paying for the secondary action, e.g. `pay w1:2wood, char:1wood`
+    fn payment(&mut self, resource: &Resource) -> Result<ResourceCost, NotationError> {
+        self.skip_whitespace();
+        let offset = self.offset;
+        if self.word()? != "pay" {
+            return Err(error(offset, "Expected 'pay'"));
+        }
+
+        let mut units = Vec::new();
+        loop {
+            let unit = self.unit()?;
+            self.expect(':')?;
+            self.skip_whitespace();
+            let amount_offset = self.offset;
+            let amount = self.amount()?;
+            // Checked before the units are listed, so that huge amounts are not allocated
+            if amount > 4 - units.len() as u32 {
+                return Err(error(
+                    amount_offset,
+                    "Between one and four resources are paid",
+                ));
+            }
+            self.skip_whitespace();
+            let offset = self.offset;
+            if self.resource()? != *resource {
+                return Err(error(
+                    offset,
+                    &format!("The action is paid with {}", resource_name(resource)),
+                ));
+            }
+            units.extend(std::iter::repeat_n(unit, amount as usize));
+            if !self.eat(',') {
+                break;
+            }
+        }
+        // A payment lists its units in the order of UNITS, whatever order they were written in
+        units.sort_by_key(|unit| UNITS.iter().position(|u| u == unit));
+
+        match units[..] {
+            [u1] => Ok(ResourceCost::One(u1)),
+            [u1, u2] => Ok(ResourceCost::Two(u1, u2)),
+            [u1, u2, u3] => Ok(ResourceCost::Three(u1, u2, u3)),
+            [u1, u2, u3, u4] => Ok(ResourceCost::Four(u1, u2, u3, u4)),
+            _ => Err(error(offset, "Between one and four resources are paid")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{
+        game::GameRng,
+        test_support::game,
+        turn::predict::{get_actions, sample_action},
+    };
+
+    fn round_trip(mask: &TurnMask) {
+        let text = format_turn(mask);
+        assert_eq!(parse_turn(&text).as_ref(), Ok(mask), "{text}");
+    }
+
+    #[test]
+    fn example() {
+        let text = "MOVE char(-1,3)+2oil; mech1(0,2)[w1,w3]; BUILD mill@w2 pay w1:2wood";
+        let mask = parse_turn(text).unwrap();
+        let TurnMask::PrimaryAndSecondary(Primary::Move(Move::Move2(character, mech)), secondary) =
+            mask
+        else {
+            panic!("{mask:?}");
+        };
+        assert_eq!(
+            character,
+            UnitMovement::Character(Movement::Single((
+                Position::new(-1, 3),
+                ResourceField::single(&Resource::Oil, 2)
+            )))
+        );
+        assert_eq!(
+            mech,
+            UnitMovement::Mech(
+                Mech::First,
+                Movement::Single((
+                    Position::new(0, 2),
+                    WorkerMask::get_worker(Worker::First) | WorkerMask::get_worker(Worker::Third),
+                    ResourceField::empty()
+                ))
+            )
+        );
+        assert_eq!(
+            secondary,
+            Secondary::Build(
+                Building::Mill,
+                Worker::Second,
+                ResourceCost::Two(
+                    UnitPosition::Worker(Worker::First),
+                    UnitPosition::Worker(Worker::First)
+                )
+            )
+        );
+        assert_eq!(format_turn(&mask), text);
+    }
+
+    #[test]
+    fn case_and_spacing() {
+        let mask = parse_turn(
+            " trade W1 : Wood > Oil ,char:food>metal ; enlist COIN/power pay char:1food",
+        )
+        .unwrap();
+        assert_eq!(
+            format_turn(&mask),
+            "TRADE w1:wood>oil, char:food>metal; ENLIST coin/power pay char:1food"
+        );
+        let mask = parse_turn("TAX; BUILD mill@w1 pay w2:1wood, w1:2wood").unwrap();
+        assert_eq!(
+            format_turn(&mask),
+            "TAX; BUILD mill@w1 pay w1:2wood, w2:1wood"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = "MOVE char(-1,3".parse::<TurnMask>().unwrap_err();
+        assert_eq!(error.offset, 14);
+        assert_eq!(
+            error.pointer("MOVE char(-1,3"),
+            "MOVE char(-1,3\n              ^ Expected ')'"
+        );
+
+        let error = "MOVE w9(0,0)".parse::<TurnMask>().unwrap_err();
+        assert_eq!(
+            (error.offset, error.message.as_str()),
+            (5, "Unknown name 'w9'")
+        );
+
+        let error = "TAX; BUILD mill@w1 pay w1:1oil"
+            .parse::<TurnMask>()
+            .unwrap_err();
+        assert_eq!(error.offset, 27);
+
+        assert!("TAX; BOLSTER".parse::<TurnMask>().is_err());
+        assert!(
+            "TAX; BUILD mill@w1 pay w1:5wood"
+                .parse::<TurnMask>()
+                .is_err()
+        );
+        for text in [
+            "TAX; BUILD mill@w1 pay w1:4000000000wood",
+            "TAX; BUILD mill@w1 pay w1:2wood, w2:3wood",
+        ] {
+            let error = text.parse::<TurnMask>().unwrap_err();
+            assert_eq!(
+                error.message, "Between one and four resources are paid",
+                "{text}"
+            );
+        }
+        assert!("TAX; BUILD mill@w1".parse::<TurnMask>().is_err());
+        assert!("PRODUCE w1, w2, w3, w4".parse::<TurnMask>().is_err());
+        assert!("".parse::<TurnMask>().is_err());
+    }
+
+    #[test]
+    fn generated_actions_round_trip() {
+        let mut game = game(2);
+        for mask in get_actions(&game) {
+            round_trip(&mask);
+        }
+
+        let mut rng = GameRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let mask = sample_action(&game, &mut rng);
+            round_trip(&mask);
+            game = game.apply(&mask).unwrap();
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use std::{collections::HashSet, error::Error, fmt};
+
+use crate::{
+    game::game::Game,
+    turn::{
+        check::IllegalMove,
+        mask::{Move, Primary, Produce, ResourceCost, Secondary, Trade, TurnMask},
+        predict::get_actions,
+    },
+};
+
+/// The generator and the validator disagree about the last turn of the line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PerftError {
+    /// A generated turn was rejected by the validator
+    Rejected {
+        line: Vec<TurnMask>,
+        reason: IllegalMove,
+    },
+    /// The same turn was generated more than once
+    Duplicate { line: Vec<TurnMask> },
+    /// A reordering of a generated turn was accepted by the validator, but not generated
+    Missing { line: Vec<TurnMask> },
+}
+
+impl fmt::Display for PerftError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PerftError::Rejected { line, reason } => write!(
+                f,
+                "Generated turn {:?} after {} turns was rejected: {reason}",
+                line.last(),
+                line.len() - 1
+            ),
+            PerftError::Duplicate { line } => write!(
+                f,
+                "Turn {:?} after {} turns was generated twice",
+                line.last(),
+                line.len() - 1
+            ),
+            PerftError::Missing { line } => write!(
+                f,
+                "Legal turn {:?} after {} turns was not generated",
+                line.last(),
+                line.len() - 1
+            ),
+        }
+    }
+}
+
+impl Error for PerftError {}
+
+/// Counts the legal turn sequences of the given length
+pub fn perft(game: &Game, depth: u32) -> Result<u64, PerftError> {
+    count(game, depth, &mut Vec::new())
+}
+
+/// Counts the legal turn sequences of the given length for every legal first turn
+pub fn perft_divide(game: &Game, depth: u32) -> Result<Vec<(TurnMask, u64)>, PerftError> {
+    if depth == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut line = Vec::new();
+    successors(game, &line)?
+        .into_iter()
+        .map(|(mask, next)| {
+            line.push(mask);
+            let nodes = count(&next, depth - 1, &mut line);
+            line.pop();
+            nodes.map(|nodes| (mask, nodes))
+        })
+        .collect()
+}
+
+fn count(game: &Game, depth: u32, line: &mut Vec<TurnMask>) -> Result<u64, PerftError> {
+    if depth == 0 {
+        return Ok(1);
+    }
+
+    let successors = successors(game, line)?;
+    if depth == 1 {
+        return Ok(successors.len() as u64);
+    }
+    let mut nodes = 0;
+    for (mask, next) in successors {
+        line.push(mask);
+        nodes += count(&next, depth - 1, line)?;
+        line.pop();
+    }
+    Ok(nodes)
+}
+
+/// Generates and validates every turn of the active player. The units of a turn are listed in
+/// some order, every other order of them has to be either generated as well or rejected.
+fn successors(game: &Game, line: &[TurnMask]) -> Result<Vec<(TurnMask, Game)>, PerftError> {
+    let mut seen = HashSet::new();
+    let mut successors = Vec::new();
+    for mask in get_actions(game) {
+        if !seen.insert(mask) {
+            return Err(PerftError::Duplicate {
+                line: [line, &[mask]].concat(),
+            });
+        }
+        match game.apply(&mask) {
+            Ok(next) => successors.push((mask, next)),
+            Err(reason) => {
+                return Err(PerftError::Rejected {
+                    line: [line, &[mask]].concat(),
+                    reason,
+                });
+            }
+        }
+    }
+
+    for (mask, _) in &successors {
+        for reordered in reorderings(mask) {
+            if !seen.contains(&reordered) && game.apply(&reordered).is_ok() {
+                return Err(PerftError::Missing {
+                    line: [line, &[reordered]].concat(),
+                });
+            }
+        }
+    }
+    Ok(successors)
+}
+
+/// The turn with the units of its move, production, trade and payment in every order
+fn reorderings(mask: &TurnMask) -> Vec<TurnMask> {
+    let (primary, secondary) = match mask {
+        TurnMask::PrimaryOnly(primary) => (primary, None),
+        TurnMask::PrimaryAndSecondary(primary, secondary) => (primary, Some(secondary)),
+    };
+    let primaries: Vec<Primary> = match primary {
+        Primary::Move(m) => {
+            let movements: Vec<_> = m.movements().into_iter().copied().collect();
+            permutations(&movements)
+                .iter()
+                .filter_map(|movements| Move::from_movements(movements))
+                .map(Primary::Move)
+                .collect()
+        }
+        Primary::Produce(produce) => permutations(&produce.workers())
+            .into_iter()
+            .filter_map(|workers| match workers[..] {
+                [w1] => Some(Produce::Produce1(w1)),
+                [w1, w2] => Some(Produce::Produce2(w1, w2)),
+                [w1, w2, w3] => Some(Produce::Produce3(w1, w2, w3)),
+                _ => None,
+            })
+            .map(Primary::Produce)
+            .collect(),
+        Primary::Trade(Trade::Trade2(t1, t2)) => vec![
+            Primary::Trade(Trade::Trade2(*t1, *t2)),
+            Primary::Trade(Trade::Trade2(*t2, *t1)),
+        ],
+        primary => vec![*primary],
+    };
+
+    let Some(secondary) = secondary else {
+        return primaries.into_iter().map(TurnMask::PrimaryOnly).collect();
+    };
+    let (Secondary::Upgrade(.., cost)
+    | Secondary::Deploy(.., cost)
+    | Secondary::Build(.., cost)
+    | Secondary::Enlist(.., cost)) = secondary;
+    let secondaries: Vec<Secondary> = permutations(&cost.units())
+        .into_iter()
+        .filter_map(|units| match units[..] {
+            [u1] => Some(ResourceCost::One(u1)),
+            [u1, u2] => Some(ResourceCost::Two(u1, u2)),
+            [u1, u2, u3] => Some(ResourceCost::Three(u1, u2, u3)),
+            [u1, u2, u3, u4] => Some(ResourceCost::Four(u1, u2, u3, u4)),
+            _ => None,
+        })
+        .map(|cost| match *secondary {
+            Secondary::Upgrade(p, s, _) => Secondary::Upgrade(p, s, cost),
+            Secondary::Deploy(m, w, _) => Secondary::Deploy(m, w, cost),
+            Secondary::Build(b, w, _) => Secondary::Build(b, w, cost),
+            Secondary::Enlist(s, o, _) => Secondary::Enlist(s, o, cost),
+        })
+        .collect();
+
+    primaries
+        .iter()
+        .flat_map(|primary| {
+            secondaries
+                .iter()
+                .map(|secondary| TurnMask::PrimaryAndSecondary(*primary, *secondary))
+        })
+        .collect()
+}
+
+/// All orders of the items, repeated items give repeated orders
+fn permutations<T: Copy>(items: &[T]) -> Vec<Vec<T>> {
+    if items.is_empty() {
+        return vec![Vec::new()];
+    }
+    (0..items.len())
+        .flat_map(|i| {
+            let mut rest = items.to_vec();
+            let item = rest.remove(i);
+            permutations(&rest).into_iter().map(move |mut order| {
+                order.insert(0, item);
+                order
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{
+        game::{GameRng, Resource},
+        template::Position,
+        test_support::game,
+        turn::predict::{get_fixed_primaries, get_secondaries, sample_action},
+    };
+
+    #[test]
+    fn perft_two_players() {
+        let game = game(2);
+        assert_eq!(perft(&game, 0), Ok(1));
+        assert_eq!(perft(&game, 1), Ok(58));
+        assert_eq!(perft(&game, 2), Ok(2204));
+    }
+
+    #[test]
+    fn perft_three_players() {
+        let game = game(3);
+        assert_eq!(perft(&game, 1), Ok(58));
+        assert_eq!(perft(&game, 2), Ok(2204));
+    }
+
+    #[test]
+    #[ignore = "slow in debug builds"]
+    fn perft_depth_three() {
+        assert_eq!(perft(&game(2), 3), Ok(140866));
+        assert_eq!(perft(&game(3), 3), Ok(103588));
+    }
+
+    #[test]
+    fn perft_divide_sums_up() {
+        let game = game(3);
+        let divide = perft_divide(&game, 2).unwrap();
+        let total: u64 = divide.iter().map(|(_, nodes)| nodes).sum();
+        assert_eq!(divide.len() as u64, perft(&game, 1).unwrap());
+        assert_eq!(total, perft(&game, 2).unwrap());
+    }
+
+    #[test]
+    fn sampled_reorderings_are_generated() {
+        let mut game = game(2);
+        // Enough resources to pay from several fields
+        for (x, y, amount) in [(2, 1, 2), (3, 0, 1), (-1, 3, 2), (0, 3, 1)] {
+            for resource in [
+                Resource::Wood,
+                Resource::Oil,
+                Resource::Metal,
+                Resource::Food,
+            ] {
+                game.update_field(&Position::new(x, y), |field| {
+                    field.resources.add_resource(&resource, amount)
+                });
+            }
+        }
+
+        // Moves are compared in the perft above, listing them all is too slow here
+        let mut rng = GameRng::seed_from_u64(7);
+        let mut checked = 0;
+        for _ in 0..30 {
+            for _ in 0..10 {
+                let mask = sample_action(&game, &mut rng);
+                for reordered in reorderings(&mask) {
+                    let (primary, secondary) = match reordered {
+                        TurnMask::PrimaryOnly(Primary::Move(_))
+                        | TurnMask::PrimaryAndSecondary(Primary::Move(_), _) => continue,
+                        TurnMask::PrimaryOnly(primary) => (primary, None),
+                        TurnMask::PrimaryAndSecondary(primary, secondary) => {
+                            (primary, Some(secondary))
+                        }
+                    };
+                    let generated = get_fixed_primaries(&game).contains(&primary)
+                        && secondary.is_none_or(|secondary| {
+                            get_secondaries(&game, &primary).contains(&secondary)
+                        });
+                    assert_eq!(
+                        generated,
+                        game.apply(&reordered).is_ok(),
+                        "{reordered:?} of {mask:?}"
+                    );
+                    checked += usize::from(reordered != mask);
+                }
+            }
+            game = game.apply(&sample_action(&game, &mut rng)).unwrap();
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn permutations_of_repeated_items() {
+        assert_eq!(permutations(&[1, 2]), vec![vec![1, 2], vec![2, 1]]);
+        assert_eq!(permutations(&[1, 1]).len(), 2);
+        assert_eq!(permutations::<i32>(&[]), vec![Vec::<i32>::new()]);
+    }
+}
@@ -1,271 +1,661 @@
+use std::rc::Rc;
+
+use rand::{Rng, seq::IndexedRandom};
+
 use crate::{
     game::{
-        turnhelper::{check_secondary_cost, map_primary, turn},
-        turnmask::{
-            Building, Mech, Move, Primary, Recruit, Resource, Secondary, SecondaryUpgrade, Tile,
-            TurnMask,
+        RESOURCES,
+        board::{Field, ResourceField},
+        buildings::BUILDINGS,
+        game::Game,
+        mechs::MECHS,
+        player::PlayerState,
+        production::{WORKERS, Worker},
+        recruits::RECRUITS,
+        upgrades::{PRIMARY_UPGRADES, SECONDARY_UPGRADES},
+    },
+    template::{PrimaryAction, SecondaryAction},
+    turn::{
+        check::{
+            History, check_build, check_carry_workers, check_character_movement, check_coins,
+            check_deploy, check_enlist, check_mech_movement, check_passing, check_payment,
+            check_primary, check_resources, check_speed, check_trade1, check_trade2, check_upgrade,
+            check_worker_movement,
+        },
+        execute::{execute_primary, map_primary, map_secondary_resource},
+        mask::{
+            Move, Movement, Primary, Produce, ResourceCost, Secondary, Trade, TradeUnit, TurnMask,
+            UNITS, UnitMovement, UnitPosition, WorkerMask,
         },
     },
-    game_state::PlayerState,
-    template::SecondaryAction,
 };
 
-use super::mask::{PrimaryUpgrade, Produce};
-
-pub fn get_actions(state: &PlayerState) -> Vec<TurnMask> {
+/// All legal turns of the active player
+pub fn get_actions(game: &Game) -> Vec<TurnMask> {
     let mut actions = Vec::new();
-    for primary in get_primaries(state) {
-        let primary_only = TurnMask::PrimaryOnly(primary);
+    for primary in get_primaries(game) {
         actions.push(TurnMask::PrimaryOnly(primary));
-        let secondary = state.get_secondary(map_primary(&primary));
-        let new_state = turn(*state, &primary_only);
-        if check_secondary_cost(&new_state, &secondary) {
-            for secondary in get_secondaries(&new_state, secondary) {
-                actions.push(TurnMask::PrimaryAndSecondary(primary, secondary));
-            }
+        for secondary in get_secondaries(game, &primary) {
+            actions.push(TurnMask::PrimaryAndSecondary(primary, secondary));
         }
     }
 
     actions
 }
 
-fn get_primaries(state: &PlayerState) -> Vec<Primary> {
-    let mut primaries = Vec::new();
-
-    primaries.push(Primary::Tax);
-    if state.coins >= 1 {
-        primaries.extend(get_trades());
-        primaries.push(Primary::Promote);
-        primaries.push(Primary::Bolster);
-        primaries.push(Primary::Enforce);
+/// Whether both secondary actions only differ in how they are paid
+fn same_choice(a: &Secondary, b: &Secondary) -> bool {
+    match (a, b) {
+        (Secondary::Upgrade(p1, s1, _), Secondary::Upgrade(p2, s2, _)) => p1 == p2 && s1 == s2,
+        (Secondary::Deploy(m1, w1, _), Secondary::Deploy(m2, w2, _)) => m1 == m2 && w1 == w2,
+        (Secondary::Build(b1, w1, _), Secondary::Build(b2, w2, _)) => b1 == b2 && w1 == w2,
+        (Secondary::Enlist(s1, o1, _), Secondary::Enlist(s2, o2, _)) => s1 == s2 && o1 == o2,
+        _ => false,
     }
-    if state.can_produce() {
-        primaries.extend(get_produces(state));
+}
+
+/// A subset of the legal turns of the active player, small enough for searches and policies.
+///
+/// Listing every turn is not feasible late in the game, so moves take a single unit without cargo,
+/// trades exchange a single resource and each secondary action is paid in one way only. Agents
+/// built on it never play the other turns; use `get_actions` for all of them.
+pub fn get_candidates(game: &Game) -> Vec<TurnMask> {
+    let player = game.get_active_player();
+    let mut primaries: Vec<Primary> = get_fixed_primaries(game)
+        .into_iter()
+        .filter(|primary| !matches!(primary, Primary::Trade(Trade::Trade2(..))))
+        .collect();
+    primaries.extend(
+        get_unit_movements(game, player, &History::new(), Cargo::Empty)
+            .into_iter()
+            .map(|(movement, _)| Primary::Move(Move::Move1(movement))),
+    );
+
+    let mut turns = Vec::new();
+    for primary in primaries {
+        turns.push(TurnMask::PrimaryOnly(primary));
+        let mut secondaries: Vec<Secondary> = Vec::new();
+        for secondary in get_secondaries(game, &primary) {
+            if !secondaries.iter().any(|s| same_choice(s, &secondary)) {
+                secondaries.push(secondary);
+            }
+        }
+        turns.extend(
+            secondaries
+                .into_iter()
+                .map(|secondary| TurnMask::PrimaryAndSecondary(primary, secondary)),
+        );
     }
-    primaries.extend(get_moves(state));
+    turns
+}
+
+/// All legal primary actions of the active player
+pub fn get_primaries(game: &Game) -> Vec<Primary> {
+    let mut primaries = get_fixed_primaries(game);
+    primaries.extend(get_moves(game));
 
     primaries
 }
 
-fn get_secondaries(state: &PlayerState, secondary: SecondaryAction) -> Vec<Secondary> {
-    match secondary {
-        SecondaryAction::Upgrade => get_upgrades(state),
-        SecondaryAction::Deploy => get_deploys(state),
-        SecondaryAction::Build => get_builds(state),
-        SecondaryAction::Enlist => get_enlists(state),
-    }
+/// All legal primary actions of the active player except for moves, whose number grows combinatorially
+pub fn get_fixed_primaries(game: &Game) -> Vec<Primary> {
+    let mut primaries: Vec<Primary> = [
+        Primary::Tax,
+        Primary::Promote,
+        Primary::Bolster,
+        Primary::Enforce,
+    ]
+    .into_iter()
+    .filter(|primary| check_primary(game, primary).is_none())
+    .collect();
+    primaries.extend(get_trades(game));
+    primaries.extend(get_produces(game));
+
+    primaries
 }
 
-fn get_enlists(state: &PlayerState) -> Vec<Secondary> {
-    let mut secondary = Vec::new();
-    if !state.recruits.secondary_card_recruited {
-        secondary.push(Recruit::Card);
+/// All legal secondary actions after the primary action was executed
+pub fn get_secondaries(game: &Game, primary: &Primary) -> Vec<Secondary> {
+    let mut game = game.clone();
+    execute_primary(&mut game, primary);
+    let player = game.get_active_player();
+    let action = player.get_secondary(map_primary(primary));
+    let costs = get_costs(&game, player, &action);
+    if costs.is_empty() {
+        return Vec::new();
     }
-    if !state.recruits.secondary_coin_recruited {
-        secondary.push(Recruit::Coin);
-    }
-    if !state.recruits.secondary_military_recruited {
-        secondary.push(Recruit::Power);
+
+    let mut secondaries = Vec::new();
+    let mut push = |secondary: &dyn Fn(ResourceCost) -> Secondary| {
+        secondaries.extend(costs.iter().map(|cost| secondary(*cost)));
+    };
+    match action {
+        SecondaryAction::Upgrade => {
+            for p in PRIMARY_UPGRADES {
+                for s in SECONDARY_UPGRADES {
+                    if check_upgrade(player, &p, &s).is_none() {
+                        push(&|cost| Secondary::Upgrade(p, s, cost));
+                    }
+                }
+            }
+        }
+        SecondaryAction::Deploy => {
+            for mech in MECHS {
+                for worker in WORKERS {
+                    if check_deploy(player, mech, worker).is_none() {
+                        push(&|cost| Secondary::Deploy(mech, worker, cost));
+                    }
+                }
+            }
+        }
+        SecondaryAction::Build => {
+            for building in BUILDINGS {
+                for worker in WORKERS {
+                    if check_build(&game, player, building, worker).is_none() {
+                        push(&|cost| Secondary::Build(building, worker, cost));
+                    }
+                }
+            }
+        }
+        SecondaryAction::Enlist => {
+            for secondary in RECRUITS {
+                for onetime in RECRUITS {
+                    if check_enlist(player, secondary, onetime).is_none() {
+                        push(&|cost| Secondary::Enlist(secondary, onetime, cost));
+                    }
+                }
+            }
+        }
     }
-    if !state.recruits.secondary_popularity_recruited {
-        secondary.push(Recruit::Popularity);
+
+    secondaries
+}
+
+/// All sets of paying units that cover the cost of the secondary action
+fn get_costs(game: &Game, player: &Rc<PlayerState>, action: &SecondaryAction) -> Vec<ResourceCost> {
+    let resource = map_secondary_resource(action);
+    let payers: Vec<UnitPosition> = UNITS
+        .into_iter()
+        .filter(|unit| {
+            player
+                .get_unit_field(unit)
+                .is_some_and(|field| field.resources.has(&resource, 1))
+        })
+        .collect();
+
+    let length = usize::from(player.upgrades.get_upgrade_cost(action));
+    multisets(&payers, length)
+        .into_iter()
+        .filter_map(|units| match units[..] {
+            [u1] => Some(ResourceCost::One(u1)),
+            [u1, u2] => Some(ResourceCost::Two(u1, u2)),
+            [u1, u2, u3] => Some(ResourceCost::Three(u1, u2, u3)),
+            [u1, u2, u3, u4] => Some(ResourceCost::Four(u1, u2, u3, u4)),
+            _ => None,
+        })
+        .filter(|cost| check_payment(game, player, action, cost).is_none())
+        .collect()
+}
+
+/// All selections with repetition of the given length, each in the order of the items.
+///
+/// The order of the paying units does not change a payment, so every multiset is listed once, in
+/// the order of `UNITS` that `check_payment` requires. Moves, trades and productions are listed in
+/// every order the validator accepts.
+fn multisets<T: Copy>(items: &[T], length: usize) -> Vec<Vec<T>> {
+    if length == 0 {
+        return vec![Vec::new()];
     }
+    items
+        .iter()
+        .enumerate()
+        .flat_map(|(i, item)| {
+            multisets(&items[i..], length - 1)
+                .into_iter()
+                .map(move |mut rest| {
+                    rest.insert(0, *item);
+                    rest
+                })
+        })
+        .collect()
+}
 
-    let mut onetime = Vec::new();
-    if !state.recruits.onetime_card_recruited {
-        onetime.push(Recruit::Card);
+/// Samples a legal turn without generating all moves. The kind of primary action is chosen uniformly,
+/// sampled moves carry neither resources nor workers.
+pub fn sample_action<R: Rng + ?Sized>(game: &Game, rng: &mut R) -> TurnMask {
+    let player = game.get_active_player();
+    let primaries = get_fixed_primaries(game);
+    let mut actions = Vec::new();
+    for action in primaries.iter().map(map_primary) {
+        if !actions.contains(&action) {
+            actions.push(action);
+        }
     }
-    if !state.recruits.onetime_coin_recruited {
-        onetime.push(Recruit::Coin);
+    if !get_unit_movements(game, player, &History::new(), Cargo::Empty).is_empty() {
+        actions.push(PrimaryAction::Move);
     }
-    if !state.recruits.onetime_military_recruited {
-        onetime.push(Recruit::Power);
+
+    let primary = match actions.choose(rng) {
+        Some(PrimaryAction::Move) => sample_move(game, rng),
+        Some(action) => primaries
+            .iter()
+            .filter(|primary| map_primary(primary) == *action)
+            .collect::<Vec<_>>()
+            .choose(rng)
+            .map(|primary| **primary),
+        None => None,
     }
-    if !state.recruits.onetime_popularity_recruited {
-        onetime.push(Recruit::Popularity);
+    .unwrap_or(Primary::Tax);
+
+    match get_secondaries(game, &primary).choose(rng) {
+        Some(secondary) => TurnMask::PrimaryAndSecondary(primary, *secondary),
+        None => TurnMask::PrimaryOnly(primary),
     }
+}
+
+/// Samples the units of a move one after another
+fn sample_move<R: Rng + ?Sized>(game: &Game, rng: &mut R) -> Option<Primary> {
+    let player = game.get_active_player();
+    let slots = if player.upgrades.move_evolved { 3 } else { 2 };
+    let units = rng.random_range(1..=slots);
 
-    let mut recruits = Vec::new();
-    for sec in secondary {
-        for once in onetime.clone() {
-            recruits.push(Secondary::Enlist(sec, once));
+    let mut history = History::new();
+    let mut sequence = Vec::with_capacity(units);
+    for _ in 0..units {
+        let mut movements = get_unit_movements(game, player, &history, Cargo::Empty);
+        if movements.is_empty() {
+            break;
         }
+        let (movement, next) = movements.swap_remove(rng.random_range(0..movements.len()));
+        sequence.push(movement);
+        history = next;
     }
 
-    recruits
+    Move::from_movements(&sequence).map(Primary::Move)
 }
 
-fn get_builds(state: &PlayerState) -> Vec<Secondary> {
-    let mut builds = Vec::new();
-    if !state.buildings.mine_built {
-        builds.push(Secondary::Build(Building::Tunnel));
+fn get_trades(game: &Game) -> Vec<Primary> {
+    let player = game.get_active_player();
+    if check_coins(player, PrimaryAction::Trade).is_some() {
+        return Vec::new();
     }
-    if !state.buildings.armory_built {
-        builds.push(Secondary::Build(Building::Armory));
-    }
-    if !state.buildings.monument_built {
-        builds.push(Secondary::Build(Building::Monument));
+
+    let mut singles: Vec<TradeUnit> = Vec::new();
+    for unit in UNITS {
+        for from in RESOURCES {
+            for to in RESOURCES {
+                let trade = (unit, from, to);
+                if check_trade1(game, &trade).is_none() {
+                    singles.push(trade);
+                }
+            }
+        }
     }
-    if !state.buildings.mill_built {
-        builds.push(Secondary::Build(Building::Mill(Tile::Woods)));
-        builds.push(Secondary::Build(Building::Mill(Tile::Mountain)));
-        builds.push(Secondary::Build(Building::Mill(Tile::Tundra)));
-        builds.push(Secondary::Build(Building::Mill(Tile::Farm)));
-        builds.push(Secondary::Build(Building::Mill(Tile::Village)));
+
+    let mut trades: Vec<Primary> = singles
+        .iter()
+        .map(|t| Primary::Trade(Trade::Trade1(*t)))
+        .collect();
+    // Each trade of a legal double trade is legal on its own
+    for t1 in singles.iter() {
+        for t2 in singles.iter() {
+            if check_trade2(game, t1, t2).is_none() {
+                trades.push(Primary::Trade(Trade::Trade2(*t1, *t2)));
+            }
+        }
     }
 
-    builds
+    trades
 }
 
-fn get_deploys(state: &PlayerState) -> Vec<Secondary> {
-    let mut deploys = Vec::new();
-    if !state.mechs.first_deployed {
-        deploys.push(Secondary::Deploy(Mech::First));
-    }
-    if !state.mechs.second_deployed {
-        deploys.push(Secondary::Deploy(Mech::Second));
-    }
-    if !state.mechs.third_deployed {
-        deploys.push(Secondary::Deploy(Mech::Third));
-    }
-    if !state.mechs.fourth_deployed {
-        deploys.push(Secondary::Deploy(Mech::Fourth));
+fn get_produces(game: &Game) -> Vec<Primary> {
+    let player = game.get_active_player();
+    let workers: Vec<Worker> = WORKERS
+        .into_iter()
+        .filter(|w| player.production.get(*w).is_some())
+        .collect();
+
+    let mut candidates = Vec::new();
+    for w1 in workers.iter() {
+        candidates.push(Produce::Produce1(*w1));
+        for w2 in workers.iter() {
+            candidates.push(Produce::Produce2(*w1, *w2));
+            if player.upgrades.produce_evolved {
+                for w3 in workers.iter() {
+                    candidates.push(Produce::Produce3(*w1, *w2, *w3));
+                }
+            }
+        }
     }
-    deploys
+
+    candidates
+        .into_iter()
+        .map(Primary::Produce)
+        .filter(|primary| check_primary(game, primary).is_none())
+        .collect()
 }
 
-const PRIMARY_UPGRADES: [PrimaryUpgrade; 6] = [
-    PrimaryUpgrade::Move,
-    PrimaryUpgrade::Produce,
-    PrimaryUpgrade::Bolster,
-    PrimaryUpgrade::Enforce,
-    PrimaryUpgrade::Promote,
-    PrimaryUpgrade::Tax,
-];
-
-const SECONDARY_UPGRADES: [SecondaryUpgrade; 4] = [
-    SecondaryUpgrade::Build,
-    SecondaryUpgrade::Deploy,
-    SecondaryUpgrade::Enlist,
-    SecondaryUpgrade::Upgrade,
-];
-
-fn get_upgrades(state: &PlayerState) -> Vec<Secondary> {
-    let primary = PRIMARY_UPGRADES
-        .iter()
-        .filter(|&upgrade| state.upgrades.can_upgrade_primary(upgrade))
-        .collect::<Vec<&PrimaryUpgrade>>();
-    let secondary = SECONDARY_UPGRADES
-        .iter()
-        .filter(|&upgrade| state.upgrades.can_upgrade_secondary(upgrade))
-        .collect::<Vec<&SecondaryUpgrade>>();
+fn get_moves(game: &Game) -> Vec<Primary> {
+    let player = game.get_active_player();
+    let slots = if player.upgrades.move_evolved { 3 } else { 2 };
+    let mut moves = Vec::new();
+    collect_moves(
+        game,
+        player,
+        &History::new(),
+        slots,
+        &mut Vec::new(),
+        &mut moves,
+    );
+
+    moves
+}
 
-    let mut upgrades = Vec::new();
-    for prim in primary {
-        for sec in secondary.clone() {
-            upgrades.push(Secondary::Upgrade(*prim, *sec));
+/// Extends the sequence of unit movements by every legal movement given the history so far
+fn collect_moves(
+    game: &Game,
+    player: &Rc<PlayerState>,
+    history: &History,
+    slots: usize,
+    sequence: &mut Vec<UnitMovement>,
+    moves: &mut Vec<Primary>,
+) {
+    for (movement, next) in get_unit_movements(game, player, history, Cargo::All) {
+        sequence.push(movement);
+        if let Some(movement) = Move::from_movements(sequence) {
+            moves.push(Primary::Move(movement));
         }
+        if sequence.len() < slots {
+            collect_moves(game, player, &next, slots, sequence, moves);
+        }
+        sequence.pop();
     }
+}
 
-    upgrades
+/// Which resources and workers the generated movements carry along
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cargo {
+    All,
+    Empty,
 }
 
-fn get_moves(state: &PlayerState) -> Vec<Primary> {
-    // todo: this is not the full action space
-    let mut moves = Vec::new();
-    for tile1 in vec![
-        Tile::Woods,
-        Tile::Mountain,
-        Tile::Tundra,
-        Tile::Farm,
-        Tile::Village,
-    ] {
-        let prod = state.production.get(&tile1);
-        if prod <= 0 {
+/// All legal movements of a single unit together with the resulting history
+pub fn get_unit_movements(
+    game: &Game,
+    player: &Rc<PlayerState>,
+    history: &History,
+    cargo: Cargo,
+) -> Vec<(UnitMovement, History)> {
+    let fields = game.board.get_fields();
+    let mut movements = Vec::new();
+
+    let from = &player.character.location;
+    for t1 in fields.iter() {
+        let mut h1 = history.clone();
+        if check_character_movement(game, player, from, t1, true, &mut h1).is_some() {
             continue;
         }
-        for tile2 in vec![
-            Tile::Woods,
-            Tile::Mountain,
-            Tile::Tundra,
-            Tile::Farm,
-            Tile::Village,
-        ] {
-            if tile1 == tile2 {
+        for (r1, h2) in get_carried_resources(&h1, cargo, from, t1) {
+            movements.push((
+                UnitMovement::Character(Movement::Single((t1.position, r1))),
+                h2.clone(),
+            ));
+            if check_speed(player).is_some() || check_passing(t1).is_some() {
                 continue;
             }
-            moves.push(Primary::Move(Move::Move1((tile1, tile2))));
-            if prod >= 2 {
-                moves.push(Primary::Move(Move::Move2((tile1, tile2), (tile1, tile2))));
+            for t2 in fields.iter() {
+                let mut h3 = h2.clone();
+                if check_character_movement(game, player, t1, t2, false, &mut h3).is_some() {
+                    continue;
+                }
+                for (r2, h4) in get_carried_resources(&h3, cargo, t1, t2) {
+                    movements.push((
+                        UnitMovement::Character(Movement::Double(
+                            (t1.position, r1),
+                            (t2.position, r2),
+                        )),
+                        h4,
+                    ));
+                }
             }
-            if state.upgrades.move_evolved && prod >= 3 {
-                moves.push(Primary::Move(Move::Move3(
-                    (tile1, tile2),
-                    (tile1, tile2),
-                    (tile1, tile2),
-                )));
+        }
+    }
+
+    for worker in WORKERS {
+        let from = match player.production.get(worker) {
+            Some(from) => from,
+            None => continue,
+        };
+        for to in fields.iter() {
+            let mut h1 = history.clone();
+            if check_worker_movement(game, player, from, to, worker, true, &mut h1).is_some() {
+                continue;
+            }
+            for (r, h2) in get_carried_resources(&h1, cargo, from, to) {
+                movements.push((
+                    UnitMovement::Worker(worker, Movement::Single((to.position, r))),
+                    h2,
+                ));
             }
         }
     }
 
-    moves
+    for mech in MECHS {
+        let from = match player.mechs.get(mech) {
+            Some(from) => from,
+            None => continue,
+        };
+        for t1 in fields.iter() {
+            let mut h1 = history.clone();
+            if check_mech_movement(game, player, from, t1, mech, true, &mut h1).is_some() {
+                continue;
+            }
+            for (r1, h2) in get_carried_resources(&h1, cargo, from, t1) {
+                for (w1, h3) in
+                    get_carried_workers(&h2, cargo, player, from, t1, WorkerMask::empty())
+                {
+                    movements.push((
+                        UnitMovement::Mech(mech, Movement::Single((t1.position, w1, r1))),
+                        h3.clone(),
+                    ));
+                    if check_speed(player).is_some() {
+                        continue;
+                    }
+                    for t2 in fields.iter() {
+                        let mut h4 = h3.clone();
+                        if check_mech_movement(game, player, t1, t2, mech, false, &mut h4).is_some()
+                        {
+                            continue;
+                        }
+                        for (r2, h5) in get_carried_resources(&h4, cargo, t1, t2) {
+                            for (w2, h6) in get_carried_workers(&h5, cargo, player, t1, t2, w1) {
+                                movements.push((
+                                    UnitMovement::Mech(
+                                        mech,
+                                        Movement::Double(
+                                            (t1.position, w1, r1),
+                                            (t2.position, w2, r2),
+                                        ),
+                                    ),
+                                    h6,
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    movements
 }
 
-const TILES: [Tile; 5] = [
-    Tile::Woods,
-    Tile::Mountain,
-    Tile::Tundra,
-    Tile::Farm,
-    Tile::Village,
-];
+/// All resources which can be carried from the source field along with the resulting history
+fn get_carried_resources(
+    history: &History,
+    cargo: Cargo,
+    from: &Rc<Field>,
+    to: &Rc<Field>,
+) -> Vec<(ResourceField, History)> {
+    let available = match (cargo, history.resources_at(from)) {
+        (_, None) => return Vec::new(),
+        (Cargo::All, Some(available)) => available,
+        (Cargo::Empty, Some(_)) => ResourceField::empty(),
+    };
 
-fn get_produces(state: &PlayerState) -> Vec<Primary> {
-    let tiles = TILES
-        .iter()
-        .filter(|&tile| state.production.get(tile) > 0)
-        .collect::<Vec<&Tile>>();
-
-    let mut produces = Vec::new();
-    if state.upgrades.produce_evolved {
-        for i in 0..tiles.len() - 2 {
-            for j in i + 1..tiles.len() - 1 {
-                for k in j + 1..tiles.len() {
-                    produces.push(Primary::Produce(Produce::Produce3(
-                        *tiles[i], *tiles[j], *tiles[k],
-                    )));
+    let mut carried = Vec::new();
+    for wood in 0..=available.wood {
+        for metal in 0..=available.metal {
+            for oil in 0..=available.oil {
+                for food in 0..=available.food {
+                    let amount = ResourceField {
+                        wood,
+                        metal,
+                        oil,
+                        food,
+                    };
+                    let mut next = history.clone();
+                    if check_resources(from, to, amount, &mut next).is_none() {
+                        carried.push((amount, next));
+                    }
                 }
             }
         }
     }
-    for i in 0..tiles.len() - 1 {
-        for j in i + 1..tiles.len() {
-            produces.push(Primary::Produce(Produce::Produce2(*tiles[i], *tiles[j])));
+
+    carried
+}
+
+/// All sets of workers which a mech can carry from the source field along with the resulting history
+fn get_carried_workers(
+    history: &History,
+    cargo: Cargo,
+    player: &Rc<PlayerState>,
+    from: &Rc<Field>,
+    to: &Rc<Field>,
+    carried: WorkerMask,
+) -> Vec<(WorkerMask, History)> {
+    let stationed = match (cargo, history.workers_at(player, from)) {
+        (_, None) => return Vec::new(),
+        (Cargo::All, Some(stationed)) => stationed.bits(),
+        (Cargo::Empty, Some(_)) => 0,
+    };
+
+    let mut sets = Vec::new();
+    let mut subset = stationed;
+    loop {
+        let workers = WorkerMask::from_bits_retain(subset);
+        let mut next = history.clone();
+        if check_carry_workers(player, from, to, workers, carried, &mut next).is_none() {
+            sets.push((workers, next));
+        }
+        if subset == 0 {
+            break;
         }
+        subset = (subset - 1) & stationed;
     }
+    sets.reverse();
+
+    sets
+}
 
-    for tile in tiles {
-        produces.push(Primary::Produce(Produce::Produce1(*tile)));
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        game::{Resource, buildings::Building},
+        template::Position,
+        test_support::game,
+    };
+
+    fn add_resource(game: &mut Game, (x, y): (i8, i8), resource: Resource, amount: u32) {
+        game.update_field(&Position::new(x, y), |field| {
+            field.resources.add_resource(&resource, amount)
+        });
     }
 
-    produces
-}
+    #[test]
+    fn every_generated_turn_is_legal() {
+        let mut game = game(2);
+        add_resource(&mut game, (2, 1), Resource::Wood, 3);
+        add_resource(&mut game, (3, 0), Resource::Oil, 2);
 
-fn get_trades() -> Vec<Primary> {
-    let mut trades = Vec::new();
-    for res1 in vec![
-        Resource::Wood,
-        Resource::Metal,
-        Resource::Oil,
-        Resource::Food,
-    ] {
-        for res2 in vec![
-            Resource::Wood,
-            Resource::Metal,
-            Resource::Oil,
-            Resource::Food,
-        ] {
-            trades.push(Primary::Trade(res1, res2));
+        let actions = get_actions(&game);
+        assert!(actions.contains(&TurnMask::PrimaryOnly(Primary::Tax)));
+        for action in actions {
+            assert!(game.apply(&action).is_ok(), "{action:?}");
         }
     }
-    trades
+
+    #[test]
+    fn candidates_are_a_subset_of_the_legal_turns() {
+        let mut game = game(2);
+        add_resource(&mut game, (2, 1), Resource::Wood, 3);
+        add_resource(&mut game, (3, 0), Resource::Oil, 2);
+
+        let actions = get_actions(&game);
+        let candidates = get_candidates(&game);
+        assert!(candidates.len() < actions.len());
+        for candidate in candidates {
+            assert!(actions.contains(&candidate), "{candidate:?}");
+        }
+    }
+
+    #[test]
+    fn single_moves_agree_with_the_validator() {
+        let game = game(2);
+        let primaries = get_primaries(&game);
+        for worker in [Worker::First, Worker::Second] {
+            for field in game.board.get_fields() {
+                let step = (field.position, ResourceField::empty());
+                let primary = Primary::Move(Move::Move1(UnitMovement::Worker(
+                    worker,
+                    Movement::Single(step),
+                )));
+                assert_eq!(
+                    primaries.contains(&primary),
+                    check_primary(&game, &primary).is_none(),
+                    "{primary:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn multisets_ignore_the_order() {
+        assert_eq!(
+            multisets(&[1, 2, 3], 2),
+            vec![
+                vec![1, 1],
+                vec![1, 2],
+                vec![1, 3],
+                vec![2, 2],
+                vec![2, 3],
+                vec![3, 3]
+            ]
+        );
+        assert_eq!(multisets(&[1, 2], 0), vec![Vec::<i32>::new()]);
+        assert!(multisets::<i32>(&[], 2).is_empty());
+    }
+
+    #[test]
+    fn payments_come_from_every_source() {
+        let mut game = game(2);
+        add_resource(&mut game, (2, 1), Resource::Wood, 2);
+        add_resource(&mut game, (3, 0), Resource::Wood, 1);
+
+        let (w1, w2) = (
+            UnitPosition::Worker(Worker::First),
+            UnitPosition::Worker(Worker::Second),
+        );
+        let costs: Vec<ResourceCost> = get_secondaries(&game, &Primary::Tax)
+            .into_iter()
+            .filter_map(|secondary| match secondary {
+                Secondary::Build(Building::Mill, Worker::First, cost) => Some(cost),
+                _ => None,
+            })
+            .collect();
+        // Each payment is listed once, whatever the order of its units
+        assert_eq!(costs, vec![ResourceCost::Three(w1, w1, w2)]);
+    }
 }